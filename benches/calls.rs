@@ -0,0 +1,48 @@
+//! The latency of update and query calls to the counter canister, with Criterion.
+//!
+//! The counter is deployed through the wallet of the test context, so this needs a
+//! replica like the tests: `cargo bench --bench calls`. Without one the benchmarks are
+//! skipped.
+use candid::Encode;
+use criterion::{criterion_group, criterion_main, Criterion};
+use ic_test_utils::bench::CallBench;
+use ic_test_utils::cleanup::CanisterGuard;
+use ic_test_utils::context::Env;
+use ic_test_utils::embedded::counter_wasm;
+use ic_test_utils::Error;
+
+fn calls(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let setup = runtime.block_on(async {
+        let env = Env::get().await?;
+        let wallet = env.wallet()?;
+        let counter = env
+            .management()
+            .deploy(&wallet, counter_wasm(), (), 1_000_000_000_000, None)
+            .await?;
+        Ok::<_, Error>((env, counter))
+    });
+    let (env, counter) = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            eprintln!("Skipping the call benchmarks, no replica: {}", err.report());
+            return;
+        }
+    };
+
+    let increment = CallBench::update(env.backend(), counter, "increment", Encode!().unwrap());
+    c.bench_function("update", |b| {
+        b.to_async(&runtime)
+            .iter_custom(|iters| increment.iter_custom(iters))
+    });
+    let get = CallBench::query(env.backend(), counter, "get", Encode!().unwrap());
+    c.bench_function("query", |b| {
+        b.to_async(&runtime)
+            .iter_custom(|iters| get.iter_custom(iters))
+    });
+
+    let _ = runtime.block_on(CanisterGuard::new(counter, env.agent()).cleanup());
+}
+
+criterion_group!(benches, calls);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+#![deny(missing_docs)]
+//! Macros for `ic-test-utils`.
+//!
+//! Use them through the re-exports in `ic-test-utils`.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{meta::ParseNestedMeta, parse_macro_input, FnArg, Ident, ItemFn, LitInt, LitStr, Pat};
+
+#[derive(Default)]
+struct Config {
+    account: Option<LitStr>,
+    url: Option<LitStr>,
+    cycles: Option<LitInt>,
+    canisters: Vec<(Ident, LitStr)>,
+}
+
+impl Config {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("account") {
+            self.account = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("url") {
+            self.url = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("cycles") {
+            self.cycles = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("canisters") {
+            meta.parse_nested_meta(|canister| {
+                let name = canister
+                    .path
+                    .get_ident()
+                    .cloned()
+                    .ok_or_else(|| canister.error("expected a canister name"))?;
+                let wasm_path = canister.value()?.parse()?;
+                self.canisters.push((name, wasm_path));
+                Ok(())
+            })?;
+        } else {
+            return Err(meta.error("expected `account`, `url`, `cycles` or `canisters`"));
+        }
+        Ok(())
+    }
+}
+
+/// Turn an async function into a test running against a replica.
+///
+/// ```text
+/// #[ic_test(account = "alice", cycles = 1_000_000_000_000, canisters(counter = "counter.wasm"))]
+/// async fn counter_works(agent: &Agent, counter: Canister<'_, Counter>) { ... }
+/// ```
+///
+/// * `account`: the dfx identity to build the agent with, `IC_TEST_ACCOUNT` takes precedence
+/// * `url`: the replica url, `IC_TEST_URL` takes precedence
+/// * `cycles`: the cycles each canister is created with
+/// * `canisters`: canisters to create and install before the test, by parameter name and wasm path
+///
+/// A parameter named `agent` gets the agent, every other parameter must be
+/// a declared canister. The created canisters are deleted after the test,
+/// also when it panics.
+#[proc_macro_attribute]
+pub fn ic_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut config = Config::default();
+    let parser = syn::meta::parser(|meta| config.parse(meta));
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemFn);
+
+    match expand(config, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(config: Config, mut item: ItemFn) -> syn::Result<TokenStream2> {
+    if item.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            item.sig.fn_token,
+            "#[ic_test] functions must be async",
+        ));
+    }
+
+    let mut args = Vec::new();
+    for input in &item.sig.inputs {
+        let ident = match input {
+            FnArg::Typed(typed) => match &*typed.pat {
+                Pat::Ident(pat) => &pat.ident,
+                pat => return Err(syn::Error::new_spanned(pat, "expected a parameter name")),
+            },
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(receiver, "unexpected `self`"))
+            }
+        };
+        if ident == "agent" {
+            args.push(quote!(__harness.agent()));
+        } else if config.canisters.iter().any(|(name, _)| name == ident) {
+            args.push(quote!(::ic_test_utils::Canister::new(#ident, __harness.agent())));
+        } else {
+            return Err(syn::Error::new_spanned(
+                ident,
+                "parameters must be `agent` or a canister declared in `canisters(...)`",
+            ));
+        }
+    }
+
+    let name = item.sig.ident.clone();
+    let output = item.sig.output.clone();
+    let attrs = std::mem::take(&mut item.attrs);
+    let inner = Ident::new("__ic_test_inner", Span::call_site());
+    item.sig.ident = inner.clone();
+
+    let account = config
+        .account
+        .unwrap_or_else(|| LitStr::new("default", Span::call_site()));
+    let url = match config.url {
+        Some(url) => quote!(Some(#url)),
+        None => quote!(None),
+    };
+    let cycles = match config.cycles {
+        Some(cycles) => quote!(#cycles),
+        None => quote!(::ic_test_utils::harness::DEFAULT_CYCLES),
+    };
+    let deploys = config.canisters.iter().map(|(name, wasm_path)| {
+        let message = format!("failed to deploy `{}`", name);
+        quote! {
+            let #name = __harness.deploy(#wasm_path, #cycles).await.expect(#message);
+        }
+    });
+
+    Ok(quote! {
+        #[test]
+        #(#attrs)*
+        fn #name() #output {
+            #item
+
+            ::ic_test_utils::harness::__private::runtime().block_on(async {
+                let mut __harness = ::ic_test_utils::harness::TestHarness::setup(#account, #url)
+                    .await
+                    .expect("failed to set up the test agent");
+                #(#deploys)*
+
+                let __result = ::ic_test_utils::harness::__private::FutureExt::catch_unwind(
+                    ::std::panic::AssertUnwindSafe(#inner(#(#args),*)),
+                )
+                .await;
+                __harness.teardown().await;
+                match __result {
+                    Ok(output) => output,
+                    Err(panic) => ::std::panic::resume_unwind(panic),
+                }
+            })
+        }
+    })
+}
@@ -0,0 +1,143 @@
+//! Assert that calls fail the way they should.
+//!
+//! ```
+//! # async fn run(canister: ic_test_utils::Canister<'_, ()>) {
+//! use candid::Encode;
+//! use ic_test_utils::assert::{assert_rejected_with, assert_trap};
+//! use ic_test_utils::RejectCode;
+//!
+//! let result = canister.call_update("admin_only", Encode!().unwrap()).await;
+//! assert_rejected_with(result, RejectCode::CanisterReject, "not authorized");
+//!
+//! let result = canister.call_update("divide", Encode!(&1u64, &0u64).unwrap()).await;
+//! assert_trap(result, "division by zero");
+//! # }
+//! ```
+use std::fmt::Debug;
+
+use regex::Regex;
+
+use candid::{CandidType, Decode, Deserialize, Principal};
+
+use crate::wasm::wasm_hash;
+use crate::{Canister, ManagementCanister, RejectCode, Result};
+
+/// Panic unless `result` is a reject with `code`, and a message matching the
+/// regex `pattern`.
+pub fn assert_rejected_with<T>(result: Result<T>, code: RejectCode, pattern: &str) {
+    let regex = Regex::new(pattern).expect("invalid regex");
+    let err = match result {
+        Ok(_) => panic!("expected a {:?} reject, the call succeeded", code),
+        Err(err) => err,
+    };
+    match err.reject() {
+        Some((actual, message)) => {
+            assert_eq!(actual, code, "unexpected reject code, message: {}", message);
+            assert!(
+                regex.is_match(message),
+                "reject message {:?} does not match {:?}",
+                message,
+                pattern
+            );
+        }
+        None => panic!("expected a {:?} reject, got: {}", code, err.report()),
+    }
+}
+
+/// Panic unless the canister trapped while executing the call with a message
+/// matching the regex `pattern`.
+pub fn assert_trap<T>(result: Result<T>, pattern: &str) {
+    assert_rejected_with(result, RejectCode::CanisterError, pattern)
+}
+
+/// Panic unless the canister `canister_id` uses less than `bytes` of memory in total,
+/// to catch memory regressions, for example after an upgrade.
+pub async fn assert_memory_below(
+    management: &ManagementCanister<'_>,
+    canister_id: Principal,
+    bytes: u64,
+) {
+    let status = management
+        .canister_status(canister_id)
+        .await
+        .unwrap_or_else(|err| panic!("canister_status failed: {}", err.report()));
+    let used = status.total_memory_size();
+    assert!(
+        used < bytes,
+        "canister {} uses {} bytes of memory (heap: {:?}, stable: {:?}), expected less than {}",
+        canister_id,
+        used,
+        status.heap_memory_size(),
+        status.stable_memory_size(),
+        bytes
+    );
+}
+
+/// Panic unless `canister_id` runs the module `wasm`, comparing the hash the replica
+/// reports with [`wasm_hash`], to prove the right artifact was deployed.
+pub async fn assert_module_hash_matches(
+    management: &ManagementCanister<'_>,
+    canister_id: Principal,
+    wasm: &[u8],
+) {
+    let expected = wasm_hash(wasm).expect("failed to hash the module");
+    let status = management
+        .canister_status(canister_id)
+        .await
+        .unwrap_or_else(|err| panic!("canister_status failed: {}", err.report()));
+    match status.module_hash {
+        Some(installed) => assert_eq!(
+            hex::encode(&installed),
+            hex::encode(expected),
+            "canister {} runs a different module",
+            canister_id
+        ),
+        None => panic!(
+            "canister {} is empty, expected module {}",
+            canister_id,
+            hex::encode(expected)
+        ),
+    }
+}
+
+/// Call the query `method_name` with `arg` as a query and then as an update, which runs
+/// through consensus, and panic unless both decode to the same reply. Returns the reply.
+///
+/// This catches query paths returning stale or divergent data, like a cache that isn't
+/// invalidated or state only updates maintain.
+///
+/// ```
+/// # async fn run(ledger: ic_test_utils::Canister<'_, ()>) {
+/// use candid::Encode;
+/// use ic_test_utils::assert::assert_query_matches_update;
+///
+/// let supply: u64 =
+///     assert_query_matches_update(&ledger, "total_supply", Encode!().unwrap()).await;
+/// # }
+/// ```
+pub async fn assert_query_matches_update<T, R>(
+    canister: &Canister<'_, T>,
+    method_name: &str,
+    arg: Vec<u8>,
+) -> R
+where
+    R: CandidType + for<'de> Deserialize<'de> + PartialEq + Debug,
+{
+    let decode = |reply: Result<Vec<u8>>, kind: &str| {
+        let reply = reply.unwrap_or_else(|err| {
+            panic!("{} call to {} failed: {}", kind, method_name, err.report())
+        });
+        Decode!(&reply, R)
+            .unwrap_or_else(|err| panic!("failed to decode the {} reply: {}", kind, err))
+    };
+    let query = decode(canister.call_query(method_name, arg.clone()).await, "query");
+    let update = decode(canister.call_update(method_name, arg).await, "update");
+    assert_eq!(
+        query,
+        update,
+        "{} of canister {} replies differently as a query and as an update",
+        method_name,
+        canister.principal()
+    );
+    update
+}
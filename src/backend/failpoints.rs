@@ -0,0 +1,275 @@
+//! Inject failures at chosen calls, to test how a client copes with partial failures.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use ic_agent::ic_types::Principal;
+use ic_agent::{Agent, AgentError};
+
+use super::{Backend, BackendFuture};
+use crate::canister::Canister;
+use crate::{Error, RejectCode, Result};
+
+/// What a failpoint does, see [`Failpoints`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailAction {
+    /// Stop the called canister, then make the call
+    StopCanister,
+    /// Start the called canister, then make the call
+    StartCanister,
+    /// Fail the call with a transport error without making it, like a dropped connection
+    DropConnection,
+    /// Make the call, but fail with a transport error instead of returning the reply,
+    /// so the client doesn't know whether the call ran
+    DropReply,
+    /// Fail the call with a reject without making it
+    Reject {
+        /// The reject code
+        code: RejectCode,
+        /// The reject message
+        message: String,
+    },
+    /// Wait, then make the call
+    Delay(Duration),
+}
+
+/// A failpoint triggered by a call, see [`Failpoints::triggered`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggeredFailpoint {
+    /// The called canister
+    pub canister_id: Principal,
+    /// The called method
+    pub method: String,
+    /// The number of the call to the method, starting at 1
+    pub nth: u32,
+    /// What the failpoint did
+    pub action: FailAction,
+}
+
+struct Failpoint {
+    canister_id: Option<Principal>,
+    method: String,
+    nth: u32,
+    action: FailAction,
+}
+
+#[derive(Default)]
+struct State {
+    failpoints: Vec<Failpoint>,
+    calls: HashMap<(Option<Principal>, String), u32>,
+    triggered: Vec<TriggeredFailpoint>,
+}
+
+/// A backend running failpoints before chosen calls made through it.
+///
+/// A failpoint fires once, at the `nth` call to a method, counting the calls to the
+/// method of any canister, or of one canister with [`Failpoints::before_call_to`].
+/// The calls made by the failpoints themselves, like stopping a canister, go straight
+/// to the inner backend and are not counted.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::backend::{FailAction, Failpoints};
+/// use ic_test_utils::Canister;
+///
+/// let failpoints = Failpoints::new(agent);
+/// failpoints.before_call("transfer", 3, FailAction::StopCanister);
+/// failpoints.before_call("transfer", 5, FailAction::DropReply);
+///
+/// let ledger = Canister::<()>::new(ledger, &failpoints);
+/// for _ in 0..5 {
+///     let result = ledger.call_update("transfer", Encode!(&100u64).unwrap()).await;
+///     // The client should cope with the canister being stopped and the lost reply
+/// }
+/// assert_eq!(failpoints.triggered().len(), 2);
+/// # }
+/// ```
+pub struct Failpoints<'a> {
+    inner: &'a dyn Backend,
+    state: Mutex<State>,
+}
+
+impl<'a> Failpoints<'a> {
+    /// Pass the calls on to `inner`, without failpoints yet
+    pub fn new(inner: &'a dyn Backend) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Run `action` before the `nth` call to `method_name`, of any canister,
+    /// counting from 1
+    pub fn before_call(&self, method_name: &str, nth: u32, action: FailAction) -> &Self {
+        self.add(None, method_name, nth, action)
+    }
+
+    /// Run `action` before the `nth` call to `method_name` of `canister_id`,
+    /// counting from 1
+    pub fn before_call_to(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        nth: u32,
+        action: FailAction,
+    ) -> &Self {
+        self.add(Some(canister_id), method_name, nth, action)
+    }
+
+    /// The failpoints that fired so far, in the order they fired
+    pub fn triggered(&self) -> Vec<TriggeredFailpoint> {
+        self.lock().triggered.clone()
+    }
+
+    /// Remove the failpoints that didn't fire yet
+    pub fn clear(&self) {
+        self.lock().failpoints.clear();
+    }
+
+    fn add(
+        &self,
+        canister_id: Option<Principal>,
+        method_name: &str,
+        nth: u32,
+        action: FailAction,
+    ) -> &Self {
+        self.lock().failpoints.push(Failpoint {
+            canister_id,
+            method: method_name.into(),
+            nth: nth.max(1),
+            action,
+        });
+        self
+    }
+
+    /// Count the call, and take the failpoint firing for it, if there is one
+    fn next_action(&self, canister_id: Principal, method_name: &str) -> Option<FailAction> {
+        let mut state = self.lock();
+        let mut count = |canister_id| {
+            let calls = state
+                .calls
+                .entry((canister_id, method_name.to_string()))
+                .or_insert(0);
+            *calls += 1;
+            *calls
+        };
+        let any_canister = count(None);
+        let this_canister = count(Some(canister_id));
+
+        let position = state.failpoints.iter().position(|failpoint| {
+            failpoint.method == method_name
+                && match failpoint.canister_id {
+                    None => failpoint.nth == any_canister,
+                    Some(id) => id == canister_id && failpoint.nth == this_canister,
+                }
+        })?;
+        let failpoint = state.failpoints.remove(position);
+        state.triggered.push(TriggeredFailpoint {
+            canister_id,
+            method: method_name.into(),
+            nth: failpoint.nth,
+            action: failpoint.action.clone(),
+        });
+        Some(failpoint.action)
+    }
+
+    async fn call(
+        &self,
+        update: bool,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let action = self.next_action(canister_id, method_name);
+        match action {
+            Some(FailAction::StopCanister) => {
+                Canister::new_management(self.inner)
+                    .stop_canister(canister_id)
+                    .await?
+            }
+            Some(FailAction::StartCanister) => {
+                Canister::new_management(self.inner)
+                    .start_canister(canister_id)
+                    .await?
+            }
+            Some(FailAction::DropConnection) => return Err(transport_error("connection")),
+            Some(FailAction::Reject { code, message }) => {
+                return Err(Error::Rejected {
+                    code,
+                    message,
+                    method: method_name.into(),
+                })
+            }
+            Some(FailAction::Delay(delay)) => tokio::time::sleep(delay).await,
+            Some(FailAction::DropReply) | None => {}
+        }
+
+        let result = if update {
+            self.inner
+                .update_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        } else {
+            self.inner
+                .query_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        };
+        match action {
+            Some(FailAction::DropReply) => Err(transport_error("reply")),
+            _ => result,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn transport_error(dropped: &str) -> Error {
+    Error::Agent(AgentError::TransportError(
+        format!("{} dropped by a failpoint", dropped).into(),
+    ))
+}
+
+impl Backend for Failpoints<'_> {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(true, canister_id, effective_canister_id, method_name, arg))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(false, canister_id, effective_canister_id, method_name, arg))
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        self.inner.agent()
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        self.inner.get_time()
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        self.inner.set_time(time)
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        self.inner.advance_time(duration)
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        self.inner.tick()
+    }
+}
@@ -0,0 +1,208 @@
+//! Backends executing the calls made through a [`Canister`](crate::Canister).
+//!
+//! A [`Canister`](crate::Canister) can run either against a replica through an
+//! [`Agent`], or against a [`PocketIc`] instance, so the same test can run against both.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent) {
+//! use ic_test_utils::backend::PocketIc;
+//! use ic_test_utils::Canister;
+//!
+//! // Against a replica
+//! let management = Canister::new_management(agent);
+//!
+//! // Against PocketIC
+//! let pic = PocketIc::new("http://127.0.0.1:8080").await.unwrap();
+//! let management = Canister::new_management(&pic);
+//!
+//! // PocketIC time only moves when told to
+//! use ic_test_utils::backend::Backend;
+//! management.backend().advance_time(std::time::Duration::from_secs(60)).await.unwrap();
+//! management.backend().tick().await.unwrap();
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant, SystemTime};
+
+use garcon::Waiter;
+use ic_agent::ic_types::Principal;
+use ic_agent::{Agent, AgentError, RequestId};
+
+use crate::{get_waiter, Error, Result};
+
+mod failpoints;
+#[cfg(feature = "pocket-ic")]
+mod outcalls;
+#[cfg(feature = "pocket-ic")]
+mod pocket_ic;
+mod record;
+mod retry;
+mod transcript;
+#[cfg(feature = "pocket-ic")]
+mod xnet;
+
+pub use failpoints::{FailAction, Failpoints, TriggeredFailpoint};
+#[cfg(feature = "pocket-ic")]
+pub use outcalls::{HttpMocks, HttpOutcall, HttpOutcallResponse};
+#[cfg(feature = "pocket-ic")]
+pub use pocket_ic::PocketIc;
+pub use record::{Recorder, Replay, ReplayReport, ReplayedCall, Session};
+pub use retry::{Retry, RetryOn, RetryPolicy};
+pub use transcript::{CallOutcome, Transcript, TranscriptEntry};
+#[cfg(feature = "pocket-ic")]
+pub use xnet::{CompletedCall, Subnet};
+
+/// How long a round takes on a replica, used by [`await_rounds`].
+pub const ROUND_DURATION: Duration = Duration::from_secs(1);
+
+/// The future returned by the [`Backend`] calls.
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Something that can execute canister calls.
+pub trait Backend: Send + Sync {
+    /// Make an update call and wait for the reply.
+    ///
+    /// The `effective_canister_id` is used to route calls to the management canister,
+    /// if it is `None` the `canister_id` is used.
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>>;
+
+    /// Make a query call.
+    ///
+    /// The `effective_canister_id` is used like for [`Backend::update_call`].
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>>;
+
+    /// The agent, if this backend talks to a replica
+    fn agent(&self) -> Option<&Agent> {
+        None
+    }
+
+    /// The current time of the backend.
+    /// Only supported by backends with deterministic time, like [`PocketIc`].
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        Box::pin(async { Err(Error::UnsupportedByBackend("time control")) })
+    }
+
+    /// Set the time of the backend.
+    /// Only supported by backends with deterministic time, like [`PocketIc`].
+    fn set_time(&self, _time: SystemTime) -> BackendFuture<'_, ()> {
+        Box::pin(async { Err(Error::UnsupportedByBackend("time control")) })
+    }
+
+    /// Move the time of the backend forward by `duration`.
+    /// Only supported by backends with deterministic time, like [`PocketIc`].
+    fn advance_time(&self, _duration: Duration) -> BackendFuture<'_, ()> {
+        Box::pin(async { Err(Error::UnsupportedByBackend("time control")) })
+    }
+
+    /// Execute a single round.
+    /// Only supported by backends with deterministic time, like [`PocketIc`].
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        Box::pin(async { Err(Error::UnsupportedByBackend("ticks")) })
+    }
+}
+
+impl Backend for Agent {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let mut builder = Agent::update(self, &canister_id, method_name);
+            if let Some(ref effective_canister_id) = effective_canister_id {
+                builder.with_effective_canister_id(*effective_canister_id);
+            }
+            builder.with_arg(arg);
+            let request_id = builder
+                .call()
+                .await
+                .map_err(|e| Error::from_agent(e, method_name))?;
+            let effective_canister_id = effective_canister_id.unwrap_or(canister_id);
+            wait_for_request(self, request_id, effective_canister_id, method_name).await
+        })
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let mut builder = Agent::query(self, &canister_id, method_name);
+            if let Some(ref effective_canister_id) = effective_canister_id {
+                builder.with_effective_canister_id(*effective_canister_id);
+            }
+            builder.with_arg(arg);
+            builder
+                .call()
+                .await
+                .map_err(|e| Error::from_agent(e, method_name))
+        })
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        Some(self)
+    }
+}
+
+/// Poll the status of an update call to `method_name` until it completes.
+///
+/// If the waiter gives up this returns [`Error::PollTimeout`] with the request id,
+/// call this again with it to keep polling.
+pub async fn wait_for_request(
+    agent: &Agent,
+    request_id: RequestId,
+    effective_canister_id: Principal,
+    method_name: &str,
+) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    match agent
+        .wait(request_id, effective_canister_id, false, get_waiter())
+        .await
+    {
+        Ok(reply) => Ok(reply),
+        Err(AgentError::TimeoutWaitingForResponse()) => Err(Error::PollTimeout {
+            request_id,
+            effective_canister_id,
+            method: method_name.into(),
+            waited: started.elapsed(),
+        }),
+        Err(err) => Err(Error::from_agent(err, method_name)),
+    }
+}
+
+/// Let the system make progress for `rounds` rounds, e.g. for inter-canister
+/// calls to settle or timers to run.
+///
+/// On a backend with deterministic time this executes `rounds` ticks, on a replica
+/// this waits for [`ROUND_DURATION`] per round.
+pub async fn await_rounds(backend: &dyn Backend, rounds: u32) -> Result<()> {
+    if backend.agent().is_some() {
+        let mut delay = garcon::Delay::throttle(ROUND_DURATION * rounds);
+        delay.start();
+        let _ = delay.async_wait().await;
+        return Ok(());
+    }
+
+    for _ in 0..rounds {
+        backend.tick().await?;
+    }
+    Ok(())
+}
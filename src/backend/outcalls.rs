@@ -0,0 +1,246 @@
+//! Mocked HTTPS outcalls on PocketIC.
+//!
+//! PocketIC doesn't make the HTTPS outcalls of canisters, it holds them until they
+//! are answered. [`HttpMocks`] answers them with a response per URL pattern, so canisters
+//! making outcalls can be tested without the network.
+use ic_agent::ic_types::Principal;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::pocket_ic::{canister_result, RawCanisterResult};
+use super::PocketIc;
+use crate::{Error, RejectCode, Result};
+
+/// The rounds executed waiting for the outcalls of a call
+const MAX_ROUNDS: usize = 100;
+
+/// An HTTPS outcall made by a canister, waiting for its response
+#[derive(Debug, Clone)]
+pub struct HttpOutcall {
+    subnet_id: String,
+    /// The id of the request on its subnet
+    pub request_id: u64,
+    /// The method, `GET`, `POST` or `HEAD`
+    pub method: String,
+    /// The requested URL
+    pub url: String,
+    /// The request headers, as name and value
+    pub headers: Vec<(String, String)>,
+    /// The request body
+    pub body: Vec<u8>,
+    /// The size limit of the response set by the canister
+    pub max_response_bytes: Option<u64>,
+}
+
+/// The mocked response to an HTTPS outcall
+#[derive(Debug, Clone)]
+pub enum HttpOutcallResponse {
+    /// A response of the server
+    Reply {
+        /// The HTTP status code
+        status: u16,
+        /// The response headers, as name and value
+        headers: Vec<(String, String)>,
+        /// The response body
+        body: Vec<u8>,
+    },
+    /// A failure to reach the server
+    Reject {
+        /// The reject code the canister gets
+        code: RejectCode,
+        /// The reject message
+        message: String,
+    },
+}
+
+impl HttpOutcallResponse {
+    /// A `200` response with `body` and no headers
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::Reply {
+            status: 200,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    fn to_raw(&self) -> Value {
+        match self {
+            Self::Reply {
+                status,
+                headers,
+                body,
+            } => json!({ "CanisterHttpReply": {
+                "status": status,
+                "headers": headers
+                    .iter()
+                    .map(|(name, value)| json!({ "name": name, "value": value }))
+                    .collect::<Vec<_>>(),
+                "body": base64::encode(body),
+            }}),
+            Self::Reject { code, message } => json!({ "CanisterHttpReject": {
+                "reject_code": code.as_u64(),
+                "message": message,
+            }}),
+        }
+    }
+}
+
+/// The mocked responses to HTTPS outcalls, by URL pattern.
+///
+/// The first pattern matching the URL of an outcall gives its response.
+///
+/// ```
+/// # async fn run(pic: &ic_test_utils::backend::PocketIc, oracle: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::backend::{HttpMocks, HttpOutcallResponse};
+///
+/// let mocks = HttpMocks::new()
+///     .mock(r"^https://api\.example\.com/price", HttpOutcallResponse::ok(r#"{"price":42}"#))
+///     .unwrap();
+/// let reply = pic
+///     .update_with_outcalls(oracle, "fetch_price", Encode!().unwrap(), &mocks)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HttpMocks {
+    mocks: Vec<(Regex, HttpOutcallResponse)>,
+}
+
+impl HttpMocks {
+    /// No mocked responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the outcalls to URLs matching the regex `url_pattern` with `response`
+    pub fn mock(mut self, url_pattern: &str, response: HttpOutcallResponse) -> Result<Self> {
+        let pattern = Regex::new(url_pattern)
+            .map_err(|e| Error::Generic(format!("Invalid URL pattern: {}", e)))?;
+        self.mocks.push((pattern, response));
+        Ok(self)
+    }
+
+    /// The response to `outcall`, if a pattern matches its URL
+    pub fn response(&self, outcall: &HttpOutcall) -> Option<&HttpOutcallResponse> {
+        self.mocks
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&outcall.url))
+            .map(|(_, response)| response)
+    }
+}
+
+impl PocketIc {
+    /// The HTTPS outcalls waiting for a response
+    pub async fn pending_outcalls(&self) -> Result<Vec<HttpOutcall>> {
+        let outcalls: Vec<RawHttpOutcall> = self.get("read/get_canister_http").await?;
+        outcalls
+            .into_iter()
+            .map(|outcall| {
+                Ok(HttpOutcall {
+                    subnet_id: outcall.subnet_id.subnet_id,
+                    request_id: outcall.request_id,
+                    method: outcall.http_method.to_uppercase(),
+                    url: outcall.url,
+                    headers: outcall
+                        .headers
+                        .into_iter()
+                        .map(|header| (header.name, header.value))
+                        .collect(),
+                    body: base64::decode(outcall.body)
+                        .map_err(|e| Error::PocketIc(format!("Invalid outcall body: {}", e)))?,
+                    max_response_bytes: outcall.max_response_bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Answer `outcall` with `response`
+    pub async fn respond_to_outcall(
+        &self,
+        outcall: &HttpOutcall,
+        response: &HttpOutcallResponse,
+    ) -> Result<()> {
+        let mock = json!({
+            "subnet_id": { "subnet_id": outcall.subnet_id },
+            "request_id": outcall.request_id,
+            "response": response.to_raw(),
+            "additional_responses": [],
+        });
+        self.post::<Value>("update/mock_canister_http", &mock)
+            .await?;
+        Ok(())
+    }
+
+    /// Answer the pending outcalls with `mocks` and return how many were answered.
+    /// Fails on the first outcall no pattern matches.
+    pub async fn respond_to_outcalls(&self, mocks: &HttpMocks) -> Result<usize> {
+        let outcalls = self.pending_outcalls().await?;
+        for outcall in &outcalls {
+            let response = mocks.response(outcall).ok_or_else(|| {
+                Error::PocketIc(format!(
+                    "No mocked response for {} {}",
+                    outcall.method, outcall.url
+                ))
+            })?;
+            self.respond_to_outcall(outcall, response).await?;
+        }
+        Ok(outcalls.len())
+    }
+
+    /// Call `method_name` of `canister_id` as an update,
+    /// answering the outcalls it makes with `mocks`
+    pub async fn update_with_outcalls(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+        mocks: &HttpMocks,
+    ) -> Result<Vec<u8>> {
+        let message_id = self.submit(canister_id, method_name, arg).await?;
+
+        // Outcalls show up a round after the call made them, and a response can
+        // lead to more outcalls, so rounds are executed until none are left
+        let mut idle_rounds = 0;
+        for _ in 0..MAX_ROUNDS {
+            self.tick().await?;
+            if self.respond_to_outcalls(mocks).await? == 0 {
+                idle_rounds += 1;
+                if idle_rounds == 2 {
+                    break;
+                }
+            } else {
+                idle_rounds = 0;
+            }
+        }
+
+        let result: RawCanisterResult = self
+            .post("update/await_ingress_message", &message_id)
+            .await?;
+        canister_result(result, method_name)
+    }
+}
+
+#[derive(Deserialize)]
+struct RawHttpOutcall {
+    subnet_id: RawSubnetId,
+    request_id: u64,
+    http_method: String,
+    url: String,
+    headers: Vec<RawHeader>,
+    body: String,
+    max_response_bytes: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct RawSubnetId {
+    pub(super) subnet_id: String,
+}
+
+#[derive(Deserialize)]
+struct RawHeader {
+    name: String,
+    value: String,
+}
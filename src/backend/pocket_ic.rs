@@ -0,0 +1,391 @@
+//! A client for the PocketIC server REST api.
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use garcon::Waiter;
+use ic_agent::ic_types::Principal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Backend, BackendFuture};
+use crate::{Error, RejectCode, Result};
+
+/// A PocketIC instance on a PocketIC server.
+///
+/// The instance is created with a single application subnet, or several with
+/// [`PocketIc::with_application_subnets`], and deleted again when the `PocketIc` is dropped.
+pub struct PocketIc {
+    client: reqwest::Client,
+    server_url: String,
+    instance_id: u64,
+    sender: RwLock<Principal>,
+}
+
+impl PocketIc {
+    /// Create a new instance on the PocketIC server at `server_url`.
+    /// Calls are made by the anonymous principal until [`PocketIc::set_sender`] is called.
+    pub async fn new(server_url: impl Into<String>) -> Result<Self> {
+        Self::with_application_subnets(server_url, 1).await
+    }
+
+    /// Like [`PocketIc::new`], but the instance has `count` application subnets,
+    /// for calls across subnets, see [`PocketIc::create_canister_on_subnet`]
+    pub async fn with_application_subnets(
+        server_url: impl Into<String>,
+        count: usize,
+    ) -> Result<Self> {
+        let server_url = server_url.into().trim_end_matches('/').to_string();
+        let client = reqwest::Client::new();
+        let subnet = json!({
+            "state_config": "New",
+            "instruction_config": "Production",
+            "dts_flag": "Enabled",
+        });
+        let config = json!({
+            "nns": null,
+            "sns": null,
+            "ii": null,
+            "fiduciary": null,
+            "bitcoin": null,
+            "system": [],
+            "application": vec![subnet; count],
+            "verified_application": [],
+        });
+        let response = client
+            .post(format!("{}/instances", server_url))
+            .json(&config)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::PocketIc(response.text().await?));
+        }
+
+        let created: Value = response.json().await?;
+        let instance_id = created["Created"]["instance_id"]
+            .as_u64()
+            .ok_or_else(|| Error::PocketIc(format!("Failed to create instance: {}", created)))?;
+
+        Ok(Self {
+            client,
+            server_url,
+            instance_id,
+            sender: RwLock::new(Principal::anonymous()),
+        })
+    }
+
+    /// The id of the instance on the server
+    pub fn instance_id(&self) -> u64 {
+        self.instance_id
+    }
+
+    /// The principal making the calls
+    pub fn sender(&self) -> Principal {
+        *self.sender.read().expect("sender lock poisoned")
+    }
+
+    /// Make all following calls as `sender`
+    pub fn set_sender(&self, sender: Principal) {
+        *self.sender.write().expect("sender lock poisoned") = sender;
+    }
+
+    fn instance_url(&self, endpoint: &str) -> String {
+        format!(
+            "{}/instances/{}/{}",
+            self.server_url, self.instance_id, endpoint
+        )
+    }
+
+    /// Post `body` to the instance `endpoint`, following the
+    /// server's "started" responses until the operation is done.
+    pub(crate) async fn post<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: &impl Serialize,
+    ) -> Result<T> {
+        self.request(reqwest::Method::POST, endpoint, Some(body))
+            .await
+    }
+
+    /// Get the instance `endpoint`
+    pub(crate) async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        self.request(reqwest::Method::GET, endpoint, None::<&()>)
+            .await
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        endpoint: &str,
+        body: Option<&impl Serialize>,
+    ) -> Result<T> {
+        let mut waiter = garcon::Delay::builder()
+            .throttle(Duration::from_millis(20))
+            .timeout(Duration::from_secs(60 * 5))
+            .build();
+        waiter.start();
+
+        let mut response = loop {
+            let mut request = self
+                .client
+                .request(method.clone(), self.instance_url(endpoint));
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+            let response = request.send().await?;
+            // The instance is busy with another operation
+            if response.status() != reqwest::StatusCode::CONFLICT {
+                break response;
+            }
+            waiter
+                .async_wait()
+                .await
+                .map_err(|_| Error::PocketIc("Timed out waiting for the instance".into()))?;
+        };
+
+        if response.status() == reqwest::StatusCode::ACCEPTED {
+            let started: Started = response.json().await?;
+            let url = format!(
+                "{}/read_graph/{}/{}",
+                self.server_url, started.state_label, started.op_id
+            );
+            loop {
+                response = self.client.get(&url).send().await?;
+                if response.status() == reqwest::StatusCode::OK {
+                    break;
+                }
+                waiter
+                    .async_wait()
+                    .await
+                    .map_err(|_| Error::PocketIc("Timed out waiting for the operation".into()))?;
+            }
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::PocketIc(response.text().await?));
+        }
+        Ok(response.json().await?)
+    }
+
+    /// The current time of the instance
+    pub async fn get_time(&self) -> Result<SystemTime> {
+        let time: RawTime = self.get("read/get_time").await?;
+        Ok(UNIX_EPOCH + Duration::from_nanos(time.nanos_since_epoch))
+    }
+
+    /// Set the time of the instance
+    pub async fn set_time(&self, time: SystemTime) -> Result<()> {
+        let nanos_since_epoch = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::PocketIc(format!("Invalid time: {}", e)))?
+            .as_nanos() as u64;
+        self.post::<Value>("update/set_time", &RawTime { nanos_since_epoch })
+            .await?;
+        Ok(())
+    }
+
+    /// Move the time of the instance forward by `duration`
+    pub async fn advance_time(&self, duration: Duration) -> Result<()> {
+        let now = self.get_time().await?;
+        self.set_time(now + duration).await
+    }
+
+    /// Execute a round on the instance
+    pub async fn tick(&self) -> Result<()> {
+        self.post::<Value>("update/tick", &json!({ "blockmakers": null }))
+            .await?;
+        Ok(())
+    }
+
+    async fn canister_call(
+        &self,
+        endpoint: &str,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let call = self.raw_call(canister_id, effective_canister_id, method_name, arg);
+        canister_result(self.post(endpoint, &call).await?, method_name)
+    }
+
+    /// Submit an update call to `method_name` of `canister_id` without executing it,
+    /// returning the id of the message
+    pub(super) async fn submit(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Value> {
+        let call = self.raw_call(canister_id, None, method_name, arg);
+        match self
+            .post::<RawSubmitResult>("update/submit_ingress_message", &call)
+            .await?
+        {
+            RawSubmitResult::Ok(message_id) => Ok(message_id),
+            RawSubmitResult::Err(err) => Err(user_error(err, method_name)),
+        }
+    }
+
+    /// The body of a call to `method_name` of `canister_id` by the sender
+    pub(super) fn raw_call(
+        &self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Value {
+        let effective_principal = match effective_canister_id {
+            Some(id) => json!({ "CanisterId": { "canister_id": base64::encode(id.as_slice()) } }),
+            None if canister_id == Principal::management_canister() => json!("None"),
+            None => {
+                json!({ "CanisterId": { "canister_id": base64::encode(canister_id.as_slice()) } })
+            }
+        };
+        json!({
+            "sender": { "principal_id": base64::encode(self.sender().as_slice()) },
+            "canister_id": { "canister_id": base64::encode(canister_id.as_slice()) },
+            "effective_principal": effective_principal,
+            "method": method_name,
+            "payload": base64::encode(arg),
+        })
+    }
+}
+
+/// The reply of a call to `method_name`, or its reject as an error
+pub(super) fn canister_result(result: RawCanisterResult, method_name: &str) -> Result<Vec<u8>> {
+    match result {
+        RawCanisterResult::Ok(RawWasmResult::Reply(data)) => {
+            Ok(base64::decode(data)
+                .map_err(|e| Error::PocketIc(format!("Invalid reply: {}", e)))?)
+        }
+        RawCanisterResult::Ok(RawWasmResult::Reject(message)) => Err(Error::Rejected {
+            code: RejectCode::CanisterReject,
+            message,
+            method: method_name.into(),
+        }),
+        RawCanisterResult::Err(err) => Err(user_error(err, method_name)),
+    }
+}
+
+/// A PocketIC user error for a call to `method_name`
+pub(super) fn user_error(err: UserError, method_name: &str) -> Error {
+    Error::Rejected {
+        code: reject_code(&err.code),
+        message: format!("{}: {}", err.code, err.description),
+        method: method_name.into(),
+    }
+}
+
+impl Backend for PocketIc {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.canister_call(
+            "update/execute_ingress_message",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.canister_call(
+            "read/query",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        Box::pin(PocketIc::get_time(self))
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        Box::pin(PocketIc::set_time(self, time))
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        Box::pin(PocketIc::advance_time(self, duration))
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        Box::pin(PocketIc::tick(self))
+    }
+}
+
+impl Drop for PocketIc {
+    fn drop(&mut self) {
+        let url = format!("{}/instances/{}", self.server_url, self.instance_id);
+        // The blocking client can not be used from within an async runtime,
+        // so the instance is deleted from its own thread.
+        let _ =
+            std::thread::spawn(move || reqwest::blocking::Client::new().delete(url).send()).join();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawTime {
+    nanos_since_epoch: u64,
+}
+
+#[derive(Deserialize)]
+struct Started {
+    state_label: String,
+    op_id: String,
+}
+
+#[derive(Deserialize)]
+pub(super) enum RawCanisterResult {
+    Ok(RawWasmResult),
+    Err(UserError),
+}
+
+#[derive(Deserialize)]
+enum RawSubmitResult {
+    Ok(Value),
+    Err(UserError),
+}
+
+#[derive(Deserialize)]
+pub(super) enum RawWasmResult {
+    Reply(String),
+    Reject(String),
+}
+
+/// The reject code of a PocketIC error code like `"CanisterCalledTrap"`
+fn reject_code(error_code: &Value) -> RejectCode {
+    match error_code.as_str().unwrap_or_default() {
+        "SubnetOversubscribed"
+        | "MaxNumberOfCanistersReached"
+        | "CanisterQueueFull"
+        | "IngressMessageTimeout"
+        | "CanisterQueueNotEmpty"
+        | "IngressHistoryFull" => RejectCode::SysTransient,
+        "CanisterNotFound" | "CanisterSnapshotNotFound" | "CanisterWasmModuleNotFound" => {
+            RejectCode::DestinationInvalid
+        }
+        "CanisterRejectedMessage" => RejectCode::CanisterReject,
+        // Like a replica, a missing method is a canister error
+        _ => RejectCode::CanisterError,
+    }
+}
+
+#[derive(Deserialize)]
+pub(super) struct UserError {
+    code: Value,
+    description: String,
+}
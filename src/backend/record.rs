@@ -0,0 +1,544 @@
+//! Record the calls of a run and replay them without a replica.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use ic_agent::ic_types::Principal;
+use ic_agent::Agent;
+use serde::{Deserialize, Serialize};
+
+use super::{Backend, BackendFuture};
+use crate::{Error, RejectCode, Result};
+
+/// The kind of a recorded call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CallKind {
+    Update,
+    Query,
+}
+
+/// What a recorded call returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordedReply {
+    /// The hex encoded reply
+    Reply(String),
+    /// The call was rejected
+    Rejected { code: u64, message: String },
+    /// The call failed otherwise
+    Error(String),
+}
+
+/// A call and its reply, as saved by [`Recorder::save`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedCall {
+    kind: CallKind,
+    canister_id: String,
+    effective_canister_id: Option<String>,
+    method: String,
+    /// The hex encoded argument
+    arg: String,
+    reply: RecordedReply,
+}
+
+impl RecordedCall {
+    fn key(&self) -> CallKey {
+        (
+            self.kind,
+            self.canister_id.clone(),
+            self.effective_canister_id.clone(),
+            self.method.clone(),
+            self.arg.clone(),
+        )
+    }
+}
+
+type CallKey = (CallKind, String, Option<String>, String, String);
+
+fn call_key(
+    kind: CallKind,
+    canister_id: Principal,
+    effective_canister_id: Option<Principal>,
+    method_name: &str,
+    arg: &[u8],
+) -> CallKey {
+    (
+        kind,
+        canister_id.to_text(),
+        effective_canister_id.map(|id| id.to_text()),
+        method_name.into(),
+        hex::encode(arg),
+    )
+}
+
+/// A backend that records the calls made through it.
+///
+/// A [`Recorder`] passes the calls on to another backend and keeps each call with its
+/// reply. [`Replay`] serves the saved replies back, so the decoding and assertions of a
+/// test can run offline, and a smoke test can run in CI in a fraction of the time.
+/// Only the calls made through [`Backend::update_call`] and [`Backend::query_call`]
+/// are recorded, calls made with the agent directly are not.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, counter: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::backend::{Recorder, Replay};
+/// use ic_test_utils::Canister;
+///
+/// let recorder = Recorder::new(agent);
+/// Canister::<()>::new(counter, &recorder)
+///     .call_update("inc", Encode!().unwrap())
+///     .await
+///     .unwrap();
+/// recorder.save("counter.calls.json").unwrap();
+///
+/// // Later, without a replica
+/// let replay = Replay::load("counter.calls.json").unwrap();
+/// Canister::<()>::new(counter, &replay)
+///     .call_update("inc", Encode!().unwrap())
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct Recorder<'a> {
+    inner: &'a dyn Backend,
+    calls: Mutex<Vec<RecordedCall>>,
+}
+
+impl<'a> Recorder<'a> {
+    /// Record the calls passed on to `inner`
+    pub fn new(inner: &'a dyn Backend) -> Self {
+        Self {
+            inner,
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The number of calls recorded so far
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no call was recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Write the recorded calls to the json file at `path`, for [`Replay::load`]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(&*self.lock())?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    async fn record(
+        &self,
+        kind: CallKind,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let (_, canister, effective, method, hex_arg) =
+            call_key(kind, canister_id, effective_canister_id, method_name, &arg);
+        let result = match kind {
+            CallKind::Update => {
+                self.inner
+                    .update_call(canister_id, effective_canister_id, method_name, arg)
+                    .await
+            }
+            CallKind::Query => {
+                self.inner
+                    .query_call(canister_id, effective_canister_id, method_name, arg)
+                    .await
+            }
+        };
+
+        let reply = match &result {
+            Ok(reply) => RecordedReply::Reply(hex::encode(reply)),
+            Err(err) => match err.reject() {
+                Some((code, message)) => RecordedReply::Rejected {
+                    code: code.as_u64(),
+                    message: message.into(),
+                },
+                None => RecordedReply::Error(err.report()),
+            },
+        };
+        self.lock().push(RecordedCall {
+            kind,
+            canister_id: canister,
+            effective_canister_id: effective,
+            method,
+            arg: hex_arg,
+            reply,
+        });
+        result
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<RecordedCall>> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Backend for Recorder<'_> {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.record(
+            CallKind::Update,
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.record(
+            CallKind::Query,
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        self.inner.agent()
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        self.inner.get_time()
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        self.inner.set_time(time)
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        self.inner.advance_time(duration)
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        self.inner.tick()
+    }
+}
+
+/// A backend serving the replies saved by [`Recorder::save`].
+///
+/// A call gets the reply recorded for the same method, canister and argument.
+/// When the same call was recorded several times, the replies are served in the
+/// order they were recorded. A call that wasn't recorded fails.
+pub struct Replay {
+    replies: Mutex<HashMap<CallKey, VecDeque<RecordedReply>>>,
+}
+
+impl Replay {
+    /// Load the calls saved to the json file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let calls: Vec<RecordedCall> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let mut replies: HashMap<CallKey, VecDeque<RecordedReply>> = HashMap::new();
+        for call in calls {
+            replies.entry(call.key()).or_default().push_back(call.reply);
+        }
+        Ok(Self {
+            replies: Mutex::new(replies),
+        })
+    }
+
+    fn replay(
+        &self,
+        kind: CallKind,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = call_key(kind, canister_id, effective_canister_id, method_name, arg);
+        let reply = self
+            .replies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| {
+                Error::Generic(format!(
+                    "No recorded reply for {} of canister {} with this argument",
+                    method_name, canister_id
+                ))
+            })?;
+
+        match reply {
+            RecordedReply::Reply(reply) => {
+                hex::decode(reply).map_err(|e| Error::Generic(format!("Invalid reply: {}", e)))
+            }
+            RecordedReply::Rejected { code, message } => Err(Error::Rejected {
+                code: RejectCode::from(code),
+                message,
+                method: method_name.into(),
+            }),
+            RecordedReply::Error(message) => Err(Error::Generic(message)),
+        }
+    }
+}
+
+impl Backend for Replay {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        let result = self.replay(
+            CallKind::Update,
+            canister_id,
+            effective_canister_id,
+            method_name,
+            &arg,
+        );
+        Box::pin(async move { result })
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        let result = self.replay(
+            CallKind::Query,
+            canister_id,
+            effective_canister_id,
+            method_name,
+            &arg,
+        );
+        Box::pin(async move { result })
+    }
+}
+
+/// A recorded call sequence, re-executed by [`Session::replay`]
+///
+/// Unlike [`Replay`], which serves the recorded replies, a session makes the recorded
+/// calls again against a real backend, in the order they were recorded, to reproduce a
+/// bug seen on a testnet in a freshly deployed environment. Canister ids and other
+/// principals are remapped to those of the new environment, in the called canister and
+/// inside the candid arguments and replies.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal) {
+/// use std::collections::HashMap;
+///
+/// use ic_test_utils::backend::Session;
+///
+/// let session = Session::load("testnet.calls.json").unwrap();
+/// let recorded_ledger = session.canisters()[0];
+///
+/// // Deploy a fresh ledger, then replay the calls of the testnet session against it
+/// let map = HashMap::from([(recorded_ledger, ledger)]);
+/// let report = session.replay(agent, &map).await;
+/// if let Some(call) = report.first_divergence() {
+///     panic!("replay diverged: {}", call);
+/// }
+/// # }
+/// ```
+pub struct Session {
+    calls: Vec<RecordedCall>,
+}
+
+impl Session {
+    /// Load the calls saved to the json file at `path` by [`Recorder::save`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let calls = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Self { calls })
+    }
+
+    /// The number of recorded calls
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Whether no call was recorded
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// The canisters called in the session, in the order they were first called,
+    /// with the effective canister ids of management canister calls
+    pub fn canisters(&self) -> Vec<Principal> {
+        let mut canisters = Vec::new();
+        for call in &self.calls {
+            let ids = std::iter::once(&call.canister_id).chain(&call.effective_canister_id);
+            for id in ids.filter_map(|id| Principal::from_text(id).ok()) {
+                if id != Principal::management_canister() && !canisters.contains(&id) {
+                    canisters.push(id);
+                }
+            }
+        }
+        canisters
+    }
+
+    /// Make the recorded calls through `backend` in order, with the principals of `map`
+    /// replaced, and compare each result with the recording.
+    /// All calls are made, also after a call diverged.
+    ///
+    /// The principals in the arguments and replies are replaced where their candid
+    /// encoding appears in the bytes, so a principal of the same length inside a blob
+    /// is replaced as well.
+    pub async fn replay(
+        &self,
+        backend: &dyn Backend,
+        map: &HashMap<Principal, Principal>,
+    ) -> ReplayReport {
+        let remap = |id: &str| -> Result<Principal> {
+            let id = Principal::from_text(id)?;
+            Ok(map.get(&id).copied().unwrap_or(id))
+        };
+        let mut calls = Vec::new();
+        for (index, recorded) in self.calls.iter().enumerate() {
+            let call = async {
+                let canister_id = remap(&recorded.canister_id)?;
+                let effective_canister_id = recorded
+                    .effective_canister_id
+                    .as_deref()
+                    .map(remap)
+                    .transpose()?;
+                let arg = hex::decode(&recorded.arg)
+                    .map_err(|e| Error::Generic(format!("Invalid argument: {}", e)))?;
+                let arg = remap_principals(&arg, map);
+                let result = match recorded.kind {
+                    CallKind::Update => {
+                        backend
+                            .update_call(canister_id, effective_canister_id, &recorded.method, arg)
+                            .await
+                    }
+                    CallKind::Query => {
+                        backend
+                            .query_call(canister_id, effective_canister_id, &recorded.method, arg)
+                            .await
+                    }
+                };
+                Ok((canister_id, result))
+            };
+            let (canister_id, result) = match call.await {
+                Ok(called) => called,
+                Err(err) => (Principal::management_canister(), Err(err)),
+            };
+            let matches_recording = matches_recording(&recorded.reply, &result, map);
+            calls.push(ReplayedCall {
+                index,
+                canister_id,
+                method: recorded.method.clone(),
+                result,
+                matches_recording,
+            });
+        }
+        ReplayReport { calls }
+    }
+}
+
+/// A call made by [`Session::replay`]
+#[derive(Debug)]
+pub struct ReplayedCall {
+    /// The position of the call in the session
+    pub index: usize,
+    /// The called canister, after remapping
+    pub canister_id: Principal,
+    /// The called method
+    pub method: String,
+    /// The reply, or the error of the call
+    pub result: Result<Vec<u8>>,
+    /// Whether the call ended like in the recording: with the same reply, after
+    /// remapping, with a reject with the same code, or with another error
+    pub matches_recording: bool,
+}
+
+impl fmt::Display for ReplayedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "call {} to {} of canister {}",
+            self.index, self.method, self.canister_id
+        )?;
+        match self.result {
+            Ok(ref reply) => write!(f, " replied with {} bytes", reply.len()),
+            Err(ref err) => write!(f, " failed: {}", err.report()),
+        }
+    }
+}
+
+/// The calls made by [`Session::replay`]
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// The calls, in the order of the session
+    pub calls: Vec<ReplayedCall>,
+}
+
+impl ReplayReport {
+    /// The first call that didn't end like in the recording
+    pub fn first_divergence(&self) -> Option<&ReplayedCall> {
+        self.calls.iter().find(|call| !call.matches_recording)
+    }
+}
+
+fn matches_recording(
+    recorded: &RecordedReply,
+    result: &Result<Vec<u8>>,
+    map: &HashMap<Principal, Principal>,
+) -> bool {
+    match (recorded, result) {
+        (RecordedReply::Reply(recorded), Ok(reply)) => {
+            hex::decode(recorded).is_ok_and(|recorded| remap_principals(&recorded, map) == *reply)
+        }
+        (RecordedReply::Rejected { code, .. }, Err(err)) => {
+            err.reject_code().map(|code| code.as_u64()) == Some(*code)
+        }
+        (RecordedReply::Error(_), Err(err)) => err.reject_code().is_none(),
+        _ => false,
+    }
+}
+
+/// Replace the candid encoding of each principal of `map` in `bytes`: the `1` marking
+/// an id, the length and the bytes of the principal
+fn remap_principals(bytes: &[u8], map: &HashMap<Principal, Principal>) -> Vec<u8> {
+    let encode = |principal: &Principal| {
+        let id = principal.as_slice();
+        let mut encoded = vec![1, id.len() as u8];
+        encoded.extend_from_slice(id);
+        encoded
+    };
+    let replacements: Vec<(Vec<u8>, Vec<u8>)> = map
+        .iter()
+        .filter(|(from, to)| from != to && !from.as_slice().is_empty())
+        .map(|(from, to)| (encode(from), encode(to)))
+        .collect();
+
+    let mut remapped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    'bytes: while i < bytes.len() {
+        for (from, to) in &replacements {
+            if bytes[i..].starts_with(from) {
+                remapped.extend_from_slice(to);
+                i += from.len();
+                continue 'bytes;
+            }
+        }
+        remapped.push(bytes[i]);
+        i += 1;
+    }
+    remapped
+}
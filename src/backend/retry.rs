@@ -0,0 +1,264 @@
+//! Retry calls failing with known transient errors.
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ic_agent::ic_types::Principal;
+use ic_agent::Agent;
+
+use super::{Backend, BackendFuture};
+use crate::canister::is_transport_error;
+use crate::chaos::next_u64;
+use crate::{Error, ErrorKind, RejectCode, Result};
+
+/// A class of errors a [`RetryPolicy`] retries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOn {
+    /// The replica couldn't be reached, or answered with a server error
+    Transport,
+    /// The call was rejected with [`RejectCode::SysTransient`]
+    Transient,
+    /// The waiter gave up on an update, see [`Error::PollTimeout`].
+    /// The update is submitted again and might run twice.
+    PollTimeout,
+    /// The error is of this kind, see [`Error::kind`]
+    Kind(ErrorKind),
+}
+
+impl RetryOn {
+    fn matches(&self, err: &Error) -> bool {
+        match self {
+            Self::Transport => is_transport_error(err.root()),
+            Self::Transient => err.reject_code() == Some(RejectCode::SysTransient),
+            Self::PollTimeout => matches!(err.root(), Error::PollTimeout { .. }),
+            Self::Kind(kind) => err.kind() == *kind,
+        }
+    }
+}
+
+/// Which calls to retry, how often and how long to wait in between.
+///
+/// Retrying an update that failed with a transport error can run it twice, if the
+/// replica got it before the connection failed. Use
+/// [`Canister::call_update_idempotent`](crate::Canister::call_update_idempotent) for
+/// updates that must run at most once.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    retry_on: Vec<RetryOn>,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Try a call up to 3 times when the replica can't be reached or rejects it as
+    /// transient, waiting 200ms and then twice as long each time, up to 5s, with jitter
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_on: vec![RetryOn::Transport, RetryOn::Transient],
+            initial_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+
+    /// Never retry
+    pub fn none() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// How often a call is tried, counting the first attempt
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Also retry the errors of `class`
+    pub fn retry_on(mut self, class: RetryOn) -> Self {
+        if !self.retry_on.contains(&class) {
+            self.retry_on.push(class);
+        }
+        self
+    }
+
+    /// Retry only the errors of `classes`
+    pub fn retry_only(mut self, classes: &[RetryOn]) -> Self {
+        self.retry_on = classes.to_vec();
+        self
+    }
+
+    /// Wait `initial` before the first retry, and twice as long before each of the
+    /// next, up to `max`
+    pub fn delays(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_delay = initial;
+        self.max_delay = max.max(initial);
+        self
+    }
+
+    /// Whether to wait a random time between half the delay and the delay, so
+    /// parallel tests don't retry in lockstep
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Whether `err` is retried
+    pub fn is_retryable(&self, err: &Error) -> bool {
+        self.retry_on.iter().any(|class| class.matches(err))
+    }
+
+    /// The delay before retry number `retry`, starting at 1, with `random` for the jitter
+    fn delay(&self, retry: u32, random: u64) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        let nanos = u64::try_from(half.as_nanos()).unwrap_or(u64::MAX);
+        match nanos {
+            0 => delay,
+            nanos => half + Duration::from_nanos(random % nanos),
+        }
+    }
+}
+
+/// A backend retrying the calls made through it by a [`RetryPolicy`].
+///
+/// Errors the policy doesn't retry, like the reject of a canister, are returned right
+/// away. Once the attempts are used up the last error is returned.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, counter: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::backend::{Retry, RetryOn, RetryPolicy};
+/// use ic_test_utils::Canister;
+///
+/// let policy = RetryPolicy::new().max_attempts(5).retry_on(RetryOn::PollTimeout);
+/// let backend = Retry::new(agent.clone(), policy);
+/// Canister::<()>::new(counter, &backend)
+///     .call_update("inc", Encode!().unwrap())
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct Retry<B> {
+    inner: B,
+    policy: RwLock<RetryPolicy>,
+    random: Mutex<u64>,
+}
+
+impl<B: Backend> Retry<B> {
+    /// Retry the calls passed on to `inner` by `policy`
+    pub fn new(inner: B, policy: RetryPolicy) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos().into());
+        Self {
+            inner,
+            policy: RwLock::new(policy),
+            random: Mutex::new(seed),
+        }
+    }
+
+    /// The backend the calls are passed on to
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// The policy
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Replace the policy, for the calls made from now on
+    pub fn set_policy(&self, policy: RetryPolicy) {
+        *self.policy.write().unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    async fn call(
+        &self,
+        update: bool,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let policy = self.policy();
+        let mut attempt = 1;
+        loop {
+            let result = if update {
+                self.inner
+                    .update_call(canister_id, effective_canister_id, method_name, arg.clone())
+                    .await
+            } else {
+                self.inner
+                    .query_call(canister_id, effective_canister_id, method_name, arg.clone())
+                    .await
+            };
+            match result {
+                Err(err) if attempt < policy.max_attempts && policy.is_retryable(&err) => {
+                    let random =
+                        next_u64(&mut self.random.lock().unwrap_or_else(|e| e.into_inner()));
+                    tokio::time::sleep(policy.delay(attempt, random)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<B: Backend> Backend for Retry<B> {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(true, canister_id, effective_canister_id, method_name, arg))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(false, canister_id, effective_canister_id, method_name, arg))
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        self.inner.agent()
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        self.inner.get_time()
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        self.inner.set_time(time)
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        self.inner.advance_time(duration)
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        self.inner.tick()
+    }
+}
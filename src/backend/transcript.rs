@@ -0,0 +1,245 @@
+//! A transcript of the calls of a test, dumped when the test panics.
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use ic_agent::ic_types::Principal;
+use ic_agent::Agent;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::{Backend, BackendFuture};
+use crate::Result;
+
+/// How a call in a [`Transcript`] ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallOutcome {
+    /// The call was replied to
+    Reply {
+        /// The length of the reply in bytes
+        len: usize,
+    },
+    /// The call was rejected
+    Rejected {
+        /// The reject code
+        code: u64,
+        /// The reject message
+        message: String,
+    },
+    /// The call failed otherwise
+    Error {
+        /// The error with its causes
+        message: String,
+    },
+}
+
+/// A call in a [`Transcript`]
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEntry {
+    /// `update` or `query`
+    pub kind: &'static str,
+    /// The called canister
+    pub canister_id: String,
+    /// The effective canister id of a management canister call
+    pub effective_canister_id: Option<String>,
+    /// The called method
+    pub method: String,
+    /// The hex encoded sha256 hash of the argument
+    pub arg_sha256: String,
+    /// The length of the argument in bytes
+    pub arg_len: usize,
+    /// How long the call took, in milliseconds
+    pub duration_ms: u64,
+    /// How the call ended
+    pub outcome: CallOutcome,
+}
+
+/// A backend keeping a transcript of the calls made through it.
+///
+/// A [`Transcript`] passes the calls on to another backend and notes the method,
+/// canister, argument hash, duration and outcome of each. If it is dropped while the
+/// test panics, the transcript is printed to stderr as JSON, or written to the file
+/// given to [`Transcript::write_on_panic`], so a flaky failure in CI comes with the
+/// calls leading up to it.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, counter: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::backend::Transcript;
+/// use ic_test_utils::Canister;
+///
+/// let transcript = Transcript::new(agent).write_on_panic("target/counter.transcript.json");
+/// Canister::<()>::new(counter, &transcript)
+///     .call_update("inc", Encode!().unwrap())
+///     .await
+///     .unwrap();
+/// assert_eq!(transcript.entries().len(), 1);
+/// # }
+/// ```
+pub struct Transcript<'a> {
+    inner: &'a dyn Backend,
+    entries: Mutex<Vec<TranscriptEntry>>,
+    panic_path: Option<PathBuf>,
+}
+
+impl<'a> Transcript<'a> {
+    /// Keep a transcript of the calls passed on to `inner`
+    pub fn new(inner: &'a dyn Backend) -> Self {
+        Self {
+            inner,
+            entries: Mutex::new(Vec::new()),
+            panic_path: None,
+        }
+    }
+
+    /// Write the transcript to `path` instead of stderr when the test panics
+    pub fn write_on_panic(mut self, path: impl AsRef<Path>) -> Self {
+        self.panic_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The calls so far, oldest first
+    pub fn entries(&self) -> Vec<TranscriptEntry> {
+        self.lock().clone()
+    }
+
+    /// The calls so far as JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&*self.lock())?)
+    }
+
+    /// Write the calls so far as JSON to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        kind: &'static str,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let arg_sha256 = hex::encode(Sha256::digest(&arg));
+        let arg_len = arg.len();
+        let started = Instant::now();
+        let result = if kind == "update" {
+            self.inner
+                .update_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        } else {
+            self.inner
+                .query_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        };
+        let duration_ms = u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+        let outcome = match &result {
+            Ok(reply) => CallOutcome::Reply { len: reply.len() },
+            Err(err) => match err.reject() {
+                Some((code, message)) => CallOutcome::Rejected {
+                    code: code.as_u64(),
+                    message: message.into(),
+                },
+                None => CallOutcome::Error {
+                    message: err.report(),
+                },
+            },
+        };
+        self.lock().push(TranscriptEntry {
+            kind,
+            canister_id: canister_id.to_text(),
+            effective_canister_id: effective_canister_id.map(|id| id.to_text()),
+            method: method_name.into(),
+            arg_sha256,
+            arg_len,
+            duration_ms,
+            outcome,
+        });
+        result
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<TranscriptEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Transcript<'_> {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        let json = match self.to_json() {
+            Ok(json) => json,
+            Err(err) => return eprintln!("failed to render the call transcript: {}", err),
+        };
+        match self.panic_path {
+            Some(ref path) => match std::fs::write(path, json) {
+                Ok(()) => eprintln!("call transcript written to {}", path.display()),
+                Err(err) => eprintln!(
+                    "failed to write the call transcript to {}: {}",
+                    path.display(),
+                    err
+                ),
+            },
+            None => eprintln!("call transcript:\n{}", json),
+        }
+    }
+}
+
+impl Backend for Transcript<'_> {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(
+            "update",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(
+            "query",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        self.inner.agent()
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        self.inner.get_time()
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        self.inner.set_time(time)
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        self.inner.advance_time(duration)
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        self.inner.tick()
+    }
+}
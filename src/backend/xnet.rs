@@ -0,0 +1,231 @@
+//! Calls across subnets on a PocketIC instance with several subnets.
+//!
+//! Canisters are placed on a subnet with [`PocketIc::create_canister_on_subnet`], and
+//! [`PocketIc::updates_in_rounds`] counts the rounds until calls complete, so a test can
+//! check the latency of the cross-subnet calls a canister makes and the order they
+//! complete in.
+//!
+//! ```
+//! # async fn run(wasm: Vec<u8>) {
+//! use candid::Encode;
+//! use ic_test_utils::backend::PocketIc;
+//! use ic_test_utils::Canister;
+//!
+//! let pic = PocketIc::with_application_subnets("http://127.0.0.1:8080", 2)
+//!     .await
+//!     .unwrap();
+//! let subnets = pic.subnets().await.unwrap();
+//! let a = pic.create_canister_on_subnet(subnets[0].id, 1_000_000_000_000).await.unwrap();
+//! let b = pic.create_canister_on_subnet(subnets[1].id, 1_000_000_000_000).await.unwrap();
+//!
+//! let management = Canister::new_management(&pic);
+//! management.install_code_no_arg(a, &wasm).await.unwrap();
+//! management.install_code_no_arg(b, &wasm).await.unwrap();
+//!
+//! // `a` calls `b` on the other subnet
+//! let (_, rounds) = pic
+//!     .update_in_rounds(a, "call", Encode!(&b).unwrap(), 20)
+//!     .await
+//!     .unwrap();
+//! assert!(rounds > 1);
+//! # }
+//! ```
+use std::collections::HashMap;
+
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
+use ic_agent::ic_types::Principal;
+use serde_json::json;
+
+use super::outcalls::RawSubnetId;
+use super::pocket_ic::{canister_result, RawCanisterResult};
+use super::PocketIc;
+use crate::{Error, Result};
+
+/// A subnet of a PocketIC instance
+#[derive(Debug, Clone)]
+pub struct Subnet {
+    /// The id of the subnet
+    pub id: Principal,
+    /// The kind of the subnet, like `Application` or `NNS`
+    pub kind: String,
+    /// The ranges of the canister ids on the subnet, first and last id
+    pub canister_ranges: Vec<(Principal, Principal)>,
+}
+
+/// An update call completed by [`PocketIc::updates_in_rounds`]
+#[derive(Debug)]
+pub struct CompletedCall {
+    /// The position of the call in the submitted calls
+    pub index: usize,
+    /// The rounds executed until the call completed
+    pub rounds: u32,
+    /// The reply, or the reject as an error
+    pub result: Result<Vec<u8>>,
+}
+
+impl PocketIc {
+    /// The subnets of the instance
+    pub async fn subnets(&self) -> Result<Vec<Subnet>> {
+        let topology: RawTopology = self.get("_/topology").await?;
+        let mut subnets = topology
+            .subnet_configs
+            .into_iter()
+            .map(|(id, config)| {
+                let canister_ranges = config
+                    .canister_ranges
+                    .into_iter()
+                    .map(|range| Ok((range.start.principal()?, range.end.principal()?)))
+                    .collect::<Result<_>>()?;
+                Ok(Subnet {
+                    id: Principal::from_text(&id)
+                        .map_err(|e| Error::PocketIc(format!("Invalid subnet id: {}", e)))?,
+                    kind: config.subnet_kind,
+                    canister_ranges,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The server returns a map, the subnets are sorted to be found at the same index
+        subnets.sort_by_key(|subnet| subnet.canister_ranges.first().map(|range| range.0));
+        Ok(subnets)
+    }
+
+    /// The subnet `canister_id` is on, `None` if there is no such canister
+    pub async fn canister_subnet(&self, canister_id: Principal) -> Result<Option<Principal>> {
+        let subnet: Option<RawSubnetId> = self
+            .post(
+                "read/get_subnet",
+                &json!({ "canister_id": base64::encode(canister_id.as_slice()) }),
+            )
+            .await?;
+        subnet
+            .map(|subnet| {
+                let id = base64::decode(subnet.subnet_id)
+                    .map_err(|e| Error::PocketIc(format!("Invalid subnet id: {}", e)))?;
+                Ok(Principal::try_from_slice(&id)?)
+            })
+            .transpose()
+    }
+
+    /// Create an empty canister with `cycles` on the subnet `subnet_id`, controlled by
+    /// the sender
+    pub async fn create_canister_on_subnet(
+        &self,
+        subnet_id: Principal,
+        cycles: u64,
+    ) -> Result<Principal> {
+        let method_name = "provisional_create_canister_with_cycles";
+        let arg = Encode!(&ProvisionalCreateArgs {
+            amount: Some(Nat::from(cycles)),
+        })?;
+        let mut call = self.raw_call(Principal::management_canister(), None, method_name, arg);
+        call["effective_principal"] =
+            json!({ "SubnetId": { "subnet_id": base64::encode(subnet_id.as_slice()) } });
+        let result: RawCanisterResult = self.post("update/execute_ingress_message", &call).await?;
+        let reply = canister_result(result, method_name)?;
+        Ok(Decode!(&reply, CreateCanisterResult)?.canister_id)
+    }
+
+    /// Call `method_name` of `canister_id` as an update, executing a round at a time,
+    /// and return the reply with the rounds it took.
+    /// Fails if the call didn't complete within `max_rounds`.
+    pub async fn update_in_rounds(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+        max_rounds: u32,
+    ) -> Result<(Vec<u8>, u32)> {
+        let mut completed = self
+            .updates_in_rounds(vec![(canister_id, method_name, arg)], max_rounds)
+            .await?;
+        let call = completed.remove(0);
+        Ok((call.result?, call.rounds))
+    }
+
+    /// Submit the update `calls` as canister id, method name and argument at once, then
+    /// execute a round at a time until all completed, and return them in the order they
+    /// completed. Calls completing in the same round are in the order they were submitted.
+    /// Fails if a call didn't complete within `max_rounds`.
+    pub async fn updates_in_rounds(
+        &self,
+        calls: Vec<(Principal, &str, Vec<u8>)>,
+        max_rounds: u32,
+    ) -> Result<Vec<CompletedCall>> {
+        let mut pending = Vec::new();
+        for (index, (canister_id, method_name, arg)) in calls.into_iter().enumerate() {
+            let message_id = self.submit(canister_id, method_name, arg).await?;
+            pending.push((index, method_name, message_id));
+        }
+
+        let mut completed = Vec::new();
+        for round in 1..=max_rounds {
+            self.tick().await?;
+            let mut still_pending = Vec::new();
+            for (index, method_name, message_id) in pending {
+                let status: Option<RawCanisterResult> = self
+                    .post(
+                        "read/ingress_status",
+                        &json!({ "raw_message_id": message_id, "raw_caller": null }),
+                    )
+                    .await?;
+                match status {
+                    Some(result) => completed.push(CompletedCall {
+                        index,
+                        rounds: round,
+                        result: canister_result(result, method_name),
+                    }),
+                    None => still_pending.push((index, method_name, message_id)),
+                }
+            }
+            pending = still_pending;
+            if pending.is_empty() {
+                return Ok(completed);
+            }
+        }
+        Err(Error::PocketIc(format!(
+            "{} calls did not complete within {} rounds",
+            pending.len(),
+            max_rounds
+        )))
+    }
+}
+
+#[derive(CandidType)]
+struct ProvisionalCreateArgs {
+    amount: Option<Nat>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct CreateCanisterResult {
+    canister_id: Principal,
+}
+
+#[derive(Deserialize)]
+struct RawTopology {
+    subnet_configs: HashMap<String, RawSubnetConfig>,
+}
+
+#[derive(Deserialize)]
+struct RawSubnetConfig {
+    subnet_kind: String,
+    canister_ranges: Vec<RawCanisterRange>,
+}
+
+#[derive(Deserialize)]
+struct RawCanisterRange {
+    start: RawCanisterId,
+    end: RawCanisterId,
+}
+
+#[derive(Deserialize)]
+struct RawCanisterId {
+    canister_id: String,
+}
+
+impl RawCanisterId {
+    fn principal(&self) -> Result<Principal> {
+        let id = base64::decode(&self.canister_id)
+            .map_err(|e| Error::PocketIc(format!("Invalid canister id: {}", e)))?;
+        Ok(Principal::try_from_slice(&id)?)
+    }
+}
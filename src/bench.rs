@@ -0,0 +1,150 @@
+//! Benchmark the latency and the cycles of calls, with Criterion or on their own.
+//!
+//! A [`CallBench`] makes the same call again and again through one backend, so the
+//! agent and its connection are reused between iterations, and makes a few warm-up
+//! calls before the first timed one. [`CallBench::iter_custom`] fits Criterion's
+//! async `iter_custom`, see `benches/calls.rs`:
+//!
+//! ```
+//! use candid::{Encode, Principal};
+//! use criterion::Criterion;
+//! use ic_test_utils::bench::CallBench;
+//! use ic_test_utils::get_agent;
+//!
+//! fn inc(c: &mut Criterion, counter: Principal) {
+//!     let runtime = tokio::runtime::Runtime::new().unwrap();
+//!     let agent = runtime.block_on(get_agent("default", None)).unwrap();
+//!     let bench = CallBench::update(&agent, counter, "inc", Encode!().unwrap());
+//!     c.bench_function("inc", |b| {
+//!         b.to_async(&runtime).iter_custom(|iters| bench.iter_custom(iters))
+//!     });
+//! }
+//! ```
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, counter: candid::Principal) {
+//! use candid::Encode;
+//! use ic_test_utils::bench::CallBench;
+//!
+//! let bench = CallBench::update(agent, counter, "inc", Encode!().unwrap()).warm_up(5);
+//! let elapsed = bench.measure(100).await.unwrap();
+//! println!("{:?} per call", elapsed / 100);
+//! let cycles = bench.cycles_per_call(100).await.unwrap();
+//! assert!(cycles < 1_000_000);
+//! # }
+//! ```
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use candid::Principal;
+
+use crate::backend::Backend;
+use crate::cost::to_i128;
+use crate::{Canister, Result};
+
+/// A call repeated by a benchmark
+pub struct CallBench<'a> {
+    backend: &'a dyn Backend,
+    canister_id: Principal,
+    method: String,
+    arg: Vec<u8>,
+    update: bool,
+    warm_up: u64,
+    warmed_up: AtomicBool,
+}
+
+impl<'a> CallBench<'a> {
+    /// Benchmark the update `method_name` of `canister_id` with `arg`.
+    /// By default three warm-up calls are made before the first timed one.
+    pub fn update(
+        backend: &'a dyn Backend,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Self {
+        Self::new(true, backend, canister_id, method_name, arg)
+    }
+
+    /// Benchmark the query `method_name` of `canister_id` with `arg`
+    pub fn query(
+        backend: &'a dyn Backend,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Self {
+        Self::new(false, backend, canister_id, method_name, arg)
+    }
+
+    fn new(
+        update: bool,
+        backend: &'a dyn Backend,
+        canister_id: Principal,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Self {
+        Self {
+            backend,
+            canister_id,
+            method: method_name.into(),
+            arg,
+            update,
+            warm_up: 3,
+            warmed_up: AtomicBool::new(false),
+        }
+    }
+
+    /// The number of untimed calls made before the first timed one
+    pub fn warm_up(mut self, calls: u64) -> Self {
+        self.warm_up = calls;
+        self
+    }
+
+    /// Make the call once
+    pub async fn call(&self) -> Result<Vec<u8>> {
+        if self.update {
+            self.backend
+                .update_call(self.canister_id, None, &self.method, self.arg.clone())
+                .await
+        } else {
+            self.backend
+                .query_call(self.canister_id, None, &self.method, self.arg.clone())
+                .await
+        }
+    }
+
+    /// Make the call `iters` times and return the time it took, after the warm-up
+    /// calls if they weren't made yet
+    pub async fn measure(&self, iters: u64) -> Result<Duration> {
+        if !self.warmed_up.swap(true, Ordering::SeqCst) {
+            for _ in 0..self.warm_up {
+                self.call().await?;
+            }
+        }
+        let start = Instant::now();
+        for _ in 0..iters {
+            self.call().await?;
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Like [`CallBench::measure`] for Criterion's `iter_custom`, panicking if a call
+    /// fails
+    pub async fn iter_custom(&self, iters: u64) -> Duration {
+        match self.measure(iters).await {
+            Ok(elapsed) => elapsed,
+            Err(err) => panic!("benchmark of {} failed: {}", self.method, err.report()),
+        }
+    }
+
+    /// Make the call `calls` times and return the average cycles the canister used
+    /// per call, read from its balance like [`measure_cycles`](crate::cost::measure_cycles)
+    pub async fn cycles_per_call(&self, calls: u64) -> Result<i128> {
+        let management = Canister::new_management(self.backend);
+        let before = management.canister_status(self.canister_id).await?.cycles;
+        for _ in 0..calls {
+            self.call().await?;
+        }
+        let after = management.canister_status(self.canister_id).await?.cycles;
+        Ok((to_i128(before)? - to_i128(after)?) / i128::from(calls.max(1)))
+    }
+}
@@ -0,0 +1,184 @@
+//! Create, install and inspect canisters from the command line, with the identity,
+//! replica and canister names resolved like the library does in tests.
+#![allow(clippy::result_large_err)]
+
+use std::process::ExitCode;
+
+use candid::{Encode, Principal};
+use ic_test_utils::canister::InstallMode;
+use ic_test_utils::dfx::local_replica_url;
+use ic_test_utils::harness::{ACCOUNT_ENV_VAR, DEFAULT_CYCLES, URL_ENV_VAR};
+use ic_test_utils::interface::encode_text;
+use ic_test_utils::registry::CanisterRegistry;
+use ic_test_utils::{get_agent, Agent, Canister, Error, Result};
+
+const USAGE: &str = "\
+Usage: ic-test-utils [--account NAME] [--url URL] [--network NETWORK] COMMAND
+
+Commands:
+  create [CYCLES]                 Create an empty canister through the wallet
+  install CANISTER WASM [ARG]     Install a module, with a candid text argument
+  reinstall CANISTER WASM [ARG]   Replace the module and the state
+  upgrade CANISTER WASM [ARG]     Upgrade the module, keeping the stable memory
+  status CANISTER                 Print the status of a canister
+  start CANISTER                  Start a canister
+  stop CANISTER                   Stop a canister
+  delete CANISTER                 Stop and delete a canister
+  top-up CANISTER CYCLES          Deposit cycles from the wallet
+
+A CANISTER is a principal, or a name resolved from the CANISTER_ID_<NAME> env vars
+and the canister_ids.json of the dfx project in the current directory.
+The account defaults to IC_TEST_ACCOUNT, then `default`, the url to IC_TEST_URL, then
+the replica dfx runs for the current directory.";
+
+struct Options {
+    account: String,
+    url: Option<String>,
+    network: String,
+    command: Vec<String>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options {
+        account: std::env::var(ACCOUNT_ENV_VAR).unwrap_or_else(|_| "default".into()),
+        url: std::env::var(URL_ENV_VAR).ok(),
+        network: "local".into(),
+        command: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| Error::Generic(format!("{} needs a value", flag)))
+        };
+        match arg.as_str() {
+            "--account" => options.account = value("--account")?,
+            "--url" => options.url = Some(value("--url")?),
+            "--network" => options.network = value("--network")?,
+            _ => {
+                options.command.push(arg);
+                options.command.extend(args);
+                break;
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn resolve(registry: &CanisterRegistry, canister: &str) -> Result<Principal> {
+    Principal::from_text(canister).or_else(|_| registry.resolve(canister))
+}
+
+fn parse_cycles(cycles: &str) -> Result<u64> {
+    cycles
+        .replace('_', "")
+        .parse()
+        .map_err(|e| Error::Generic(format!("Invalid cycles {}: {}", cycles, e)))
+}
+
+async fn install(
+    agent: &Agent,
+    registry: &CanisterRegistry,
+    mode: InstallMode,
+    args: &[String],
+) -> Result<()> {
+    let (canister, wasm) = match args {
+        [canister, wasm, ..] => (resolve(registry, canister)?, wasm),
+        _ => return Err(Error::Generic("Expected CANISTER WASM [ARG]".into())),
+    };
+    let bytecode = std::fs::read(wasm)?;
+    let arg = match args.get(2) {
+        Some(text) => encode_text(text, None)?,
+        None => Encode!()?,
+    };
+    Canister::new_management(agent)
+        .install_raw(canister, &bytecode, mode, &arg)
+        .await
+}
+
+async fn run(options: Options) -> Result<()> {
+    let (command, args) = match options.command.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return Err(Error::Generic(USAGE.into())),
+    };
+    if matches!(command, "help" | "--help" | "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let registry = CanisterRegistry::new()
+        .with_dfx_project(".", &options.network)
+        .unwrap_or_default();
+    let url = options.url.clone().or_else(|| local_replica_url(".").ok());
+    let agent = get_agent(options.account.as_str(), url.as_deref()).await?;
+    let management = Canister::new_management(&agent);
+    let canister = |i: usize| match options.command.get(i) {
+        Some(canister) => resolve(&registry, canister),
+        None => Err(Error::Generic("Expected a CANISTER".into())),
+    };
+    match command {
+        "create" => {
+            let cycles = args
+                .first()
+                .map_or(Ok(DEFAULT_CYCLES), |cycles| parse_cycles(cycles))?;
+            let wallet = Canister::new_wallet(&agent, &options.account, None)?;
+            println!("{}", wallet.create_canister(cycles, None).await?);
+        }
+        "install" => install(&agent, &registry, InstallMode::Install, args).await?,
+        "reinstall" => install(&agent, &registry, InstallMode::Reinstall, args).await?,
+        "upgrade" => install(&agent, &registry, InstallMode::Upgrade, args).await?,
+        "status" => {
+            let status = management.canister_status(canister(1)?).await?;
+            println!("status: {:?}", status.status);
+            println!(
+                "module hash: {}",
+                status
+                    .module_hash
+                    .as_ref()
+                    .map_or_else(|| "none".into(), |hash| hex::encode(hash.as_slice()))
+            );
+            println!("controllers:");
+            for controller in &status.settings.controllers {
+                println!("  {}", controller);
+            }
+            println!("memory: {} bytes", status.total_memory_size());
+            println!("cycles: {}", status.cycles);
+        }
+        "start" => management.start_canister(canister(1)?).await?,
+        "stop" => management.stop_canister(canister(1)?).await?,
+        "delete" => {
+            let canister = canister(1)?;
+            management.stop_canister(canister).await?;
+            management.delete_canister(canister).await?;
+        }
+        "top-up" => {
+            let cycles = match args.get(1) {
+                Some(cycles) => parse_cycles(cycles)?,
+                None => return Err(Error::Generic("Expected CANISTER CYCLES".into())),
+            };
+            let wallet = Canister::new_wallet(&agent, &options.account, None)?;
+            wallet.deposit_cycles(canister(1)?, cycles).await?;
+        }
+        command => {
+            return Err(Error::Generic(format!(
+                "Unknown command {}\n\n{}",
+                command, USAGE
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_options().and_then(|options| {
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(run(options))
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err.report());
+            ExitCode::FAILURE
+        }
+    }
+}
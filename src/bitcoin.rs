@@ -0,0 +1,288 @@
+//! Drive a local `bitcoind` regtest node and read the results through the
+//! Bitcoin API of the management canister, for ckBTC like flows.
+//!
+//! The replica has to be started with the Bitcoin integration pointing at the node,
+//! like `dfx start --enable-bitcoin`. Only canisters can call the Bitcoin API,
+//! so the calls are forwarded through a wallet.
+//! This module needs the `bitcoin` feature.
+//!
+//! ```
+//! # async fn run(wallet: ic_test_utils::WalletCanister<'_>) {
+//! use ic_test_utils::bitcoin::{wait_for_balance, Bitcoind};
+//!
+//! let bitcoind = Bitcoind::new("http://127.0.0.1:18443", "ic-btc-integration", "secret");
+//! bitcoind.create_wallet("miner").await.unwrap();
+//! let address = bitcoind.get_new_address().await.unwrap();
+//! bitcoind.fund(&address, 1.5).await.unwrap();
+//!
+//! let balance = wait_for_balance(&wallet, &address, 150_000_000).await.unwrap();
+//! assert_eq!(balance, 150_000_000);
+//! # }
+//! ```
+use std::time::Duration;
+
+use candid::{CandidType, Decode, Deserialize, Encode};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::canister::WalletCanister;
+use crate::wait::wait_for;
+use crate::{Error, Result};
+
+/// The number of confirmations a coinbase output needs before it can be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// The cycles attached to the Bitcoin API calls, enough for every network
+pub const BITCOIN_API_CYCLES: u64 = 10_000_000_000;
+
+/// How long [`wait_for_balance`] waits for the replica to see the blocks
+pub const SYNC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The bitcoind wallet error when a wallet of that name exists already
+const WALLET_EXISTS: i64 = -4;
+
+/// A bitcoind node, talked to over JSON-RPC
+pub struct Bitcoind {
+    url: String,
+    user: String,
+    password: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl Bitcoind {
+    /// A node at `url`, with the `-rpcuser` and `-rpcpassword` it was started with
+    pub fn new(
+        url: impl Into<String>,
+        user: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            user: user.into(),
+            password: password.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Call the RPC `method` with `params`
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "ic-test-utils",
+            "method": method,
+            "params": params,
+        });
+        let response: RpcResponse = self
+            .client
+            .post(&self.url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        match response.error {
+            Some(error) => Err(Error::Bitcoind {
+                code: error.code,
+                message: error.message,
+            }),
+            None => Ok(serde_json::from_value(response.result)?),
+        }
+    }
+
+    /// Create the wallet `name`, or load it if it exists already.
+    /// The other wallet calls use the only loaded wallet.
+    pub async fn create_wallet(&self, name: &str) -> Result<()> {
+        match self.call::<Value>("createwallet", json!([name])).await {
+            Err(Error::Bitcoind { code, .. }) if code == WALLET_EXISTS => {
+                // The wallet may be loaded already, which is fine
+                match self.call::<Value>("loadwallet", json!([name])).await {
+                    Ok(_) | Err(Error::Bitcoind { .. }) => Ok(()),
+                    Err(err) => Err(err),
+                }
+            }
+            result => result.map(|_| ()),
+        }
+    }
+
+    /// A new address of the wallet
+    pub async fn get_new_address(&self) -> Result<String> {
+        self.call("getnewaddress", json!([])).await
+    }
+
+    /// The height of the best chain
+    pub async fn get_block_count(&self) -> Result<u64> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    /// Mine `blocks` blocks paying to `address`, returning their hashes
+    pub async fn generate_to_address(&self, blocks: u64, address: &str) -> Result<Vec<String>> {
+        self.call("generatetoaddress", json!([blocks, address]))
+            .await
+    }
+
+    /// Send `btc` from the wallet to `address`, returning the transaction id.
+    /// The transaction is only confirmed once a block is mined.
+    pub async fn send_to_address(&self, address: &str, btc: f64) -> Result<String> {
+        self.call("sendtoaddress", json!([address, btc])).await
+    }
+
+    /// Send `btc` to `address` and confirm the transaction.
+    /// Blocks are mined to the wallet until it can afford the transfer.
+    pub async fn fund(&self, address: &str, btc: f64) -> Result<String> {
+        let miner = self.get_new_address().await?;
+        loop {
+            let balance: f64 = self.call("getbalance", json!([])).await?;
+            if balance > btc {
+                break;
+            }
+            // Coinbase outputs can only be spent once they matured
+            self.generate_to_address(COINBASE_MATURITY + 1, &miner)
+                .await?;
+        }
+        let txid = self.send_to_address(address, btc).await?;
+        self.generate_to_address(1, &miner).await?;
+        Ok(txid)
+    }
+}
+
+/// The Bitcoin network of the management canister API
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, PartialEq, Eq)]
+pub enum BitcoinNetwork {
+    /// The Bitcoin mainnet
+    #[serde(rename = "mainnet")]
+    Mainnet,
+    /// The Bitcoin testnet
+    #[serde(rename = "testnet")]
+    Testnet,
+    /// A local regtest network
+    #[serde(rename = "regtest")]
+    Regtest,
+}
+
+/// An unspent output, see [`get_utxos`]
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct Utxo {
+    /// The output
+    pub outpoint: Outpoint,
+    /// The value in satoshi
+    pub value: u64,
+    /// The height of the block holding the transaction
+    pub height: u32,
+}
+
+/// A transaction output
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct Outpoint {
+    /// The transaction id, in the byte order of the wire format
+    #[serde(with = "serde_bytes")]
+    pub txid: Vec<u8>,
+    /// The index of the output in the transaction
+    pub vout: u32,
+}
+
+/// The unspent outputs of an address, see [`get_utxos`]
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct GetUtxosResponse {
+    /// The outputs
+    pub utxos: Vec<Utxo>,
+    /// The hash of the tip the outputs are read at
+    #[serde(with = "serde_bytes")]
+    pub tip_block_hash: Vec<u8>,
+    /// The height of the tip
+    pub tip_height: u32,
+    /// The page to ask for next, if there are more outputs
+    pub next_page: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType)]
+struct GetBalanceRequest<'a> {
+    address: &'a str,
+    network: BitcoinNetwork,
+    min_confirmations: Option<u32>,
+}
+
+#[derive(CandidType)]
+struct GetUtxosRequest<'a> {
+    address: &'a str,
+    network: BitcoinNetwork,
+    filter: Option<UtxosFilter>,
+}
+
+#[derive(CandidType, Deserialize)]
+enum UtxosFilter {
+    #[serde(rename = "min_confirmations")]
+    MinConfirmations(u32),
+}
+
+/// The regtest balance of `address` in satoshi, as seen by the replica
+pub async fn get_balance(
+    wallet: &WalletCanister<'_>,
+    address: &str,
+    min_confirmations: Option<u32>,
+) -> Result<u64> {
+    let request = GetBalanceRequest {
+        address,
+        network: BitcoinNetwork::Regtest,
+        min_confirmations,
+    };
+    let data = wallet
+        .call_management(
+            "bitcoin_get_balance",
+            Encode!(&request)?,
+            BITCOIN_API_CYCLES,
+        )
+        .await?;
+    Ok(Decode!(&data, u64)?)
+}
+
+/// The regtest unspent outputs of `address`, as seen by the replica
+pub async fn get_utxos(
+    wallet: &WalletCanister<'_>,
+    address: &str,
+    min_confirmations: Option<u32>,
+) -> Result<GetUtxosResponse> {
+    let request = GetUtxosRequest {
+        address,
+        network: BitcoinNetwork::Regtest,
+        filter: min_confirmations.map(UtxosFilter::MinConfirmations),
+    };
+    let data = wallet
+        .call_management("bitcoin_get_utxos", Encode!(&request)?, BITCOIN_API_CYCLES)
+        .await?;
+    Ok(Decode!(&data, GetUtxosResponse)?)
+}
+
+/// Wait until the replica sees a balance of at least `satoshi` for `address`,
+/// as it syncs the blocks of the node with a delay
+pub async fn wait_for_balance(
+    wallet: &WalletCanister<'_>,
+    address: &str,
+    satoshi: u64,
+) -> Result<u64> {
+    wait_for(
+        || async {
+            match get_balance(wallet, address, None).await {
+                Ok(balance) if balance >= satoshi => Ok(balance),
+                Ok(balance) => Err(balance.to_string()),
+                Err(err) => Err(err.report()),
+            }
+        },
+        SYNC_TIMEOUT,
+        Duration::from_millis(500),
+    )
+    .await
+    .map_err(|timeout| Error::Generic(format!("Balance of {}: {}", address, timeout)))
+}
@@ -0,0 +1,312 @@
+//! Functions specific to the certified assets canister.
+use std::path::Path;
+
+use candid::{CandidType, Decode, Deserialize, Encode, Int, Nat};
+use ic_agent::ic_types::Principal;
+
+use super::Canister;
+use crate::backend::Backend;
+use crate::wasm::sha256;
+use crate::{Error, Result};
+
+/// The size of the chunks content is uploaded in, under the ingress message limit
+pub const CHUNK_SIZE: usize = 1_900_000;
+
+/// An asset stored in the canister, see [`Canister::list`].
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct AssetDetails {
+    /// The path of the asset, like `/index.html`
+    pub key: String,
+    /// The content type
+    pub content_type: String,
+    /// The encodings the asset is stored in
+    pub encodings: Vec<AssetEncodingDetails>,
+}
+
+/// An encoding of an asset
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct AssetEncodingDetails {
+    /// The content encoding, like `identity` or `gzip`
+    pub content_encoding: String,
+    /// The SHA-256 of the encoded content
+    pub sha256: Option<serde_bytes::ByteBuf>,
+    /// The length of the encoded content
+    pub length: Nat,
+    /// When the encoding was last modified, in nanoseconds since the epoch
+    pub modified: Int,
+}
+
+#[derive(CandidType)]
+struct StoreArg<'a> {
+    key: &'a str,
+    content_type: &'a str,
+    content_encoding: &'a str,
+    content: &'a serde_bytes::Bytes,
+    sha256: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType)]
+struct CreateBatchRequest {}
+
+#[derive(CandidType, Deserialize)]
+struct CreateBatchResponse {
+    batch_id: Nat,
+}
+
+#[derive(CandidType)]
+struct CreateChunkRequest<'a> {
+    batch_id: Nat,
+    content: &'a serde_bytes::Bytes,
+}
+
+#[derive(CandidType, Deserialize)]
+struct CreateChunkResponse {
+    chunk_id: Nat,
+}
+
+#[derive(CandidType)]
+struct CreateAssetArguments {
+    key: String,
+    content_type: String,
+}
+
+#[derive(CandidType)]
+struct SetAssetContentArguments {
+    key: String,
+    content_encoding: String,
+    chunk_ids: Vec<Nat>,
+    sha256: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType)]
+enum BatchOperationKind {
+    CreateAsset(CreateAssetArguments),
+    SetAssetContent(SetAssetContentArguments),
+}
+
+#[derive(CandidType)]
+struct CommitBatchArguments {
+    batch_id: Nat,
+    operations: Vec<BatchOperationKind>,
+}
+
+#[derive(CandidType)]
+struct DeleteAssetArguments<'a> {
+    key: &'a str,
+}
+
+#[derive(CandidType)]
+struct ListRequest {}
+
+/// An asset to upload, see [`Canister::upload`]
+#[derive(Debug, Clone)]
+pub struct Asset {
+    /// The path of the asset, like `/index.html`
+    pub key: String,
+    /// The content type
+    pub content_type: String,
+    /// The content, stored with the `identity` encoding
+    pub content: Vec<u8>,
+}
+
+/// Who can change the assets, see [`AssetsInitArgs`]
+#[derive(Debug, Clone, Default, CandidType, Deserialize)]
+pub struct AssetsPermissions {
+    /// Principals that can prepare batches
+    pub prepare: Vec<Principal>,
+    /// Principals that can commit batches and store assets
+    pub commit: Vec<Principal>,
+    /// Principals that can grant and revoke permissions
+    pub manage_permissions: Vec<Principal>,
+}
+
+/// Init arguments of the assets canister, the installer is the only committer by default
+///
+/// ```
+/// # fn run(deployer: candid::Principal) {
+/// use candid::Encode;
+/// use ic_test_utils::canister::AssetsInitArgs;
+///
+/// let arg = Encode!(&AssetsInitArgs::default().with_committers(vec![deployer]).into_payload()).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, CandidType, Deserialize)]
+pub struct AssetsInitArgs {
+    /// The permissions, instead of the default
+    pub set_permissions: Option<AssetsPermissions>,
+}
+
+impl AssetsInitArgs {
+    /// Let `committers` prepare and commit batches
+    pub fn with_committers(mut self, committers: Vec<Principal>) -> Self {
+        let permissions = self.set_permissions.get_or_insert_with(Default::default);
+        permissions.prepare = committers.clone();
+        permissions.commit = committers;
+        self
+    }
+
+    /// The argument to install the canister with, an `opt` of [`AssetsCanisterArgs`]
+    pub fn into_payload(self) -> Option<AssetsCanisterArgs> {
+        Some(AssetsCanisterArgs::Init(self))
+    }
+}
+
+/// The argument to install the assets canister with.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub enum AssetsCanisterArgs {
+    /// Init arguments
+    Init(AssetsInitArgs),
+}
+
+/// The certified assets canister, like the frontend canisters of dfx
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, frontend: candid::Principal) {
+/// use ic_test_utils::canister::AssetsCanister;
+///
+/// let assets = AssetsCanister::new_assets(agent, frontend);
+/// let keys = assets.upload_dir("dist").await.unwrap();
+/// assert!(keys.contains(&"/index.html".to_string()));
+/// # }
+/// ```
+pub struct Assets;
+
+impl<'agent> Canister<'agent, Assets> {
+    /// Create a new assets canister
+    pub fn new_assets(backend: &'agent dyn Backend, canister_id: Principal) -> Self {
+        Self::new(canister_id, backend)
+    }
+
+    /// Store a small asset with a single call.
+    /// The content has to fit in a message, use [`Canister::upload`] for larger assets.
+    pub async fn store(&self, key: &str, content_type: &str, content: &[u8]) -> Result<()> {
+        let arg = StoreArg {
+            key,
+            content_type,
+            content_encoding: "identity",
+            content: serde_bytes::Bytes::new(content),
+            sha256: Some(serde_bytes::ByteBuf::from(sha256(content).to_vec())),
+        };
+        self.call_update("store", Encode!(&arg)?).await?;
+        Ok(())
+    }
+
+    /// Upload the assets in one batch, with the content in chunks of [`CHUNK_SIZE`].
+    /// The assets are only served once all of them are uploaded.
+    pub async fn upload(&self, assets: &[Asset]) -> Result<()> {
+        let data = self
+            .call_update("create_batch", Encode!(&CreateBatchRequest {})?)
+            .await?;
+        let batch_id = Decode!(&data, CreateBatchResponse)?.batch_id;
+
+        let mut operations = Vec::with_capacity(assets.len() * 2);
+        for asset in assets {
+            let mut chunk_ids = Vec::new();
+            for chunk in asset.content.chunks(CHUNK_SIZE) {
+                let request = CreateChunkRequest {
+                    batch_id: batch_id.clone(),
+                    content: serde_bytes::Bytes::new(chunk),
+                };
+                let data = self.call_update("create_chunk", Encode!(&request)?).await?;
+                chunk_ids.push(Decode!(&data, CreateChunkResponse)?.chunk_id);
+            }
+
+            operations.push(BatchOperationKind::CreateAsset(CreateAssetArguments {
+                key: asset.key.clone(),
+                content_type: asset.content_type.clone(),
+            }));
+            operations.push(BatchOperationKind::SetAssetContent(
+                SetAssetContentArguments {
+                    key: asset.key.clone(),
+                    content_encoding: "identity".into(),
+                    chunk_ids,
+                    sha256: Some(serde_bytes::ByteBuf::from(sha256(&asset.content).to_vec())),
+                },
+            ));
+        }
+
+        let commit = CommitBatchArguments {
+            batch_id,
+            operations,
+        };
+        self.call_update("commit_batch", Encode!(&commit)?).await?;
+        Ok(())
+    }
+
+    /// Upload all the files in `dir` and its subdirectories, each under its path
+    /// relative to `dir`, like `/css/main.css`. The content types are guessed from
+    /// the file extensions. Returns the keys of the uploaded assets.
+    pub async fn upload_dir(&self, dir: impl AsRef<Path>) -> Result<Vec<String>> {
+        let mut assets = Vec::new();
+        collect_assets(dir.as_ref(), "", &mut assets)?;
+        assets.sort_by(|a, b| a.key.cmp(&b.key));
+        self.upload(&assets).await?;
+        Ok(assets.into_iter().map(|asset| asset.key).collect())
+    }
+
+    /// The assets stored in the canister
+    pub async fn list(&self) -> Result<Vec<AssetDetails>> {
+        let data = self.call_query("list", Encode!(&ListRequest {})?).await?;
+        Ok(Decode!(&data, Vec<AssetDetails>)?)
+    }
+
+    /// Delete the asset `key`
+    pub async fn delete_asset(&self, key: &str) -> Result<()> {
+        self.call_update("delete_asset", Encode!(&DeleteAssetArguments { key })?)
+            .await?;
+        Ok(())
+    }
+}
+
+fn collect_assets(dir: &Path, prefix: &str, assets: &mut Vec<Asset>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            Error::Generic(format!(
+                "File name is not utf-8: {}",
+                name.to_string_lossy()
+            ))
+        })?;
+        let key = format!("{}/{}", prefix, name);
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_assets(&path, &key, assets)?;
+        } else {
+            assets.push(Asset {
+                content_type: content_type(&path).into(),
+                content: std::fs::read(&path)?,
+                key,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The content type of a file, by its extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "wasm" => "application/wasm",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
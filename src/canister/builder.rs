@@ -0,0 +1,123 @@
+//! Create and install a canister in one go.
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+use candid::utils::ArgumentEncoder;
+use candid::{encode_args, Principal};
+
+use super::{Canister, InstallMode};
+use crate::backend::Backend;
+use crate::context::DEFAULT_ACCOUNT;
+use crate::harness::DEFAULT_CYCLES;
+use crate::{Error, Result};
+
+enum WasmSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// Builds a canister: creates it through the wallet of an account, with its settings,
+/// and installs the wasm module with the init argument.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, alice: candid::Principal) {
+/// use ic_test_utils::canister::{Canister, CanisterBuilder};
+///
+/// struct Counter;
+///
+/// let counter: Canister<'_, Counter> = CanisterBuilder::new(agent)
+///     .with_account("alice")
+///     .with_cycles(2_000_000_000_000)
+///     .with_controllers(vec![alice])
+///     .with_wasm("counter.wasm")
+///     .with_init_arg((10u64,))
+///     .deploy()
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct CanisterBuilder<'agent, T = ()> {
+    backend: &'agent dyn Backend,
+    account_name: String,
+    cycles: u64,
+    controllers: Option<Vec<Principal>>,
+    wasm: Option<WasmSource>,
+    init_arg: Result<Vec<u8>>,
+    _phantom_data: PhantomData<T>,
+}
+
+impl<'agent, T> CanisterBuilder<'agent, T> {
+    /// A canister created through the wallet of the [`DEFAULT_ACCOUNT`], with
+    /// [`DEFAULT_CYCLES`], the wallet as controller and no init argument
+    pub fn new(backend: &'agent dyn Backend) -> Self {
+        Self {
+            backend,
+            account_name: DEFAULT_ACCOUNT.into(),
+            cycles: DEFAULT_CYCLES,
+            controllers: None,
+            wasm: None,
+            init_arg: encode_args(()).map_err(Error::from),
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Create the canister through the wallet of `account_name`
+    pub fn with_account(mut self, account_name: impl Into<String>) -> Self {
+        self.account_name = account_name.into();
+        self
+    }
+
+    /// The cycles the canister is created with
+    pub fn with_cycles(mut self, cycles: u64) -> Self {
+        self.cycles = cycles;
+        self
+    }
+
+    /// The controllers of the canister, instead of the wallet.
+    /// They are set after the module is installed.
+    pub fn with_controllers(mut self, controllers: Vec<Principal>) -> Self {
+        self.controllers = Some(controllers);
+        self
+    }
+
+    /// Install the wasm module at `path`, raw or gzip compressed
+    pub fn with_wasm(mut self, path: impl Into<PathBuf>) -> Self {
+        self.wasm = Some(WasmSource::Path(path.into()));
+        self
+    }
+
+    /// Install the wasm module `bytecode`, raw or gzip compressed
+    pub fn with_wasm_bytes(mut self, bytecode: Vec<u8>) -> Self {
+        self.wasm = Some(WasmSource::Bytes(bytecode));
+        self
+    }
+
+    /// The init argument
+    pub fn with_init_arg<A: ArgumentEncoder>(mut self, arg: A) -> Self {
+        self.init_arg = encode_args(arg).map_err(Error::from);
+        self
+    }
+
+    /// Create the canister, install the module and set the controllers.
+    /// The canister isn't deleted if the install fails.
+    pub async fn deploy(self) -> Result<Canister<'agent, T>> {
+        let init_arg = self.init_arg?;
+        let bytecode = match self.wasm {
+            Some(WasmSource::Path(path)) => std::fs::read(path)?,
+            Some(WasmSource::Bytes(bytecode)) => bytecode,
+            None => return Err(Error::Generic("No wasm module to deploy".into())),
+        };
+
+        let wallet = Canister::new_wallet(self.backend, &self.account_name, None)?;
+        // The agent installs the module, so the controllers are only set once it is done
+        let canister_id = wallet.create_canister(self.cycles, None).await?;
+        let management = Canister::new_management(self.backend);
+        management
+            .install_raw(canister_id, &bytecode, InstallMode::Install, &init_arg)
+            .await?;
+        if let Some(controllers) = self.controllers {
+            management.set_controllers(canister_id, controllers).await?;
+        }
+        Ok(Canister::new(canister_id, self.backend))
+    }
+}
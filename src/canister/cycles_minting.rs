@@ -0,0 +1,85 @@
+//! Functions specific to the cycles minting canister (CMC).
+use candid::{CandidType, Deserialize};
+use ic_agent::ic_types::Principal;
+
+use super::{AccountIdentifier, Canister};
+use crate::backend::Backend;
+use crate::Result;
+
+/// The id of the cycles minting canister on the NNS subnet.
+pub const CYCLES_MINTING_CANISTER_ID: &str = "rkp4c-7iaaa-aaaaa-aaaca-cai";
+
+/// Init arguments of the cycles minting canister.
+///
+/// The canister is installed with an `opt` of this payload.
+#[derive(Debug, Clone, Default, CandidType, Deserialize)]
+pub struct CyclesMintingInitPayload {
+    /// The ICP ledger the CMC mints and burns with
+    pub ledger_canister_id: Option<Principal>,
+    /// The governance canister allowed to update the CMC
+    pub governance_canister_id: Option<Principal>,
+    /// Hex encoded account identifier of the ledger minting account
+    pub minting_account_id: Option<String>,
+    /// Last notification to be considered purged
+    pub last_purged_notification: Option<u64>,
+    /// The exchange rate canister the CMC reads the ICP/XDR rate from
+    pub exchange_rate_canister: Option<ExchangeRateCanister>,
+    /// The cycles ledger the CMC deposits cycles on
+    pub cycles_ledger_canister_id: Option<Principal>,
+}
+
+impl CyclesMintingInitPayload {
+    /// The CMC of `ledger_canister_id`, minting with `minting_account`
+    pub fn new(ledger_canister_id: Principal, minting_account: &AccountIdentifier) -> Self {
+        Self {
+            ledger_canister_id: Some(ledger_canister_id),
+            minting_account_id: Some(minting_account.to_hex()),
+            ..Default::default()
+        }
+    }
+
+    /// The governance canister allowed to update the CMC
+    pub fn with_governance(mut self, governance_canister_id: Principal) -> Self {
+        self.governance_canister_id = Some(governance_canister_id);
+        self
+    }
+
+    /// Read the ICP/XDR rate from the exchange rate canister `canister_id`
+    pub fn with_exchange_rate_canister(mut self, canister_id: Principal) -> Self {
+        self.exchange_rate_canister = Some(ExchangeRateCanister::Set(canister_id));
+        self
+    }
+
+    /// The argument to install the CMC with
+    pub fn into_payload(self) -> Option<Self> {
+        Some(self)
+    }
+}
+
+/// Whether the CMC reads the ICP/XDR rate from an exchange rate canister
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub enum ExchangeRateCanister {
+    /// Read the rate from this canister
+    Set(Principal),
+    /// Don't read the rate
+    Unset,
+}
+
+/// Cycles minting canister
+pub struct CyclesMinting;
+
+impl<'agent> Canister<'agent, CyclesMinting> {
+    /// Create a new cycles minting canister.
+    /// If the `canister_id` is `None` then the default [`CYCLES_MINTING_CANISTER_ID`]
+    /// will be used.
+    pub fn new_cycles_minting(
+        backend: &'agent dyn Backend,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<Self> {
+        let id = match canister_id.into() {
+            Some(id) => id,
+            None => Principal::from_text(CYCLES_MINTING_CANISTER_ID)?,
+        };
+        Ok(Self::new(id, backend))
+    }
+}
@@ -0,0 +1,187 @@
+//! Init arguments of the ICRC-1 ledger of the ICP ledger suite.
+//!
+//! ```
+//! # fn run(minter: candid::Principal, alice: candid::Principal) {
+//! use candid::Encode;
+//! use ic_test_utils::canister::{Icrc1Account, Icrc1InitArgs};
+//!
+//! let init = Icrc1InitArgs::new(Icrc1Account::new(minter), minter, "TKN", "Token")
+//!     .with_initial_balance(Icrc1Account::new(alice), 100_000_000)
+//!     .with_icrc2();
+//! let arg = Encode!(&init.into_payload()).unwrap();
+//! # }
+//! ```
+use candid::{CandidType, Deserialize, Int, Nat};
+use ic_agent::ic_types::Principal;
+
+/// The fee of a transfer, unless set with [`Icrc1InitArgs::with_transfer_fee`]
+pub const DEFAULT_TRANSFER_FEE: u64 = 10_000;
+
+/// An ICRC-1 account, an owner and an optional 32 byte subaccount
+#[derive(Debug, Clone, PartialEq, Eq, CandidType, Deserialize)]
+pub struct Icrc1Account {
+    /// The owner of the account
+    pub owner: Principal,
+    /// The subaccount, the default subaccount if `None`
+    pub subaccount: Option<serde_bytes::ByteBuf>,
+}
+
+impl Icrc1Account {
+    /// The default subaccount of `owner`
+    pub fn new(owner: Principal) -> Self {
+        Self {
+            owner,
+            subaccount: None,
+        }
+    }
+
+    /// The `subaccount` of `owner`
+    pub fn with_subaccount(owner: Principal, subaccount: [u8; 32]) -> Self {
+        Self {
+            owner,
+            subaccount: Some(serde_bytes::ByteBuf::from(subaccount.to_vec())),
+        }
+    }
+}
+
+/// A value of the ledger metadata
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub enum MetadataValue {
+    /// A natural number
+    Nat(Nat),
+    /// An integer
+    Int(Int),
+    /// A text
+    Text(String),
+    /// A blob
+    Blob(serde_bytes::ByteBuf),
+}
+
+/// The optional features of the ledger
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct FeatureFlags {
+    /// Whether approvals and `icrc2_transfer_from` are supported
+    pub icrc2: bool,
+}
+
+/// When and how the ledger archives its blocks
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct ArchiveOptions {
+    /// The blocks moved to an archive at once
+    pub num_blocks_to_archive: u64,
+    /// The number of blocks that triggers archiving
+    pub trigger_threshold: u64,
+    /// The controller of the archive canisters
+    pub controller_id: Principal,
+    /// Largest number of transactions returned by the archive in one response
+    pub max_transactions_per_response: Option<u64>,
+    /// Largest message the ledger sends to the archive
+    pub max_message_size_bytes: Option<u64>,
+    /// The cycles an archive canister is created with
+    pub cycles_for_archive_creation: Option<u64>,
+    /// Memory limit of an archive canister
+    pub node_max_memory_size_bytes: Option<u64>,
+}
+
+/// Init arguments of the ICRC-1 ledger
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct Icrc1InitArgs {
+    /// The account tokens are minted from and burned to
+    pub minting_account: Icrc1Account,
+    /// The account collecting the fees, the fees are burned if `None`
+    pub fee_collector_account: Option<Icrc1Account>,
+    /// Fee of a transfer
+    pub transfer_fee: Nat,
+    /// The decimals of the token, 8 if `None`
+    pub decimals: Option<u8>,
+    /// Longest memo a transfer can carry
+    pub max_memo_length: Option<u16>,
+    /// Token symbol
+    pub token_symbol: String,
+    /// Token name
+    pub token_name: String,
+    /// Metadata returned by `icrc1_metadata`
+    pub metadata: Vec<(String, MetadataValue)>,
+    /// Initial balances
+    pub initial_balances: Vec<(Icrc1Account, Nat)>,
+    /// The optional features
+    pub feature_flags: Option<FeatureFlags>,
+    /// Archiving of the blocks
+    pub archive_options: ArchiveOptions,
+}
+
+impl Icrc1InitArgs {
+    /// Create init arguments for the token `symbol` named `name`, minting with
+    /// `minting_account`, with no initial balances and the [`DEFAULT_TRANSFER_FEE`].
+    /// The archives are controlled by `archive_controller`.
+    pub fn new(
+        minting_account: Icrc1Account,
+        archive_controller: Principal,
+        symbol: impl Into<String>,
+        name: impl Into<String>,
+    ) -> Self {
+        Self {
+            minting_account,
+            fee_collector_account: None,
+            transfer_fee: Nat::from(DEFAULT_TRANSFER_FEE),
+            decimals: None,
+            max_memo_length: None,
+            token_symbol: symbol.into(),
+            token_name: name.into(),
+            metadata: Vec::new(),
+            initial_balances: Vec::new(),
+            feature_flags: None,
+            archive_options: ArchiveOptions {
+                num_blocks_to_archive: 1000,
+                trigger_threshold: 2000,
+                controller_id: archive_controller,
+                max_transactions_per_response: None,
+                max_message_size_bytes: None,
+                cycles_for_archive_creation: None,
+                node_max_memory_size_bytes: None,
+            },
+        }
+    }
+
+    /// Give `account` an initial balance of `amount`
+    pub fn with_initial_balance(mut self, account: Icrc1Account, amount: u64) -> Self {
+        self.initial_balances.push((account, Nat::from(amount)));
+        self
+    }
+
+    /// The fee of a transfer
+    pub fn with_transfer_fee(mut self, fee: u64) -> Self {
+        self.transfer_fee = Nat::from(fee);
+        self
+    }
+
+    /// The decimals of the token
+    pub fn with_decimals(mut self, decimals: u8) -> Self {
+        self.decimals = Some(decimals);
+        self
+    }
+
+    /// Collect the fees on `account` instead of burning them
+    pub fn with_fee_collector(mut self, account: Icrc1Account) -> Self {
+        self.fee_collector_account = Some(account);
+        self
+    }
+
+    /// Support approvals and `icrc2_transfer_from`
+    pub fn with_icrc2(mut self) -> Self {
+        self.feature_flags = Some(FeatureFlags { icrc2: true });
+        self
+    }
+
+    /// The argument to install the ledger with
+    pub fn into_payload(self) -> Icrc1LedgerArg {
+        Icrc1LedgerArg::Init(self)
+    }
+}
+
+/// The argument to install the ICRC-1 ledger with.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub enum Icrc1LedgerArg {
+    /// Init arguments
+    Init(Icrc1InitArgs),
+}
@@ -0,0 +1,100 @@
+//! Describe a canister type once, deploy it anywhere.
+use std::path::PathBuf;
+
+use candid::Encode;
+
+use super::{Canister, InstallMode};
+use crate::context::TestContext;
+use crate::harness::DEFAULT_CYCLES;
+use crate::Result;
+
+/// A canister type tag with what it takes to deploy the canister.
+///
+/// Implement it for the tag of a canister, and [`Canister::deploy`] creates and installs
+/// it through a [`TestContext`], which registers it by [`CanisterKind::name`] and deletes
+/// it in [`TestContext::teardown`].
+///
+/// ```
+/// # async fn run() {
+/// use std::path::PathBuf;
+///
+/// use candid::Encode;
+/// use ic_test_utils::canister::{Canister, CanisterKind};
+/// use ic_test_utils::context::TestContext;
+///
+/// struct Counter;
+///
+/// impl CanisterKind for Counter {
+///     fn name() -> &'static str {
+///         "counter"
+///     }
+///
+///     fn wasm_path() -> PathBuf {
+///         "target/wasm32-unknown-unknown/release/counter.wasm".into()
+///     }
+///
+///     fn init_arg() -> ic_test_utils::Result<Vec<u8>> {
+///         Ok(Encode!(&10u64)?)
+///     }
+/// }
+///
+/// let context = TestContext::get().await.unwrap();
+/// let counter = Canister::<Counter>::deploy(context).await.unwrap();
+/// assert_eq!(context.registry().resolve("counter").unwrap(), *counter.principal());
+/// context.teardown().await;
+/// # }
+/// ```
+pub trait CanisterKind {
+    /// The name the canister is registered under
+    fn name() -> &'static str;
+
+    /// The path of the wasm module, raw or gzip compressed
+    fn wasm_path() -> PathBuf;
+
+    /// The path of the candid interface, if there is one
+    fn candid_path() -> Option<PathBuf> {
+        None
+    }
+
+    /// The candid encoded init argument, none by default
+    fn init_arg() -> Result<Vec<u8>> {
+        Ok(Encode!()?)
+    }
+
+    /// The cycles the canister is created with
+    fn cycles() -> u64 {
+        DEFAULT_CYCLES
+    }
+
+    /// The candid interface read from [`CanisterKind::candid_path`]
+    fn candid() -> Result<Option<String>> {
+        match Self::candid_path() {
+            Some(path) => Ok(Some(std::fs::read_to_string(path)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'agent, T: CanisterKind> Canister<'agent, T> {
+    /// Create the canister through the wallet of `context` and install it with
+    /// its default init argument.
+    /// When the context reuses canisters, a canister kept by an earlier test is
+    /// reinstalled instead, if there is one.
+    pub async fn deploy(context: &'agent TestContext) -> Result<Self> {
+        let bytecode = std::fs::read(T::wasm_path())?;
+        let init_arg = T::init_arg()?;
+        let (canister_id, mode) = match context.take_reusable() {
+            Some(canister_id) => (canister_id, InstallMode::Reinstall),
+            None => (
+                context.wallet()?.create_canister(T::cycles(), None).await?,
+                InstallMode::Install,
+            ),
+        };
+        context.track(T::name(), canister_id);
+        context
+            .management()
+            .install_raw(canister_id, &bytecode, mode, &init_arg)
+            .await?;
+        Ok(Self::new(canister_id, context.backend()))
+    }
+}
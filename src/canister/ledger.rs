@@ -0,0 +1,172 @@
+//! Functions specific to the ICP ledger.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, principal: candid::Principal) {
+//! use ic_test_utils::canister::{AccountIdentifier, Canister};
+//!
+//! let ledger = Canister::new_ledger(agent, None).unwrap();
+//! let account = AccountIdentifier::new(&principal, None);
+//! let balance = ledger.account_balance(&account).await.unwrap();
+//! # }
+//! ```
+use std::fmt;
+
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_agent::ic_types::Principal;
+use sha2::{Digest, Sha224};
+
+use super::Canister;
+use crate::backend::Backend;
+use crate::Result;
+
+/// The id of the ICP ledger canister on the NNS subnet.
+pub const LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+/// A 32 byte subaccount.
+pub type Subaccount = [u8; 32];
+
+/// An account identifier on the ICP ledger.
+///
+/// This is the CRC32 checksum followed by the SHA-224 hash of the
+/// owner and the subaccount.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct AccountIdentifier([u8; 32]);
+
+impl AccountIdentifier {
+    /// Create the account identifier of the `owner` and `subaccount`.
+    /// If the `subaccount` is `None` the default (all zero) subaccount is used.
+    pub fn new(owner: &Principal, subaccount: Option<Subaccount>) -> Self {
+        let mut hasher = Sha224::new();
+        hasher.update(b"\x0Aaccount-id");
+        hasher.update(owner.as_slice());
+        hasher.update(subaccount.unwrap_or([0; 32]));
+        let hash = hasher.finalize();
+
+        let mut bytes = [0; 32];
+        bytes[..4].copy_from_slice(&crc32fast::hash(&hash).to_be_bytes());
+        bytes[4..].copy_from_slice(&hash);
+        Self(bytes)
+    }
+
+    /// The raw bytes of the account identifier
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The account identifier as a hex string,
+    /// as expected by the ledger init arguments.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl fmt::Display for AccountIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// An amount of ICP.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, CandidType, Deserialize)]
+pub struct Tokens {
+    /// Amount in e8s
+    pub e8s: u64,
+}
+
+/// Init arguments of the ICP ledger.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct LedgerInitArgs {
+    /// Hex encoded account identifier of the minting account
+    pub minting_account: String,
+    /// Initial balances as hex encoded account identifiers and amounts
+    pub initial_values: Vec<(String, Tokens)>,
+    /// Principals allowed to send transactions while the ledger is restricted
+    pub send_whitelist: Vec<Principal>,
+    /// Fee of a transfer
+    pub transfer_fee: Option<Tokens>,
+    /// Token symbol
+    pub token_symbol: Option<String>,
+    /// Token name
+    pub token_name: Option<String>,
+}
+
+impl LedgerInitArgs {
+    /// Create init arguments with the given minting account,
+    /// no initial balances and default settings.
+    pub fn new(minting_account: &AccountIdentifier) -> Self {
+        Self {
+            minting_account: minting_account.to_hex(),
+            initial_values: Vec::new(),
+            send_whitelist: Vec::new(),
+            transfer_fee: None,
+            token_symbol: None,
+            token_name: None,
+        }
+    }
+
+    /// Give `account` an initial balance of `amount`
+    pub fn with_initial_balance(mut self, account: &AccountIdentifier, amount: Tokens) -> Self {
+        self.initial_values.push((account.to_hex(), amount));
+        self
+    }
+
+    /// The fee of a transfer, instead of the ledger default
+    pub fn with_transfer_fee(mut self, fee: Tokens) -> Self {
+        self.transfer_fee = Some(fee);
+        self
+    }
+
+    /// The symbol and name of the token
+    pub fn with_token(mut self, symbol: impl Into<String>, name: impl Into<String>) -> Self {
+        self.token_symbol = Some(symbol.into());
+        self.token_name = Some(name.into());
+        self
+    }
+
+    /// The argument to install the ledger with
+    pub fn into_payload(self) -> LedgerCanisterPayload {
+        LedgerCanisterPayload::Init(self)
+    }
+}
+
+/// The argument to install the ICP ledger with.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub enum LedgerCanisterPayload {
+    /// Init arguments
+    Init(LedgerInitArgs),
+}
+
+#[derive(CandidType, Deserialize)]
+struct AccountBalanceArgs {
+    #[serde(with = "serde_bytes")]
+    account: Vec<u8>,
+}
+
+/// ICP ledger
+pub struct Ledger;
+
+impl<'agent> Canister<'agent, Ledger> {
+    /// Create a new ledger canister.
+    /// If the `canister_id` is `None` then the default [`LEDGER_CANISTER_ID`]
+    /// will be used.
+    pub fn new_ledger(
+        backend: &'agent dyn Backend,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<Self> {
+        let id = match canister_id.into() {
+            Some(id) => id,
+            None => Principal::from_text(LEDGER_CANISTER_ID)?,
+        };
+        Ok(Self::new(id, backend))
+    }
+
+    /// Get the balance of an account
+    pub async fn account_balance(&self, account: &AccountIdentifier) -> Result<Tokens> {
+        let args = AccountBalanceArgs {
+            account: account.as_bytes().to_vec(),
+        };
+        let data = self.call_query("account_balance", Encode!(&args)?).await?;
+        let tokens = Decode!(&data, Tokens)?;
+        Ok(tokens)
+    }
+}
@@ -0,0 +1,36 @@
+//! Functions specific to the ICP ledger index canister.
+use candid::{CandidType, Deserialize};
+use ic_agent::ic_types::Principal;
+
+use super::Canister;
+use crate::backend::Backend;
+use crate::Result;
+
+/// The id of the ICP index canister on the NNS subnet.
+pub const LEDGER_INDEX_CANISTER_ID: &str = "qhbym-qaaaa-aaaaa-aaafq-cai";
+
+/// Init arguments of the ICP index canister.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct IndexInitArgs {
+    /// The ledger to index
+    pub ledger_id: Principal,
+}
+
+/// ICP ledger index
+pub struct LedgerIndex;
+
+impl<'agent> Canister<'agent, LedgerIndex> {
+    /// Create a new index canister.
+    /// If the `canister_id` is `None` then the default [`LEDGER_INDEX_CANISTER_ID`]
+    /// will be used.
+    pub fn new_ledger_index(
+        backend: &'agent dyn Backend,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<Self> {
+        let id = match canister_id.into() {
+            Some(id) => id,
+            None => Principal::from_text(LEDGER_INDEX_CANISTER_ID)?,
+        };
+        Ok(Self::new(id, backend))
+    }
+}
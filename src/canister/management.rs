@@ -1,9 +1,19 @@
+use std::future::Future;
+
 use candid::{
-    encode_args, utils::ArgumentEncoder, CandidType, Deserialize, Encode, Principal,
+    encode_args, utils::ArgumentEncoder, CandidType, Decode, Deserialize, Encode, Nat, Principal,
 };
 
-use super::{Agent, Canister};
-use crate::{get_waiter, Result};
+use ic_agent::AgentError;
+
+#[cfg(feature = "wallet")]
+use super::WalletCanister;
+use super::{with_context, Canister};
+use crate::backend::Backend;
+use crate::interface::check_upgrade_compatible;
+use crate::snapshot::with_rollback;
+use crate::wasm::{embedded_candid, prepare_upload, sha256};
+use crate::{Error, Result};
 
 /// The install mode of the canister to install. If a canister is already installed,
 /// using [InstallMode::Install] will be an error. [InstallMode::Reinstall] overwrites
@@ -21,24 +31,173 @@ pub enum InstallMode {
     Upgrade,
 }
 
-/// Installation arguments for [`Canister::install_code`].
+/// What [`Canister::deploy_if_changed`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeployAction {
+    /// The module was installed on a new or empty canister
+    Installed,
+    /// The canister ran another module and was upgraded
+    Upgraded,
+    /// The canister already ran the module, nothing was installed
+    Unchanged,
+}
+
+/// Installation arguments for [`Canister::install_code`],
+/// borrowing the module and argument, which can be megabytes.
+#[derive(CandidType)]
+struct CanisterInstall<'a> {
+    mode: InstallMode,
+    canister_id: Principal,
+    wasm_module: &'a serde_bytes::Bytes,
+    arg: &'a serde_bytes::Bytes,
+}
+
 #[derive(CandidType, Deserialize)]
-pub struct CanisterInstall {
-    /// [`InstallMode`]
-    pub mode: InstallMode,
-    /// Canister id
-    pub canister_id: Principal,
+struct In {
+    canister_id: Principal,
+}
+
+/// A snapshot of a canister, see [`Canister::take_canister_snapshot`].
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct Snapshot {
+    /// Snapshot id
     #[serde(with = "serde_bytes")]
-    /// Wasm module as raw bytes
-    pub wasm_module: Vec<u8>,
+    pub id: Vec<u8>,
+    /// When the snapshot was taken, in nanoseconds since the epoch
+    pub taken_at_timestamp: u64,
+    /// Size of the snapshot in bytes
+    pub total_size: u64,
+}
+
+/// Whether a canister is running, see [`Canister::canister_status`]
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, Eq, PartialEq)]
+pub enum CanisterRunStatus {
+    /// The canister is running
+    #[serde(rename = "running")]
+    Running,
+    /// The canister is stopping, waiting for outstanding calls
+    #[serde(rename = "stopping")]
+    Stopping,
+    /// The canister is stopped
+    #[serde(rename = "stopped")]
+    Stopped,
+}
+
+/// The settings of a canister
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct CanisterSettings {
+    /// Controllers of the canister
+    pub controllers: Vec<Principal>,
+    /// Compute allocation in percent
+    pub compute_allocation: Nat,
+    /// Memory allocation in bytes
+    pub memory_allocation: Nat,
+    /// Freezing threshold in seconds
+    pub freezing_threshold: Nat,
+}
+
+/// The status of a canister, see [`Canister::canister_status`]
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct CanisterStatus {
+    /// Running, stopping or stopped
+    pub status: CanisterRunStatus,
+    /// Settings of the canister
+    pub settings: CanisterSettings,
+    /// SHA-256 of the installed module, if any
+    pub module_hash: Option<serde_bytes::ByteBuf>,
+    /// Memory used by the canister in bytes
+    pub memory_size: Nat,
+    /// Cycle balance of the canister
+    pub cycles: Nat,
+    /// Memory used by the canister by kind, reported by newer replicas
+    pub memory_metrics: Option<MemoryMetrics>,
+}
+
+/// The memory used by a canister by kind, in bytes, see [`CanisterStatus`]
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct MemoryMetrics {
+    /// The wasm heap
+    pub wasm_memory_size: Nat,
+    /// The stable memory
+    pub stable_memory_size: Nat,
+    /// The globals
+    pub global_memory_size: Nat,
+    /// The wasm module
+    pub wasm_binary_size: Nat,
+    /// The custom sections of the wasm module
+    pub custom_sections_size: Nat,
+    /// The canister history
+    pub canister_history_size: Nat,
+    /// The snapshots of the canister
+    pub snapshots_size: Nat,
+}
+
+impl CanisterStatus {
+    /// The total memory used by the canister in bytes
+    pub fn total_memory_size(&self) -> u64 {
+        nat_to_u64(&self.memory_size)
+    }
+
+    /// The size of the wasm heap in bytes, if the replica reports it
+    pub fn heap_memory_size(&self) -> Option<u64> {
+        self.memory_metrics
+            .as_ref()
+            .map(|metrics| nat_to_u64(&metrics.wasm_memory_size))
+    }
+
+    /// The size of the stable memory in bytes, if the replica reports it
+    pub fn stable_memory_size(&self) -> Option<u64> {
+        self.memory_metrics
+            .as_ref()
+            .map(|metrics| nat_to_u64(&metrics.stable_memory_size))
+    }
+}
+
+/// Memory sizes always fit, saturate instead of failing
+fn nat_to_u64(nat: &Nat) -> u64 {
+    u64::try_from(&nat.0).unwrap_or(u64::MAX)
+}
+
+/// A log line of a canister, see [`Canister::fetch_canister_logs`]
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct CanisterLogRecord {
+    /// Index of the record, increasing over the lifetime of the canister
+    pub idx: u64,
+    /// When the record was logged, in nanoseconds since the epoch
+    pub timestamp_nanos: u64,
+    /// The logged message
     #[serde(with = "serde_bytes")]
-    /// Any aditional arguments to be passed along
-    pub arg: Vec<u8>,
+    pub content: Vec<u8>,
 }
 
 #[derive(CandidType, Deserialize)]
-struct In {
+struct FetchLogsResult {
+    canister_log_records: Vec<CanisterLogRecord>,
+}
+
+#[derive(CandidType)]
+struct UpdateSettingsArgs {
+    canister_id: Principal,
+    settings: ControllersUpdate,
+}
+
+/// The part of the settings the update changes, the others are left as they are
+#[derive(CandidType)]
+struct ControllersUpdate {
+    controllers: Option<Vec<Principal>>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct TakeSnapshotArgs {
     canister_id: Principal,
+    replace_snapshot: Option<serde_bytes::ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct SnapshotArgs {
+    canister_id: Principal,
+    #[serde(with = "serde_bytes")]
+    snapshot_id: Vec<u8>,
 }
 
 // -----------------------------------------------------------------------------
@@ -51,40 +210,81 @@ struct In {
 /// ```
 /// # use ic_agent::Agent;
 /// use ic_test_utils::canister::Canister;
-/// # async fn run(agent: &Agent, principal: ic_cdk::export::candid::Principal) {
+/// # async fn run(agent: &Agent, principal: candid::Principal) {
 /// let management = Canister::new_management(agent);
-/// management.stop_canister(&agent, principal).await;
+/// management.stop_canister(principal).await;
 /// # }
 /// ```
 pub struct Management;
 
 impl<'agent> Canister<'agent, Management> {
     /// Create a new management canister
-    pub fn new_management(agent: &'agent Agent) -> Self {
+    pub fn new_management(backend: &'agent dyn Backend) -> Self {
         let id = Principal::management_canister();
-        Self::new(id, agent)
+        Self::new(id, backend)
     }
 
-    async fn _install_code<'wallet_agent, T: ArgumentEncoder>(
+    /// Call a method of the management canister concerning the canister `canister_id`
+    async fn call_management(
         &self,
-        agent: &Agent,
+        method_name: &str,
         canister_id: Principal,
-        bytecode: Vec<u8>,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let arg_len = arg.len();
+        let result = self
+            .backend
+            .update_call(*self.principal(), Some(canister_id), method_name, arg)
+            .await;
+        with_context(result, canister_id, method_name, arg_len)
+    }
+
+    /// Make a query to the management canister concerning the canister `canister_id`
+    async fn query_management(
+        &self,
+        method_name: &str,
+        canister_id: Principal,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let arg_len = arg.len();
+        let result = self
+            .backend
+            .query_call(*self.principal(), Some(canister_id), method_name, arg)
+            .await;
+        with_context(result, canister_id, method_name, arg_len)
+    }
+
+    async fn _install_code<T: ArgumentEncoder>(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
         mode: InstallMode,
         arg: T,
     ) -> Result<()> {
+        self.install_raw(canister_id, bytecode.as_ref(), mode, &encode_args(arg)?)
+            .await
+    }
+
+    /// Install code with an already encoded argument, like one encoded from text with
+    /// [`encode_text`](crate::interface::encode_text).
+    /// Neither the module nor the argument are copied before they're encoded.
+    pub async fn install_raw(
+        &self,
+        canister_id: Principal,
+        bytecode: &[u8],
+        mode: InstallMode,
+        arg: &[u8],
+    ) -> Result<()> {
+        let wasm_module = prepare_upload(bytecode)?;
         let install_args = CanisterInstall {
             mode,
             canister_id,
-            wasm_module: bytecode,
-            arg: encode_args(arg)?,
+            wasm_module: serde_bytes::Bytes::new(&wasm_module),
+            arg: serde_bytes::Bytes::new(arg),
         };
 
         let args = Encode!(&install_args)?;
-        agent
-            .update(&Principal::management_canister(), "install_code")
-            .with_arg(args)
-            .call_and_wait(get_waiter())
+        self.call_management("install_code", canister_id, args)
             .await?;
 
         Ok(())
@@ -92,54 +292,437 @@ impl<'agent> Canister<'agent, Management> {
 
     /// Install code in an existing canister.
     /// To create a canister first use [`Canister::create_canister`]
+    ///
+    /// The `bytecode` can be a raw or gzip compressed module. Large raw modules
+    /// are compressed before the upload, see [`crate::wasm::MAX_RAW_WASM_SIZE`].
+    /// It is borrowed, so a cached module can be installed without cloning it.
     pub async fn install_code<T: ArgumentEncoder>(
         &self,
-        agent: &Agent,
         canister_id: Principal,
-        bytecode: Vec<u8>,
+        bytecode: impl AsRef<[u8]>,
         arg: T,
     ) -> Result<()> {
-        self._install_code(agent, canister_id, bytecode, InstallMode::Install, arg)
+        self._install_code(canister_id, bytecode, InstallMode::Install, arg)
             .await
     }
 
+    /// Install code in an existing canister, without an init argument
+    pub async fn install_code_no_arg(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        self.install_code(canister_id, bytecode, ()).await
+    }
+
+    /// Create a canister with `cycles` through `wallet` and install `bytecode` in it,
+    /// returning the principal of the canister.
+    /// If `canister_id` is given, the canister exists already and is only installed,
+    /// so a test can deploy to the same canister on every run.
+    #[cfg(feature = "wallet")]
+    pub async fn deploy<T: ArgumentEncoder>(
+        &self,
+        wallet: &WalletCanister<'_>,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+        cycles: u64,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<Principal> {
+        let canister_id = match canister_id.into() {
+            Some(canister_id) => canister_id,
+            None => wallet.create_canister(cycles, None).await?,
+        };
+        self.install_code(canister_id, bytecode, arg).await?;
+        Ok(canister_id)
+    }
+
+    /// Like [`Canister::deploy`], but an existing canister `canister_id` is only upgraded
+    /// if it runs another module, and installed if it is empty. A canister already running
+    /// `bytecode` is left as it is, which makes rerunning tests against a persistent
+    /// replica fast.
+    ///
+    /// ```
+    /// # async fn run(management: ic_test_utils::ManagementCanister<'_>, wallet: ic_test_utils::WalletCanister<'_>, counter: candid::Principal, wasm: Vec<u8>) {
+    /// use ic_test_utils::canister::DeployAction;
+    ///
+    /// let (_, action) = management
+    ///     .deploy_if_changed(&wallet, &wasm, (), 1_000_000_000_000, counter)
+    ///     .await
+    ///     .unwrap();
+    /// assert_eq!(action, DeployAction::Unchanged);
+    /// # }
+    /// ```
+    #[cfg(feature = "wallet")]
+    pub async fn deploy_if_changed<T: ArgumentEncoder>(
+        &self,
+        wallet: &WalletCanister<'_>,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+        cycles: u64,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<(Principal, DeployAction)> {
+        let canister_id = match canister_id.into() {
+            Some(canister_id) => canister_id,
+            None => {
+                let canister_id = wallet.create_canister(cycles, None).await?;
+                self.install_code(canister_id, bytecode, arg).await?;
+                return Ok((canister_id, DeployAction::Installed));
+            }
+        };
+
+        // Compare with what would be uploaded, a large module is installed compressed
+        let upload = prepare_upload(bytecode.as_ref())?;
+        let (action, mode) = match self.canister_status(canister_id).await?.module_hash {
+            None => (DeployAction::Installed, InstallMode::Install),
+            Some(hash) if hash[..] == sha256(&upload) => {
+                return Ok((canister_id, DeployAction::Unchanged))
+            }
+            Some(_) => (DeployAction::Upgraded, InstallMode::Upgrade),
+        };
+        self.install_raw(canister_id, &upload, mode, &encode_args(arg)?)
+            .await?;
+        Ok((canister_id, action))
+    }
+
     /// Replaces code of an existing canister. This method completely erases the old canister with
     /// all its state. If you want to upgrade the canister, call [`Canister::upgrade_code`] instead.
     pub async fn reinstall_code<T: ArgumentEncoder>(
         &self,
-        agent: &Agent,
         canister_id: Principal,
-        bytecode: Vec<u8>,
+        bytecode: impl AsRef<[u8]>,
         arg: T,
     ) -> Result<()> {
-        self._install_code(agent, canister_id, bytecode, InstallMode::Reinstall, arg)
+        self._install_code(canister_id, bytecode, InstallMode::Reinstall, arg)
             .await
     }
 
+    /// Reinstall an existing canister and run `health_check`, restoring the snapshot taken
+    /// before the reinstall if either fails, see [`crate::snapshot::with_rollback`]
+    pub async fn reinstall_code_with_rollback<T, H, HFut>(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+        health_check: H,
+    ) -> Result<()>
+    where
+        T: ArgumentEncoder,
+        H: FnOnce() -> HFut,
+        HFut: Future<Output = Result<()>>,
+    {
+        with_rollback(
+            self,
+            canister_id,
+            || self.reinstall_code(canister_id, bytecode, arg),
+            health_check,
+        )
+        .await
+    }
+
     /// Upgrade an existing canister.
     /// Upgrading a canister for a test is possible even if the underlying binary hasn't changed
     pub async fn upgrade_code<T: ArgumentEncoder>(
         &self,
-        agent: &Agent,
         canister_id: Principal,
-        bytecode: Vec<u8>,
+        bytecode: impl AsRef<[u8]>,
         arg: T,
     ) -> Result<()> {
-        self._install_code(agent, canister_id, bytecode, InstallMode::Upgrade, arg)
+        self._install_code(canister_id, bytecode, InstallMode::Upgrade, arg)
             .await
     }
 
+    /// Upgrade an existing canister and run `health_check`, restoring the snapshot taken
+    /// before the upgrade if either fails, see [`crate::snapshot::with_rollback`]
+    pub async fn upgrade_code_with_rollback<T, H, HFut>(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+        health_check: H,
+    ) -> Result<()>
+    where
+        T: ArgumentEncoder,
+        H: FnOnce() -> HFut,
+        HFut: Future<Output = Result<()>>,
+    {
+        with_rollback(
+            self,
+            canister_id,
+            || self.upgrade_code(canister_id, bytecode, arg),
+            health_check,
+        )
+        .await
+    }
+
+    /// Upgrade an existing canister, without an upgrade argument
+    pub async fn upgrade_code_no_arg(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        self.upgrade_code(canister_id, bytecode, ()).await
+    }
+
+    /// Upgrade an existing canister after checking that the candid interface embedded in
+    /// `bytecode` is compatible with the interface of the installed module.
+    /// The check is skipped if the installed module has no `candid:service` metadata.
+    pub async fn upgrade_code_checked<T: ArgumentEncoder>(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+    ) -> Result<()> {
+        if let Some(old_did) = self.candid_interface(canister_id).await? {
+            let new_did = embedded_candid(bytecode.as_ref())?.ok_or_else(|| {
+                Error::IncompatibleInterface("the new module has no candid:service metadata".into())
+            })?;
+            check_upgrade_compatible(&old_did, &new_did)?;
+        }
+        self.upgrade_code(canister_id, bytecode, arg).await
+    }
+
+    /// The candid interface in the public `candid:service` metadata of the installed module.
+    /// This reads the state tree, so it is only available on an agent backend.
+    pub async fn candid_interface(&self, canister_id: Principal) -> Result<Option<String>> {
+        match self
+            .canister_metadata(canister_id, "candid:service")
+            .await?
+        {
+            Some(did) => String::from_utf8(did)
+                .map(Some)
+                .map_err(|e| Error::Generic(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// The metadata section `name` of the installed module, like `git_commit_id` or
+    /// `candid:args`, or `None` if the module has no such section.
+    /// Private sections can only be read by the controllers.
+    /// This reads the state tree, so it is only available on an agent backend.
+    ///
+    /// ```
+    /// # async fn run(management: ic_test_utils::ManagementCanister<'_>, counter: candid::Principal, expected_commit: &str) {
+    /// let commit = management
+    ///     .canister_metadata(counter, "git_commit_id")
+    ///     .await
+    ///     .unwrap()
+    ///     .expect("no git_commit_id metadata");
+    /// assert_eq!(commit, expected_commit.as_bytes());
+    /// # }
+    /// ```
+    pub async fn canister_metadata(
+        &self,
+        canister_id: Principal,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        match self
+            .agent()?
+            .read_state_canister_metadata(canister_id, name, false)
+            .await
+        {
+            Ok(section) => Ok(Some(section)),
+            Err(AgentError::LookupPathAbsent(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Upgrade an existing canister, unless it already runs `bytecode`.
+    /// Returns whether the canister was upgraded.
+    pub async fn upgrade_code_if_changed<T: ArgumentEncoder>(
+        &self,
+        canister_id: Principal,
+        bytecode: impl AsRef<[u8]>,
+        arg: T,
+    ) -> Result<bool> {
+        // Compare with what would be uploaded, a large module is installed compressed
+        let upload = prepare_upload(bytecode.as_ref())?;
+        let installed = self.module_hash(canister_id).await?;
+        if installed.as_deref() == Some(&sha256(&upload)[..]) {
+            return Ok(false);
+        }
+        self.install_raw(
+            canister_id,
+            &upload,
+            InstallMode::Upgrade,
+            &encode_args(arg)?,
+        )
+        .await?;
+        Ok(true)
+    }
+
+    /// The SHA-256 hash of the module installed on a canister,
+    /// or `None` if the canister is empty.
+    /// This reads the state tree, so it is only available on an agent backend.
+    pub async fn module_hash(&self, canister_id: Principal) -> Result<Option<Vec<u8>>> {
+        match self
+            .agent()?
+            .read_state_canister_info(canister_id, "module_hash", false)
+            .await
+        {
+            Ok(hash) => Ok(Some(hash)),
+            Err(AgentError::LookupPathAbsent(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Stop a running canister
     pub async fn stop_canister(
         &self,
-        agent: &Agent,
         canister_id: Principal, // canister to stop
     ) -> Result<()> {
         let arg = Encode!(&In { canister_id })?;
-        agent
-            .update(&Principal::management_canister(), "stop_canister")
-            .with_arg(arg)
-            .call_and_wait(get_waiter())
+        self.call_management("stop_canister", canister_id, arg)
+            .await?;
+        Ok(())
+    }
+
+    /// Start a stopped canister
+    pub async fn start_canister(
+        &self,
+        canister_id: Principal, // canister to start
+    ) -> Result<()> {
+        let arg = Encode!(&In { canister_id })?;
+        self.call_management("start_canister", canister_id, arg)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the status of a canister.
+    /// Only the controllers of a canister can get its status.
+    pub async fn canister_status(&self, canister_id: Principal) -> Result<CanisterStatus> {
+        let arg = Encode!(&In { canister_id })?;
+        let data = self
+            .call_management("canister_status", canister_id, arg)
+            .await?;
+        let status = Decode!(&data, CanisterStatus)?;
+        Ok(status)
+    }
+
+    /// Replace the controllers of a canister.
+    /// Only the controllers of a canister can change them.
+    pub async fn set_controllers(
+        &self,
+        canister_id: Principal,
+        controllers: Vec<Principal>,
+    ) -> Result<()> {
+        let arg = Encode!(&UpdateSettingsArgs {
+            canister_id,
+            settings: ControllersUpdate {
+                controllers: Some(controllers),
+            },
+        })?;
+        self.call_management("update_settings", canister_id, arg)
+            .await?;
+        Ok(())
+    }
+
+    /// Add `controller` to the controllers of a canister, unless it is one already
+    pub async fn add_controller(
+        &self,
+        canister_id: Principal,
+        controller: Principal,
+    ) -> Result<()> {
+        let mut controllers = self
+            .canister_status(canister_id)
+            .await?
+            .settings
+            .controllers;
+        if controllers.contains(&controller) {
+            return Ok(());
+        }
+        controllers.push(controller);
+        self.set_controllers(canister_id, controllers).await
+    }
+
+    /// Remove `controller` from the controllers of a canister, if it is one.
+    /// Removing the caller leaves it unable to change the controllers again.
+    pub async fn remove_controller(
+        &self,
+        canister_id: Principal,
+        controller: Principal,
+    ) -> Result<()> {
+        let mut controllers = self
+            .canister_status(canister_id)
+            .await?
+            .settings
+            .controllers;
+        if !controllers.contains(&controller) {
+            return Ok(());
+        }
+        controllers.retain(|c| *c != controller);
+        self.set_controllers(canister_id, controllers).await
+    }
+
+    /// Fetch the logs a canister wrote with `debug_print`, and its traps.
+    /// Only the controllers can fetch the logs, unless the canister makes them public.
+    pub async fn fetch_canister_logs(
+        &self,
+        canister_id: Principal,
+    ) -> Result<Vec<CanisterLogRecord>> {
+        let arg = Encode!(&In { canister_id })?;
+        let data = self
+            .query_management("fetch_canister_logs", canister_id, arg)
+            .await?;
+        let result = Decode!(&data, FetchLogsResult)?;
+        Ok(result.canister_log_records)
+    }
+
+    /// Take a snapshot of the state of a canister, replacing the snapshot
+    /// `replace_snapshot` if given.
+    /// The canister should be stopped first: [`Canister::stop_canister`]
+    pub async fn take_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        replace_snapshot: Option<Vec<u8>>,
+    ) -> Result<Snapshot> {
+        let arg = Encode!(&TakeSnapshotArgs {
+            canister_id,
+            replace_snapshot: replace_snapshot.map(serde_bytes::ByteBuf::from),
+        })?;
+        let data = self
+            .call_management("take_canister_snapshot", canister_id, arg)
+            .await?;
+        let snapshot = Decode!(&data, Snapshot)?;
+        Ok(snapshot)
+    }
+
+    /// Restore a canister to the state of a snapshot.
+    /// The canister should be stopped first: [`Canister::stop_canister`]
+    pub async fn load_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: Vec<u8>,
+    ) -> Result<()> {
+        let arg = Encode!(&SnapshotArgs {
+            canister_id,
+            snapshot_id,
+        })?;
+        self.call_management("load_canister_snapshot", canister_id, arg)
+            .await?;
+        Ok(())
+    }
+
+    /// List the snapshots of a canister
+    pub async fn list_canister_snapshots(&self, canister_id: Principal) -> Result<Vec<Snapshot>> {
+        let arg = Encode!(&In { canister_id })?;
+        let data = self
+            .call_management("list_canister_snapshots", canister_id, arg)
+            .await?;
+        let snapshots = Decode!(&data, Vec<Snapshot>)?;
+        Ok(snapshots)
+    }
+
+    /// Delete a snapshot of a canister
+    pub async fn delete_canister_snapshot(
+        &self,
+        canister_id: Principal,
+        snapshot_id: Vec<u8>,
+    ) -> Result<()> {
+        let arg = Encode!(&SnapshotArgs {
+            canister_id,
+            snapshot_id,
+        })?;
+        self.call_management("delete_canister_snapshot", canister_id, arg)
             .await?;
         Ok(())
     }
@@ -148,14 +731,10 @@ impl<'agent> Canister<'agent, Management> {
     /// make sure the canister has stopped first: [`Canister::stop_canister`]
     pub async fn delete_canister(
         &self,
-        agent: &Agent,
         canister_id: Principal, // canister to delete
     ) -> Result<()> {
         let arg = Encode!(&In { canister_id })?;
-        agent
-            .update(&Principal::management_canister(), "delete_canister")
-            .with_arg(arg)
-            .call_and_wait(get_waiter())
+        self.call_management("delete_canister", canister_id, arg)
             .await?;
         Ok(())
     }
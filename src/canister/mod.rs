@@ -3,43 +3,116 @@
 //! ```
 //! use ic_test_utils::canister::Canister;
 //!
-//! # async fn run<'a, T>(canister: Canister<'a, T>, principal: ic_cdk::export::candid::Principal, agent: &'a ic_agent::Agent) {
+//! # async fn run<'a, T>(canister: Canister<'a, T>, principal: candid::Principal, agent: &'a ic_agent::Agent) {
 //! let wallet = Canister::new_wallet(agent, "bob", None).unwrap();
 //! let management = Canister::new_management(agent);
 //! # }
 //! ```
 use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
-use crate::Result;
+use crate::backend::{wait_for_request, Backend};
+use crate::interface::encode_text;
+use crate::{Error, Result};
 use candid::{CandidType, Encode};
 use ic_agent::agent::{Agent, QueryBuilder, UpdateBuilder};
 use ic_agent::ic_types::Principal;
+use ic_agent::AgentError;
 
+mod assets;
+#[cfg(feature = "wallet")]
+mod builder;
+#[cfg(feature = "ledger")]
+mod cycles_minting;
+#[cfg(feature = "icrc")]
+mod icrc1_ledger;
+#[cfg(feature = "wallet")]
+mod kind;
+#[cfg(feature = "ledger")]
+mod ledger;
+#[cfg(feature = "ledger")]
+mod ledger_index;
 mod management;
+#[cfg(feature = "ledger")]
+mod sns_wasm;
+#[cfg(feature = "wallet")]
 mod wallet;
 
-pub use management::Management;
-pub use wallet::Wallet;
+pub use assets::{
+    Asset, AssetDetails, AssetEncodingDetails, Assets, AssetsCanisterArgs, AssetsInitArgs,
+    AssetsPermissions, CHUNK_SIZE,
+};
+#[cfg(feature = "wallet")]
+pub use builder::CanisterBuilder;
+#[cfg(feature = "ledger")]
+pub use cycles_minting::{
+    CyclesMinting, CyclesMintingInitPayload, ExchangeRateCanister, CYCLES_MINTING_CANISTER_ID,
+};
+#[cfg(feature = "icrc")]
+pub use icrc1_ledger::{
+    ArchiveOptions, FeatureFlags, Icrc1Account, Icrc1InitArgs, Icrc1LedgerArg, MetadataValue,
+    DEFAULT_TRANSFER_FEE,
+};
+#[cfg(feature = "wallet")]
+pub use kind::CanisterKind;
+#[cfg(feature = "ledger")]
+pub use ledger::{
+    AccountIdentifier, Ledger, LedgerCanisterPayload, LedgerInitArgs, Subaccount, Tokens,
+    LEDGER_CANISTER_ID,
+};
+#[cfg(feature = "ledger")]
+pub use ledger_index::{IndexInitArgs, LedgerIndex, LEDGER_INDEX_CANISTER_ID};
+pub use management::{
+    CanisterLogRecord, CanisterRunStatus, CanisterSettings, CanisterStatus, DeployAction,
+    InstallMode, Management, MemoryMetrics, Snapshot,
+};
+#[cfg(feature = "ledger")]
+pub use sns_wasm::{
+    DappCanister, DappCanisters, DeployedSns, SnsCanisterIds, SnsInitPayload, SnsWasm,
+    SNS_WASM_CANISTER_ID,
+};
+#[cfg(feature = "wallet")]
+pub use wallet::{CyclesReceipt, Wallet};
+
+/// How long an idempotent update can be resubmitted, within the five minutes
+/// the replica accepts and deduplicates a request.
+pub const IDEMPOTENT_INGRESS_EXPIRY: Duration = Duration::from_secs(4 * 60);
+
+/// The delay before the first retry of an idempotent update, it grows with each retry
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
 /// Type alias for the management canister
 pub type ManagementCanister<'agent> = Canister<'agent, Management>;
 
 /// Type alias for the wallet canister
+#[cfg(feature = "wallet")]
 pub type WalletCanister<'agent> = Canister<'agent, Wallet>;
 
+/// Type alias for the ICP ledger canister
+#[cfg(feature = "ledger")]
+pub type LedgerCanister<'agent> = Canister<'agent, Ledger>;
+
+/// Type alias for the assets canister
+pub type AssetsCanister<'agent> = Canister<'agent, Assets>;
+
+/// Type alias for the SNS-W canister
+#[cfg(feature = "ledger")]
+pub type SnsWasmCanister<'agent> = Canister<'agent, SnsWasm>;
+
 /// Represent a Canister in a test case
 pub struct Canister<'agent, T> {
     id: Principal,
-    pub(crate) agent: &'agent Agent,
+    pub(crate) backend: &'agent dyn Backend,
     _phantom_data: PhantomData<T>,
 }
 
 impl<'agent, T> Canister<'agent, T> {
-    /// Create a new canister with a given `Principal`
-    pub fn new(id: Principal, agent: &'agent Agent) -> Self {
+    /// Create a new canister with a given `Principal`.
+    /// The `backend` is usually an [`Agent`] or a [`PocketIc`](crate::backend::PocketIc) instance.
+    pub fn new(id: Principal, backend: &'agent dyn Backend) -> Self {
         Self {
             id,
-            agent,
+            backend,
             _phantom_data: PhantomData,
         }
     }
@@ -49,13 +122,27 @@ impl<'agent, T> Canister<'agent, T> {
         &self.id
     }
 
-    /// Update call to the canister
+    /// The backend executing the calls
+    pub fn backend(&self) -> &'agent dyn Backend {
+        self.backend
+    }
+
+    /// The agent, if the backend talks to a replica
+    pub(crate) fn agent(&self) -> Result<&'agent Agent> {
+        self.backend
+            .agent()
+            .ok_or(Error::UnsupportedByBackend("agent calls"))
+    }
+
+    /// Update call to the canister.
+    /// This is only available when the backend is an [`Agent`],
+    /// use [`Canister::call_update`] for any backend.
     pub fn update<A: CandidType>(
         &self,
         method_name: impl Into<String>,
         args: Option<A>,
     ) -> Result<UpdateBuilder<'_>> {
-        let mut builder = self.agent.update(&self.id, method_name);
+        let mut builder = self.agent()?.update(&self.id, method_name);
         if let Some(ref args) = args {
             let args = Encode!(args)?;
             builder.with_arg(args);
@@ -63,8 +150,143 @@ impl<'agent, T> Canister<'agent, T> {
         Ok(builder)
     }
 
-    /// Query the canister
-    pub fn query(&self, method_name: impl Into<String>) -> QueryBuilder<'_> {
-        self.agent.query(&self.id, method_name)
+    /// Query the canister.
+    /// This is only available when the backend is an [`Agent`],
+    /// use [`Canister::call_query`] for any backend.
+    pub fn query(&self, method_name: impl Into<String>) -> Result<QueryBuilder<'_>> {
+        Ok(self.agent()?.query(&self.id, method_name))
+    }
+
+    /// Make an update call with candid encoded `arg`
+    /// and wait for the candid encoded reply
+    pub async fn call_update(&self, method_name: &str, arg: Vec<u8>) -> Result<Vec<u8>> {
+        let arg_len = arg.len();
+        let result = self
+            .backend
+            .update_call(self.id, None, method_name, arg)
+            .await;
+        with_context(result, self.id, method_name, arg_len)
+    }
+
+    /// Make an update call like [`Canister::call_update`], retrying up to `retries` times
+    /// when the replica can't be reached.
+    ///
+    /// The call is signed once, so every attempt sends the same nonce and ingress expiry,
+    /// and the replica deduplicates the resubmissions: the update executes at most once.
+    /// Retries stop once the request expires after [`IDEMPOTENT_INGRESS_EXPIRY`], with
+    /// [`Error::IngressExpired`].
+    /// This is only available when the backend is an [`Agent`].
+    pub async fn call_update_idempotent(
+        &self,
+        method_name: &str,
+        arg: Vec<u8>,
+        retries: u32,
+    ) -> Result<Vec<u8>> {
+        let arg_len = arg.len();
+        let result = self.update_idempotent(method_name, arg, retries).await;
+        with_context(result, self.id, method_name, arg_len)
     }
+
+    async fn update_idempotent(
+        &self,
+        method_name: &str,
+        arg: Vec<u8>,
+        retries: u32,
+    ) -> Result<Vec<u8>> {
+        let agent = self.agent()?;
+        let expires_at = Instant::now() + IDEMPOTENT_INGRESS_EXPIRY;
+        let signed = agent
+            .update(&self.id, method_name)
+            .with_arg(arg)
+            .expire_after(IDEMPOTENT_INGRESS_EXPIRY)
+            .sign()?;
+
+        let mut attempt = 0;
+        loop {
+            let result = match agent
+                .update_signed(signed.effective_canister_id, signed.signed_update.clone())
+                .await
+            {
+                Ok(request_id) => {
+                    wait_for_request(agent, request_id, signed.effective_canister_id, method_name)
+                        .await
+                }
+                Err(err) => Err(Error::from_agent(err, method_name)),
+            };
+
+            match result {
+                Err(err) if attempt < retries && is_transport_error(&err) => {
+                    attempt += 1;
+                    let delay = RETRY_DELAY * attempt;
+                    if Instant::now() + delay >= expires_at {
+                        return Err(Error::IngressExpired {
+                            method: method_name.into(),
+                            attempts: attempt,
+                            source: Box::new(err),
+                        });
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Make a query call with candid encoded `arg`
+    /// and return the candid encoded reply
+    pub async fn call_query(&self, method_name: &str, arg: Vec<u8>) -> Result<Vec<u8>> {
+        let arg_len = arg.len();
+        let result = self
+            .backend
+            .query_call(self.id, None, method_name, arg)
+            .await;
+        with_context(result, self.id, method_name, arg_len)
+    }
+
+    /// Make an update call with the candid text `arg`, like `(record { owner = principal "aaaaa-aa" })`,
+    /// and wait for the candid encoded reply.
+    /// Numbers need a type annotation unless they are `int`s, see [`encode_text`].
+    ///
+    /// ```
+    /// # async fn run(ledger: ic_test_utils::Canister<'_, ()>) {
+    /// use ic_test_utils::interface::decode_text;
+    ///
+    /// let fixture = r#"(record { to = principal "2vxsx-fae"; amount = 100 : nat })"#;
+    /// let reply = ledger.call_update_text("transfer", fixture).await.unwrap();
+    /// println!("{}", decode_text(&reply).unwrap());
+    /// # }
+    /// ```
+    pub async fn call_update_text(&self, method_name: &str, arg: &str) -> Result<Vec<u8>> {
+        self.call_update(method_name, encode_text(arg, None)?).await
+    }
+
+    /// Make a query call with the candid text `arg` and return the candid encoded reply,
+    /// see [`Canister::call_update_text`]
+    pub async fn call_query_text(&self, method_name: &str, arg: &str) -> Result<Vec<u8>> {
+        self.call_query(method_name, encode_text(arg, None)?).await
+    }
+}
+
+/// Whether the replica couldn't be reached, rather than having rejected the call
+pub(crate) fn is_transport_error(err: &Error) -> bool {
+    match err {
+        Error::Agent(AgentError::TransportError(_)) => true,
+        Error::Agent(AgentError::HttpError(payload)) => payload.status >= 500,
+        _ => false,
+    }
+}
+
+/// Add the canister, method and argument length to the error of a call
+pub(crate) fn with_context<T>(
+    result: Result<T>,
+    canister_id: Principal,
+    method_name: &str,
+    arg_len: usize,
+) -> Result<T> {
+    result.map_err(|source| Error::Call {
+        canister_id,
+        method: method_name.into(),
+        arg_len,
+        source: Box::new(source),
+    })
 }
@@ -0,0 +1,200 @@
+//! Functions specific to the SNS-W (SNS wasm modules) canister.
+//!
+//! The [`SnsWasm`] canister deploys new SNS instances and keeps track of
+//! the ones it has deployed.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent) {
+//! use ic_test_utils::canister::{Canister, SnsInitPayload};
+//!
+//! let sns_wasm = Canister::new_sns_wasm(agent, None).unwrap();
+//! let payload = SnsInitPayload {
+//!     token_name: Some("Test token".to_string()),
+//!     token_symbol: Some("TST".to_string()),
+//!     ..Default::default()
+//! };
+//! let canisters = sns_wasm.deploy_new_sns(payload).await.unwrap();
+//! let deployed = sns_wasm.list_deployed_snses().await.unwrap();
+//! # }
+//! ```
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_agent::ic_types::Principal;
+
+use super::Canister;
+#[cfg(feature = "wallet")]
+use super::WalletCanister;
+use crate::backend::Backend;
+use crate::{Error, Result};
+
+/// The id of the SNS-W canister on the NNS subnet.
+pub const SNS_WASM_CANISTER_ID: &str = "qaa6y-5yaaa-aaaaa-aaafa-cai";
+
+/// Init payload for a new SNS.
+///
+/// This is a subset of the fields of the SNS-W `SnsInitPayload`. Every field
+/// is optional, so any field left out is treated as `null` by the canister.
+#[derive(Debug, Default, Clone, CandidType, Deserialize)]
+pub struct SnsInitPayload {
+    /// Name of the SNS token
+    pub token_name: Option<String>,
+    /// Symbol of the SNS token
+    pub token_symbol: Option<String>,
+    /// Transaction fee of the SNS ledger, in e8s
+    pub transaction_fee_e8s: Option<u64>,
+    /// Cost of a rejected proposal, in e8s
+    pub proposal_reject_cost_e8s: Option<u64>,
+    /// Minimum stake of a neuron, in e8s
+    pub neuron_minimum_stake_e8s: Option<u64>,
+    /// Principals in control of the dapp canisters if the swap fails
+    pub fallback_controller_principal_ids: Vec<String>,
+    /// Base64 encoded logo of the SNS
+    pub logo: Option<String>,
+    /// Url of the dapp
+    pub url: Option<String>,
+    /// Name of the SNS
+    pub name: Option<String>,
+    /// Description of the SNS
+    pub description: Option<String>,
+    /// Dapp canisters handed over to the SNS
+    pub dapp_canisters: Option<DappCanisters>,
+}
+
+/// Dapp canisters handed over to a new SNS.
+#[derive(Debug, Default, Clone, CandidType, Deserialize)]
+pub struct DappCanisters {
+    /// The canisters
+    pub canisters: Vec<DappCanister>,
+}
+
+/// A single dapp canister.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct DappCanister {
+    /// The id of the canister
+    pub id: Option<Principal>,
+}
+
+/// Canister ids of a deployed SNS.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct SnsCanisterIds {
+    /// Root canister
+    pub root: Option<Principal>,
+    /// Governance canister
+    pub governance: Option<Principal>,
+    /// Ledger canister
+    pub ledger: Option<Principal>,
+    /// Swap canister
+    pub swap: Option<Principal>,
+    /// Index canister
+    pub index: Option<Principal>,
+}
+
+/// An SNS in the result of a [`Canister::list_deployed_snses`] call.
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct DeployedSns {
+    /// Root canister
+    pub root_canister_id: Option<Principal>,
+    /// Governance canister
+    pub governance_canister_id: Option<Principal>,
+    /// Ledger canister
+    pub ledger_canister_id: Option<Principal>,
+    /// Swap canister
+    pub swap_canister_id: Option<Principal>,
+    /// Index canister
+    pub index_canister_id: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct SnsWasmError {
+    message: String,
+}
+
+#[derive(CandidType)]
+struct DeployNewSnsRequest {
+    sns_init_payload: Option<SnsInitPayload>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DeployNewSnsResponse {
+    canisters: Option<SnsCanisterIds>,
+    error: Option<SnsWasmError>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ListDeployedSnsesRequest {}
+
+#[derive(CandidType, Deserialize)]
+struct ListDeployedSnsesResponse {
+    instances: Vec<DeployedSns>,
+}
+
+fn decode_deploy_response(data: &[u8]) -> Result<SnsCanisterIds> {
+    let response = Decode!(data, DeployNewSnsResponse)?;
+    if let Some(err) = response.error {
+        return Err(err.message.into());
+    }
+    response
+        .canisters
+        .ok_or_else(|| Error::Generic("SNS-W returned no canister ids".into()))
+}
+
+/// SNS-W canister
+pub struct SnsWasm;
+
+impl<'agent> Canister<'agent, SnsWasm> {
+    /// Create a new SNS-W canister.
+    /// If the `canister_id` is `None` then the default [`SNS_WASM_CANISTER_ID`]
+    /// will be used.
+    pub fn new_sns_wasm(
+        backend: &'agent dyn Backend,
+        canister_id: impl Into<Option<Principal>>,
+    ) -> Result<Self> {
+        let id = match canister_id.into() {
+            Some(id) => id,
+            None => Principal::from_text(SNS_WASM_CANISTER_ID)?,
+        };
+        Ok(Self::new(id, backend))
+    }
+
+    /// Deploy a new SNS.
+    ///
+    /// SNS-W charges cycles for a deployment, use
+    /// [`Canister::deploy_new_sns_with_cycles`] if the caller is not
+    /// whitelisted.
+    pub async fn deploy_new_sns(&self, payload: SnsInitPayload) -> Result<SnsCanisterIds> {
+        let args = DeployNewSnsRequest {
+            sns_init_payload: Some(payload),
+        };
+        let data = self.call_update("deploy_new_sns", Encode!(&args)?).await?;
+        decode_deploy_response(&data)
+    }
+
+    /// Deploy a new SNS, forwarding the call through the `wallet`
+    /// so `cycles` can be attached.
+    #[cfg(feature = "wallet")]
+    pub async fn deploy_new_sns_with_cycles(
+        &self,
+        wallet: &WalletCanister<'_>,
+        payload: SnsInitPayload,
+        cycles: u64,
+    ) -> Result<SnsCanisterIds> {
+        let args = DeployNewSnsRequest {
+            sns_init_payload: Some(payload),
+        };
+        let mut builder = self.update::<()>("deploy_new_sns", None)?;
+        builder.with_arg(&Encode!(&args)?);
+        let data = wallet.call_forward(builder, cycles).await?;
+        decode_deploy_response(&data)
+    }
+
+    /// List all the SNSes deployed by this SNS-W canister.
+    pub async fn list_deployed_snses(&self) -> Result<Vec<DeployedSns>> {
+        let data = self
+            .call_query(
+                "list_deployed_snses",
+                Encode!(&ListDeployedSnsesRequest {})?,
+            )
+            .await?;
+        let response = Decode!(&data, ListDeployedSnsesResponse)?;
+        Ok(response.instances)
+    }
+}
@@ -14,11 +14,11 @@
 use std::fs::read_to_string;
 
 use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_agent::agent::UpdateBuilder;
 use ic_agent::ic_types::Principal;
-use ic_agent::{agent::UpdateBuilder, Agent};
 
-use super::Canister;
-use crate::get_waiter;
+use super::{Canister, CanisterStatus};
+use crate::backend::Backend;
 use crate::{Error, Result};
 
 pub const WALLET_IDS_PATH: &str = "../../.dfx/local/wallets.json";
@@ -51,6 +51,33 @@ pub struct CallResult {
     pub payload: Vec<u8>,
 }
 
+/// The cycles sent by [`Canister::send_cycles`], and how many the canister kept
+#[derive(Debug, Clone)]
+pub struct CyclesReceipt {
+    /// The cycles sent with the call
+    pub sent: u64,
+    /// The growth of the balance of the canister, what it accepted minus
+    /// what executing the call cost
+    pub accepted: u64,
+    /// The reply of the canister
+    pub reply: Vec<u8>,
+}
+
+impl CyclesReceipt {
+    /// The cycles sent that didn't end up in the balance of the canister.
+    /// This is an estimate from the balance, not the refund: it includes what executing
+    /// the call cost, and any other change of the balance meanwhile. The `wallet_call`
+    /// of the cycles wallet doesn't report the refund.
+    pub fn not_accepted(&self) -> u64 {
+        self.sent.saturating_sub(self.accepted)
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct In {
+    canister_id: Principal,
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct CreateResult {
     pub canister_id: Principal,
@@ -73,35 +100,112 @@ impl<'agent> Canister<'agent, Wallet> {
     /// If the `wallet_id_path` is `None` then the default [`WALLET_IDS_PATH`] will
     /// be used.
     pub fn new_wallet<'a>(
-        agent: &'agent Agent,
+        backend: &'agent dyn Backend,
         account_name: impl AsRef<str>,
         wallet_id_path: impl Into<Option<&'a str>>,
     ) -> Result<Self> {
         let id = get_wallet_principal(account_name, wallet_id_path)?;
-        let inst = Self::new(id, agent);
+        let inst = Self::new(id, backend);
         Ok(inst)
     }
 
     /// Get the current balance of a canister
     pub async fn balance(&self) -> Result<BalanceResult> {
-        let mut builder = self.agent.query(self.principal(), "wallet_balance");
-        builder.with_arg(&Encode!(&())?);
-        let data = builder.call().await?;
+        let data = self.call_query("wallet_balance", Encode!(&())?).await?;
         let balance = Decode!(&data, BalanceResult)?;
         Ok(balance)
     }
 
     /// Forward a call through the wallet, so cycles can be spent.
     pub async fn call_forward(&self, call: UpdateBuilder<'_>, cycles: u64) -> Result<Vec<u8>> {
+        self.forward(call.canister_id, call.method_name, call.arg, cycles)
+            .await
+    }
+
+    /// Call `method_name` of the management canister through the wallet, with candid
+    /// encoded `arg`. This is for the methods only canisters can call, and works with any
+    /// backend.
+    pub async fn call_management(
+        &self,
+        method_name: &str,
+        arg: Vec<u8>,
+        cycles: u64,
+    ) -> Result<Vec<u8>> {
+        self.forward(
+            Principal::management_canister(),
+            method_name.into(),
+            arg,
+            cycles,
+        )
+        .await
+    }
+
+    /// Send `cycles` to `method_name` of `canister_id`, like `wallet_receive`, without an
+    /// argument, and compare its balance before and after.
+    /// The balance is read through the management canister, so the wallet has to be a
+    /// controller of the canister.
+    ///
+    /// ```
+    /// # async fn run(wallet: ic_test_utils::WalletCanister<'_>, piggy_bank: candid::Principal) {
+    /// let receipt = wallet
+    ///     .send_cycles(piggy_bank, "wallet_receive", 1_000_000_000_000)
+    ///     .await
+    ///     .unwrap();
+    /// // Executing the call costs a few million cycles
+    /// assert!(receipt.not_accepted() < 10_000_000);
+    /// # }
+    /// ```
+    pub async fn send_cycles(
+        &self,
+        canister_id: Principal,
+        method_name: &str,
+        cycles: u64,
+    ) -> Result<CyclesReceipt> {
+        let before = self.cycle_balance(canister_id).await?;
+        let reply = self
+            .forward(canister_id, method_name.into(), Encode!()?, cycles)
+            .await?;
+        let after = self.cycle_balance(canister_id).await?;
+        Ok(CyclesReceipt {
+            sent: cycles,
+            accepted: after.saturating_sub(before),
+            reply,
+        })
+    }
+
+    /// Add `cycles` from the wallet to the balance of `canister_id`, through the
+    /// `deposit_cycles` method of the management canister
+    pub async fn deposit_cycles(&self, canister_id: Principal, cycles: u64) -> Result<()> {
+        self.call_management("deposit_cycles", Encode!(&In { canister_id })?, cycles)
+            .await?;
+        Ok(())
+    }
+
+    /// The cycle balance of a canister the wallet controls
+    async fn cycle_balance(&self, canister_id: Principal) -> Result<u64> {
+        let data = self
+            .call_management("canister_status", Encode!(&In { canister_id })?, 0)
+            .await?;
+        let status = Decode!(&data, CanisterStatus)?;
+        Ok(u64::try_from(&status.cycles.0).unwrap_or(u64::MAX))
+    }
+
+    async fn forward(
+        &self,
+        canister: Principal,
+        method_name: String,
+        args: Vec<u8>,
+        cycles: u64,
+    ) -> Result<Vec<u8>> {
         let call_forward_args = CallForwardArgs {
-            canister: call.canister_id,
-            method_name: call.method_name,
-            args: call.arg,
+            canister,
+            method_name,
+            args,
             cycles,
         };
-        let mut builder = self.agent.update(self.principal(), "wallet_call");
-        builder.with_arg(&Encode!(&call_forward_args)?);
-        let data = builder.call_and_wait(get_waiter()).await?;
+        let data = self
+            .call_update("wallet_call", Encode!(&call_forward_args)?)
+            .await?;
         let val = Decode!(&data, std::result::Result<CallResult, String>)??;
         Ok(val.payload)
     }
@@ -131,9 +235,6 @@ impl<'agent> Canister<'agent, Wallet> {
             freezing_threshold: Option<u64>,
         }
 
-        let mut builder = self
-            .agent
-            .update(self.principal(), "wallet_create_canister");
         let args = In {
             cycles,
             settings: CanisterSettings {
@@ -143,8 +244,9 @@ impl<'agent> Canister<'agent, Wallet> {
                 freezing_threshold: None,
             },
         };
-        builder.with_arg(&Encode!(&args)?);
-        let data = builder.call_and_wait(get_waiter()).await?;
+        let data = self
+            .call_update("wallet_create_canister", Encode!(&args)?)
+            .await?;
         let result = Decode!(&data, std::result::Result<CreateResult, String>)??;
         Ok(result.canister_id)
     }
@@ -0,0 +1,102 @@
+//! Verify the `IC-Certificate` header of HTTP responses served by asset canisters.
+//!
+//! A response is certified when its header holds a certificate signed by the root key,
+//! whose `certified_data` for the canister is the root hash of the header's tree,
+//! and the tree maps the path under `http_assets` to the SHA-256 of the body.
+//! The agent needs the root key of the replica, see [`Agent::fetch_root_key`].
+//! The age of the certificate is not checked, as tests may move the time of the replica.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, canister: ic_test_utils::Canister<'_, ()>) {
+//! use ic_test_utils::certification::verify_response;
+//! use ic_test_utils::http::http_get;
+//!
+//! let response = http_get(&canister, "/index.html", &[]).await.unwrap();
+//! verify_response(agent, *canister.principal(), "/index.html", &response).unwrap();
+//! # }
+//! ```
+use ic_agent::hash_tree::{HashTree, Label, LookupResult};
+use ic_agent::ic_types::Principal;
+use ic_agent::{lookup_value, Agent, Certificate};
+
+use crate::http::HttpResponse;
+use crate::wasm::sha256;
+use crate::{Error, Result};
+
+/// The header holding the certificate and the tree of a response
+pub const CERTIFICATE_HEADER: &str = "IC-Certificate";
+
+/// Verify that `response`, served by `canister_id` for `url`, is certified
+pub fn verify_response(
+    agent: &Agent,
+    canister_id: Principal,
+    url: &str,
+    response: &HttpResponse,
+) -> Result<()> {
+    let header = response
+        .header(CERTIFICATE_HEADER)
+        .ok_or_else(|| Error::NotCertified(format!("no {} header", CERTIFICATE_HEADER)))?;
+    let (certificate, tree) = parse_header(header)?;
+
+    let certificate: Certificate =
+        serde_cbor::from_slice(&certificate).map_err(|e| invalid("certificate", e))?;
+    agent.verify(&certificate, canister_id, false)?;
+    let certified_data = lookup_value(
+        &certificate,
+        vec![
+            "canister".into(),
+            canister_id.into(),
+            "certified_data".into(),
+        ],
+    )?;
+
+    let tree: HashTree = serde_cbor::from_slice(&tree).map_err(|e| invalid("tree", e))?;
+    if tree.digest()[..] != *certified_data {
+        return Err(Error::NotCertified(
+            "the tree is not the certified data of the canister".into(),
+        ));
+    }
+
+    let path = url.split('?').next().unwrap_or(url);
+    let asset_path: [Label; 2] = ["http_assets".into(), path.into()];
+    match tree.lookup_path(&asset_path) {
+        LookupResult::Found(hash) if hash == sha256(&response.body) => Ok(()),
+        LookupResult::Found(_) => Err(Error::NotCertified(format!(
+            "the body doesn't match the hash certified for {}",
+            path
+        ))),
+        _ => Err(Error::NotCertified(format!(
+            "no hash certified for {}",
+            path
+        ))),
+    }
+}
+
+/// The certificate and the tree of a header like `certificate=:<base64>:, tree=:<base64>:`
+fn parse_header(header: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut certificate = None;
+    let mut tree = None;
+    for field in header.split(',') {
+        let (name, value) = match field.trim().split_once('=') {
+            Some(field) => field,
+            None => continue,
+        };
+        let value = base64::decode(value.trim_matches(':')).map_err(|e| invalid(name, e))?;
+        match name {
+            "certificate" => certificate = Some(value),
+            "tree" => tree = Some(value),
+            _ => {}
+        }
+    }
+    match (certificate, tree) {
+        (Some(certificate), Some(tree)) => Ok((certificate, tree)),
+        _ => Err(Error::NotCertified(format!(
+            "{} header without a certificate and a tree",
+            CERTIFICATE_HEADER
+        ))),
+    }
+}
+
+fn invalid(what: &str, err: impl std::fmt::Display) -> Error {
+    Error::NotCertified(format!("invalid {}: {}", what, err))
+}
@@ -0,0 +1,168 @@
+//! Stop, start and upgrade a canister at random between calls.
+//!
+//! A [`ChaosCanister`] wraps a [`Canister`] handle. Before each call it may stop and
+//! restart the canister, or upgrade it to the same module. The random choices come from
+//! a seed, and when a call fails the seed is printed to stderr, so the failing run can
+//! be reproduced with it.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, counter: candid::Principal, wasm: Vec<u8>) {
+//! use candid::Encode;
+//! use ic_test_utils::chaos::ChaosCanister;
+//! use ic_test_utils::Canister;
+//!
+//! struct Counter;
+//!
+//! let counter = ChaosCanister::new(Canister::<Counter>::new(counter, agent), 42)
+//!     .with_probability(0.3)
+//!     .with_upgrade(&wasm);
+//! for _ in 0..10 {
+//!     counter.call_update("inc", Encode!().unwrap()).await.unwrap();
+//! }
+//! println!("{:?}", counter.events());
+//! # }
+//! ```
+use std::sync::Mutex;
+
+use crate::canister::ManagementCanister;
+use crate::{Canister, Result};
+
+/// Something done to the canister by a [`ChaosCanister`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosEvent {
+    /// The canister was stopped and started again
+    StopStart,
+    /// The canister was upgraded to its own module
+    Upgrade,
+}
+
+/// A canister handle that disrupts the canister between calls.
+pub struct ChaosCanister<'agent, T> {
+    canister: Canister<'agent, T>,
+    management: ManagementCanister<'agent>,
+    seed: u64,
+    probability: f64,
+    wasm: Option<&'agent [u8]>,
+    rng: Mutex<u64>,
+    events: Mutex<Vec<ChaosEvent>>,
+}
+
+impl<'agent, T> ChaosCanister<'agent, T> {
+    /// Wrap `canister`, with the random choices made from `seed`.
+    /// By default there is a 10% chance of a disruption before each call.
+    pub fn new(canister: Canister<'agent, T>, seed: u64) -> Self {
+        let management = Canister::new_management(canister.backend());
+        Self {
+            canister,
+            management,
+            seed,
+            probability: 0.1,
+            wasm: None,
+            rng: Mutex::new(seed),
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Set the chance of a disruption before each call, between 0 and 1
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Also upgrade the canister to `wasm`, which should be its current module.
+    /// The upgrade is done without arguments.
+    pub fn with_upgrade(mut self, wasm: &'agent [u8]) -> Self {
+        self.wasm = Some(wasm);
+        self
+    }
+
+    /// The seed the random choices are made from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The wrapped canister
+    pub fn canister(&self) -> &Canister<'agent, T> {
+        &self.canister
+    }
+
+    /// The disruptions done so far
+    pub fn events(&self) -> Vec<ChaosEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Maybe disrupt the canister, then make an update call
+    pub async fn call_update(&self, method_name: &str, arg: Vec<u8>) -> Result<Vec<u8>> {
+        let result = match self.disrupt().await {
+            Ok(_) => self.canister.call_update(method_name, arg).await,
+            Err(err) => Err(err),
+        };
+        self.print_seed_on_failure(method_name, result)
+    }
+
+    /// Maybe disrupt the canister, then make a query call
+    pub async fn call_query(&self, method_name: &str, arg: Vec<u8>) -> Result<Vec<u8>> {
+        let result = match self.disrupt().await {
+            Ok(_) => self.canister.call_query(method_name, arg).await,
+            Err(err) => Err(err),
+        };
+        self.print_seed_on_failure(method_name, result)
+    }
+
+    fn print_seed_on_failure<R>(&self, method_name: &str, result: Result<R>) -> Result<R> {
+        if result.is_err() {
+            eprintln!(
+                "chaos call to {} failed with seed {} after {:?}",
+                method_name,
+                self.seed,
+                self.events()
+            );
+        }
+        result
+    }
+
+    /// Disrupt the canister with the configured probability
+    pub async fn disrupt(&self) -> Result<Option<ChaosEvent>> {
+        let (roll, pick) = {
+            let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+            (next_f64(&mut rng), next_u64(&mut rng))
+        };
+        if roll >= self.probability {
+            return Ok(None);
+        }
+
+        let canister_id = *self.canister.principal();
+        let event = match self.wasm {
+            Some(wasm) if pick % 2 == 1 => {
+                self.management.upgrade_code(canister_id, wasm, ()).await?;
+                ChaosEvent::Upgrade
+            }
+            _ => {
+                self.management.stop_canister(canister_id).await?;
+                self.management.start_canister(canister_id).await?;
+                ChaosEvent::StopStart
+            }
+        };
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+        Ok(Some(event))
+    }
+}
+
+/// SplitMix64, good enough to pick disruptions and reproducible from the seed
+pub(crate) fn next_u64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+fn next_f64(state: &mut u64) -> f64 {
+    (next_u64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
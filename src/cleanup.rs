@@ -0,0 +1,121 @@
+//! Delete canisters created by a test once it's done with them.
+//!
+//! [`create_canister`](crate::create_canister) returns a [`CanisterGuard`]. When the guard
+//! is dropped, the canister is stopped and deleted by a cleanup task. Call
+//! [`finish_cleanup`] at the end of a test to wait for the tasks of the dropped guards,
+//! or [`CanisterGuard::keep`] to keep the canister around. When the test panics, the
+//! guard waits for the canister to be deleted before the panic goes on.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, wasm: Vec<u8>) {
+//! use ic_test_utils::{cleanup::finish_cleanup, create_canister};
+//!
+//! let canister = create_canister(agent, "alice", wasm, (), 1_000_000_000_000)
+//!     .await
+//!     .unwrap();
+//! let principal = canister.principal();
+//! drop(canister);
+//! finish_cleanup().await;
+//! # }
+//! ```
+//!
+//! Canisters are only cleaned up on an [`Agent`] backend, a PocketIC instance is
+//! deleted as a whole.
+use std::sync::Mutex;
+
+use candid::Principal;
+use futures::future::join_all;
+use tokio::task::JoinHandle;
+
+use crate::backend::Backend;
+use crate::{Agent, Canister, Result};
+
+/// Cleanup tasks of dropped guards that might still be running.
+static CLEANUP_TASKS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Stops and deletes a canister when dropped.
+pub struct CanisterGuard {
+    canister_id: Principal,
+    agent: Option<Agent>,
+}
+
+impl CanisterGuard {
+    /// Guard the canister `canister_id`, created through `backend`.
+    pub fn new(canister_id: Principal, backend: &dyn Backend) -> Self {
+        Self {
+            canister_id,
+            agent: backend.agent().cloned(),
+        }
+    }
+
+    /// The id of the canister
+    pub fn principal(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// Keep the canister, it won't be deleted.
+    pub fn keep(mut self) -> Principal {
+        self.agent = None;
+        self.canister_id
+    }
+
+    /// Stop and delete the canister now.
+    pub async fn cleanup(mut self) -> Result<()> {
+        match self.agent.take() {
+            Some(agent) => delete(&agent, self.canister_id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CanisterGuard {
+    fn drop(&mut self) {
+        let agent = match self.agent.take() {
+            Some(agent) => agent,
+            None => return,
+        };
+        let canister_id = self.canister_id;
+        let cleanup = async move {
+            let _ = delete(&agent, canister_id).await;
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if !std::thread::panicking() => {
+                let task = handle.spawn(cleanup);
+                lock_tasks().push(task);
+            }
+            // Outside of a runtime there is nothing to spawn the task on, and a panicking
+            // test drops its runtime with the tasks spawned on it right away,
+            // so the cleanup runs to completion on its own thread.
+            _ => {
+                let _ = std::thread::spawn(move || {
+                    if let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                    {
+                        runtime.block_on(cleanup);
+                    }
+                })
+                .join();
+            }
+        }
+    }
+}
+
+/// Wait for the cleanup of all dropped guards to finish.
+pub async fn finish_cleanup() {
+    let tasks = std::mem::take(&mut *lock_tasks());
+    join_all(tasks).await;
+}
+
+async fn delete(agent: &Agent, canister_id: Principal) -> Result<()> {
+    let management = Canister::new_management(agent);
+    management.stop_canister(canister_id).await?;
+    management.delete_canister(canister_id).await
+}
+
+fn lock_tasks() -> std::sync::MutexGuard<'static, Vec<JoinHandle<()>>> {
+    CLEANUP_TASKS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,130 @@
+//! The current `candid` and `ic-agent` versions, next to the `candid` 0.7 and
+//! `ic-agent` 0.16 the crate is built with.
+//!
+//! With the `candid-0.10` feature, [`compat::candid`](candid) is candid 0.10 and
+//! principals convert between both versions. Calls only see the candid encoded
+//! bytes, so arguments encoded with candid 0.10 go through any [`Backend`].
+//!
+//! With the `ic-agent-0.49` feature, an [`ic_agent::Agent`] of ic-agent 0.49 is a
+//! [`Backend`] too, so the canisters of this crate run on the agent of a workspace
+//! using the current versions, without building a second agent:
+//!
+//! ```
+//! # #[cfg(feature = "ic-agent-0.49")]
+//! # async fn run() {
+//! use ic_test_utils::compat::{self, candid::Encode};
+//! use ic_test_utils::Canister;
+//!
+//! let agent = compat::get_agent("default", None).await.unwrap();
+//! let management = Canister::new_management(&agent);
+//!
+//! let canister_id = compat::from_principal(
+//!     &compat::candid::Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(),
+//! );
+//! let status = management.canister_status(canister_id).await.unwrap();
+//!
+//! // Candid 0.10 arguments are passed as bytes
+//! use ic_test_utils::backend::Backend;
+//! let reply = agent
+//!     .update_call(canister_id, None, "greet", Encode!(&"world").unwrap())
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use ::candid::Principal;
+
+/// The candid 0.10 crate, with the `candid-0.10` feature
+pub use candid_0_10 as candid;
+/// The ic-agent 0.49 crate, with the `ic-agent-0.49` feature
+#[cfg(feature = "ic-agent-0.49")]
+pub use ic_agent_0_49 as ic_agent;
+
+#[cfg(feature = "ic-agent-0.49")]
+pub use agent::get_agent;
+
+/// A candid 0.10 principal as the principal this crate uses
+pub fn from_principal(principal: &candid::Principal) -> Principal {
+    Principal::from_slice(principal.as_slice())
+}
+
+/// A principal of this crate as a candid 0.10 principal
+pub fn to_principal(principal: &Principal) -> candid::Principal {
+    candid::Principal::from_slice(principal.as_slice())
+}
+
+#[cfg(feature = "ic-agent-0.49")]
+mod agent {
+    use candid::Principal;
+    use ic_agent_0_49::identity::BasicIdentity;
+    use ic_agent_0_49::{Agent, AgentError};
+
+    use super::to_principal;
+    use crate::backend::{Backend, BackendFuture};
+    use crate::{identity_path, Error, RejectCode, Result, URL};
+
+    /// Get an ic-agent 0.49 agent by identity name, like [`get_agent`](crate::get_agent)
+    pub async fn get_agent(name: impl Into<&str>, url: Option<&str>) -> Result<Agent> {
+        let ident_path = identity_path(name.into())?;
+        if !ident_path.exists() {
+            return Err(Error::CertNotFound(ident_path));
+        }
+        let identity = BasicIdentity::from_pem_file(&ident_path)
+            .map_err(|e| Error::Generic(format!("Invalid identity: {}", e)))?;
+        let agent = Agent::builder()
+            .with_url(url.unwrap_or(URL))
+            .with_identity(identity)
+            .build()?;
+        agent.fetch_root_key().await?;
+        Ok(agent)
+    }
+
+    fn from_agent(err: AgentError, method: &str) -> Error {
+        match err {
+            AgentError::CertifiedReject { reject, .. }
+            | AgentError::UncertifiedReject { reject, .. } => Error::Rejected {
+                code: RejectCode::from(reject.reject_code as u64),
+                message: reject.reject_message,
+                method: method.into(),
+            },
+            err => Error::Agent049(err),
+        }
+    }
+
+    impl Backend for Agent {
+        fn update_call<'a>(
+            &'a self,
+            canister_id: Principal,
+            effective_canister_id: Option<Principal>,
+            method_name: &'a str,
+            arg: Vec<u8>,
+        ) -> BackendFuture<'a, Vec<u8>> {
+            Box::pin(async move {
+                let effective_canister_id = effective_canister_id.unwrap_or(canister_id);
+                Agent::update(self, &to_principal(&canister_id), method_name)
+                    .with_effective_canister_id(to_principal(&effective_canister_id))
+                    .with_arg(arg)
+                    .call_and_wait()
+                    .await
+                    .map_err(|e| from_agent(e, method_name))
+            })
+        }
+
+        fn query_call<'a>(
+            &'a self,
+            canister_id: Principal,
+            effective_canister_id: Option<Principal>,
+            method_name: &'a str,
+            arg: Vec<u8>,
+        ) -> BackendFuture<'a, Vec<u8>> {
+            Box::pin(async move {
+                let effective_canister_id = effective_canister_id.unwrap_or(canister_id);
+                Agent::query(self, &to_principal(&canister_id), method_name)
+                    .with_effective_canister_id(to_principal(&effective_canister_id))
+                    .with_arg(arg)
+                    .call()
+                    .await
+                    .map_err(|e| from_agent(e, method_name))
+            })
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! An agent shared by all the tests of a process.
+//!
+//! Building an agent fetches the root key of the replica, so instead of doing that
+//! in every test, [`TestContext::get`] builds it once for the account given by the
+//! `IC_TEST_ACCOUNT` env var, talking to the replica at `IC_TEST_URL`, and hands
+//! out the same context to every test afterwards. [`Env`] is a shorter name for it.
+//!
+//! Each `#[tokio::test]` runs on its own runtime, and the runtime of the test that
+//! built the context is gone once that test ends. The agent keeps working, as its
+//! transport doesn't keep connections between requests, see
+//! [`transport`](crate::transport). An agent built with a transport from elsewhere has
+//! to do the same.
+//!
+//! ```
+//! # async fn run() {
+//! use ic_test_utils::context::TestContext;
+//!
+//! let context = TestContext::get().await.unwrap();
+//! let wallet = context.wallet().unwrap();
+//! let canister_id = wallet.create_canister(1_000_000_000_000, None).await.unwrap();
+//! # }
+//! ```
+//!
+//! Creating a canister is slow on a local replica. With `IC_TEST_REUSE_CANISTERS=1`, or
+//! [`TestContext::set_reuse`], [`TestContext::teardown`] keeps the canisters deployed
+//! through the context, and the next [`Canister::deploy`](crate::canister::Canister::deploy)
+//! reinstalls one of them instead of creating a new one, which resets its state.
+//!
+//! Base canisters shared by all tests, like a ledger, are set up once as fixtures.
+//! The first test asking for a fixture runs its setup, tests asking at the same time
+//! wait for it, and later tests get the same value:
+//!
+//! ```
+//! # async fn run(wasm: Vec<u8>) {
+//! use ic_test_utils::context::Env;
+//!
+//! let env = Env::get().await.unwrap();
+//! let ledger = env
+//!     .fixture("ledger", || async {
+//!         let wallet = env.wallet()?;
+//!         env.management()
+//!             .deploy(&wallet, &wasm, (), 1_000_000_000_000, None)
+//!             .await
+//!     })
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "wallet")]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use candid::Principal;
+use tokio::sync::OnceCell;
+
+use crate::backend::{Backend, Retry, RetryPolicy};
+#[cfg(feature = "wallet")]
+use crate::canister::WalletCanister;
+use crate::canister::{Canister, ManagementCanister};
+use crate::cleanup::CanisterGuard;
+use crate::harness::{ACCOUNT_ENV_VAR, URL_ENV_VAR};
+use crate::registry::CanisterRegistry;
+use crate::{get_agent, Agent, Error, Result, URL};
+
+/// The account used when `IC_TEST_ACCOUNT` isn't set, the default dfx identity.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Env var turning on the reuse of canisters between tests, unless it is `0` or `false`
+pub const REUSE_ENV_VAR: &str = "IC_TEST_REUSE_CANISTERS";
+
+static CONTEXT: OnceCell<TestContext> = OnceCell::const_new();
+
+/// Short name of [`TestContext`], `Env::get()` is `TestContext::get()`
+pub type Env = TestContext;
+
+type Fixture = Arc<OnceCell<Arc<dyn Any + Send + Sync>>>;
+
+/// The agent shared by the tests of a process, with the root key fetched.
+pub struct TestContext {
+    backend: Retry<Agent>,
+    account_name: String,
+    deployed: Mutex<CanisterRegistry>,
+    reuse: AtomicBool,
+    reusable: Mutex<Vec<Principal>>,
+    #[cfg(feature = "wallet")]
+    wallet_id: OnceLock<Principal>,
+    fixtures: Mutex<HashMap<String, Fixture>>,
+}
+
+impl TestContext {
+    /// The context of this process, built by the first test that asks for it, after
+    /// checking the replica is healthy.
+    /// Without `IC_TEST_URL` it uses the replica dfx runs for the current directory, if
+    /// there is one.
+    /// If building it fails the next test tries again.
+    pub async fn get() -> Result<&'static TestContext> {
+        CONTEXT.get_or_try_init(Self::init).await
+    }
+
+    async fn init() -> Result<Self> {
+        let account_name =
+            std::env::var(ACCOUNT_ENV_VAR).unwrap_or_else(|_| DEFAULT_ACCOUNT.into());
+        let url = std::env::var(URL_ENV_VAR).ok();
+        #[cfg(feature = "dfx-integration")]
+        let url = url.or_else(|| crate::dfx::local_replica_url(".").ok());
+        let agent = get_agent(account_name.as_str(), url.as_deref()).await?;
+        let status = agent.status().await?;
+        if let Some(health) = status
+            .replica_health_status
+            .filter(|health| health != "healthy")
+        {
+            return Err(Error::Generic(format!(
+                "Replica at {} is {}",
+                url.as_deref().unwrap_or(URL),
+                health
+            )));
+        }
+        let reuse =
+            std::env::var(REUSE_ENV_VAR).is_ok_and(|reuse| reuse != "0" && reuse != "false");
+
+        Ok(Self {
+            backend: Retry::new(agent, RetryPolicy::none()),
+            account_name,
+            deployed: Mutex::default(),
+            reuse: AtomicBool::new(reuse),
+            reusable: Mutex::default(),
+            #[cfg(feature = "wallet")]
+            wallet_id: OnceLock::new(),
+            fixtures: Mutex::default(),
+        })
+    }
+
+    /// The agent
+    pub fn agent(&self) -> &Agent {
+        self.backend.inner()
+    }
+
+    /// The agent, making calls by the retry policy of the context
+    pub fn backend(&self) -> &dyn Backend {
+        &self.backend
+    }
+
+    /// Retry the calls made through [`TestContext::backend`], and the canisters of the
+    /// context, by `policy`. Calls are not retried by default.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        self.backend.set_policy(policy);
+    }
+
+    /// The account the agent was built for
+    pub fn account_name(&self) -> &str {
+        &self.account_name
+    }
+
+    /// The wallet of the account, its id is looked up once
+    #[cfg(feature = "wallet")]
+    pub fn wallet(&self) -> Result<WalletCanister<'_>> {
+        if let Some(wallet_id) = self.wallet_id.get() {
+            return Ok(Canister::new(*wallet_id, &self.backend));
+        }
+        let wallet = Canister::new_wallet(&self.backend, &self.account_name, None)?;
+        let _ = self.wallet_id.set(*wallet.principal());
+        Ok(wallet)
+    }
+
+    /// The management canister
+    pub fn management(&self) -> ManagementCanister<'_> {
+        Canister::new_management(&self.backend)
+    }
+
+    /// The canisters deployed through the context, by name,
+    /// see [`Canister::deploy`](crate::canister::Canister::deploy)
+    pub fn registry(&self) -> CanisterRegistry {
+        self.lock().clone()
+    }
+
+    /// Register a canister deployed through the context, to delete it in
+    /// [`TestContext::teardown`]
+    pub fn track(&self, name: &str, canister_id: Principal) {
+        self.lock().register(name, canister_id);
+    }
+
+    /// Stop and delete the canisters deployed through the context, or keep them for
+    /// the next tests when reusing canisters.
+    /// This is best effort, failures are ignored.
+    pub async fn teardown(&self) {
+        let deployed = std::mem::take(&mut *self.lock());
+        for (_, canister_id) in deployed.registered() {
+            if self.reuses() {
+                self.release(canister_id);
+            } else {
+                let _ = CanisterGuard::new(canister_id, self.agent())
+                    .cleanup()
+                    .await;
+            }
+        }
+    }
+
+    /// Whether canisters are reused between tests
+    pub fn reuses(&self) -> bool {
+        self.reuse.load(Ordering::SeqCst)
+    }
+
+    /// Turn the reuse of canisters between tests on or off.
+    /// Canisters kept for reuse so far stay available.
+    pub fn set_reuse(&self, reuse: bool) {
+        self.reuse.store(reuse, Ordering::SeqCst);
+    }
+
+    /// Keep `canister_id` to be reinstalled by a later deploy
+    pub fn release(&self, canister_id: Principal) {
+        let mut reusable = self.lock_reusable();
+        if !reusable.contains(&canister_id) {
+            reusable.push(canister_id);
+        }
+    }
+
+    /// Take a canister kept for reuse, if reusing canisters and there is one
+    pub fn take_reusable(&self) -> Option<Principal> {
+        if !self.reuses() {
+            return None;
+        }
+        self.lock_reusable().pop()
+    }
+
+    /// The number of canisters kept for reuse
+    pub fn reusable(&self) -> usize {
+        self.lock_reusable().len()
+    }
+
+    /// Stop and delete the canisters kept for reuse, e.g. once all tests ran.
+    /// This is best effort, failures are ignored.
+    pub async fn delete_reusable(&self) {
+        let reusable = std::mem::take(&mut *self.lock_reusable());
+        for canister_id in reusable {
+            let _ = CanisterGuard::new(canister_id, self.agent())
+                .cleanup()
+                .await;
+        }
+    }
+
+    /// The fixture `name`, set up by `setup` the first time it is asked for.
+    /// Tests asking while it is set up wait for it. If the setup fails, the next test
+    /// asking tries again.
+    /// Fails if the fixture was set up with another type.
+    ///
+    /// The setup runs on the runtime of the test asking first, so the fixture must not
+    /// hold tasks spawned on it, or connections kept open by it.
+    pub async fn fixture<T, F, Fut>(&self, name: &str, setup: F) -> Result<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let fixture = self
+            .fixtures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(name.into())
+            .or_default()
+            .clone();
+        let value = fixture
+            .get_or_try_init(|| async {
+                let value: Arc<dyn Any + Send + Sync> = Arc::new(setup().await?);
+                Ok::<_, Error>(value)
+            })
+            .await?;
+        value
+            .clone()
+            .downcast()
+            .map_err(|_| Error::Generic(format!("The fixture {} has another type", name)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CanisterRegistry> {
+        self.deployed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_reusable(&self) -> MutexGuard<'_, Vec<Principal>> {
+        self.reusable.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,204 @@
+//! Measure what calls cost, to catch cost regressions of expensive endpoints.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, canister: ic_test_utils::Canister<'_, ()>) {
+//! use candid::Encode;
+//! use ic_test_utils::cost::{measure_cycles, measure_instructions};
+//!
+//! let (reply, cycles): (u64, i128) = measure_cycles(&canister, "expensive", Encode!().unwrap())
+//!     .await
+//!     .unwrap();
+//! assert!(cycles < 10_000_000);
+//!
+//! let (reply, instructions): (u64, u64) =
+//!     measure_instructions(&canister, "expensive", Encode!().unwrap())
+//!         .await
+//!         .unwrap();
+//! assert!(instructions < 1_000_000_000);
+//! # }
+//! ```
+//!
+//! A [`CycleWatch`] checks what a whole scenario costs a set of canisters.
+use candid::{CandidType, Decode, Deserialize, Encode, Nat, Principal};
+
+use crate::{Canister, Error, ManagementCanister, Result};
+
+/// The query a canister exposes for [`measure_instructions`]: it returns the value of
+/// `ic0.performance_counter(0)` recorded at the end of the last update call.
+pub const PERFORMANCE_COUNTER_METHOD: &str = "__get_performance_counter";
+
+/// Make an update call and return the decoded reply, with the cycles the canister
+/// used during the call.
+///
+/// The cost is the difference in the cycle balance of the canister before and after
+/// the call, read with `canister_status`, so the caller has to be a controller.
+/// Cycles received by the call reduce the cost, and on a replica the balance also
+/// drops with the memory the canister uses over time.
+pub async fn measure_cycles<T, R>(
+    canister: &Canister<'_, T>,
+    method_name: &str,
+    arg: Vec<u8>,
+) -> Result<(R, i128)>
+where
+    R: CandidType + for<'de> Deserialize<'de>,
+{
+    let management = Canister::new_management(canister.backend());
+    let canister_id = *canister.principal();
+
+    let before = management.canister_status(canister_id).await?.cycles;
+    let reply = canister.call_update(method_name, arg).await?;
+    let after = management.canister_status(canister_id).await?.cycles;
+
+    let reply = Decode!(&reply, R)?;
+    Ok((reply, to_i128(before)? - to_i128(after)?))
+}
+
+/// Make an update call and return the decoded reply, with the number of instructions
+/// the call executed.
+///
+/// The canister has to record `ic0.performance_counter(0)` at the end of its update
+/// calls, and return it from the [`PERFORMANCE_COUNTER_METHOD`] query as a `nat64`.
+pub async fn measure_instructions<T, R>(
+    canister: &Canister<'_, T>,
+    method_name: &str,
+    arg: Vec<u8>,
+) -> Result<(R, u64)>
+where
+    R: CandidType + for<'de> Deserialize<'de>,
+{
+    let reply = canister.call_update(method_name, arg).await?;
+    let counter = canister
+        .call_query(PERFORMANCE_COUNTER_METHOD, Encode!()?)
+        .await?;
+
+    let reply = Decode!(&reply, R)?;
+    let instructions = Decode!(&counter, u64)?;
+    Ok((reply, instructions))
+}
+
+/// The cycle balances of a set of canisters at the start of a scenario, to check what
+/// each of them used by the end of it.
+///
+/// The balances are read with `canister_status`, so the caller has to be a controller.
+/// Like for [`measure_cycles`], received cycles reduce what a canister used.
+///
+/// ```
+/// # async fn run(management: ic_test_utils::ManagementCanister<'_>, dex: candid::Principal, ledger: candid::Principal) {
+/// use ic_test_utils::cost::CycleWatch;
+///
+/// let watch = CycleWatch::start(&management, &[dex, ledger])
+///     .await
+///     .unwrap()
+///     .limit(dex, 5_000_000_000)
+///     .default_limit(1_000_000_000);
+///
+/// // Run the scenario
+///
+/// let report = watch.finish().await.unwrap();
+/// println!("the dex used {} cycles", report.used_by(dex).unwrap());
+/// # }
+/// ```
+pub struct CycleWatch<'a, 'agent> {
+    management: &'a ManagementCanister<'agent>,
+    balances: Vec<(Principal, i128)>,
+    limits: Vec<(Principal, i128)>,
+    default_limit: Option<i128>,
+}
+
+impl<'a, 'agent> CycleWatch<'a, 'agent> {
+    /// Read the cycle balances of `canisters`. Without limits, [`CycleWatch::finish`]
+    /// only reports what they used.
+    pub async fn start(
+        management: &'a ManagementCanister<'agent>,
+        canisters: &[Principal],
+    ) -> Result<CycleWatch<'a, 'agent>> {
+        let mut balances = Vec::with_capacity(canisters.len());
+        for canister_id in canisters.iter().copied() {
+            balances.push((canister_id, balance(management, canister_id).await?));
+        }
+        Ok(Self {
+            management,
+            balances,
+            limits: Vec::new(),
+            default_limit: None,
+        })
+    }
+
+    /// The most cycles `canister_id` may use
+    pub fn limit(mut self, canister_id: Principal, max_cycles: u128) -> Self {
+        self.limits.push((canister_id, saturate(max_cycles)));
+        self
+    }
+
+    /// The most cycles each canister without its own limit may use
+    pub fn default_limit(mut self, max_cycles: u128) -> Self {
+        self.default_limit = Some(saturate(max_cycles));
+        self
+    }
+
+    /// Read the cycle balances again and return what each canister used.
+    /// Fails naming the canisters that used more than their limit.
+    pub async fn finish(self) -> Result<CycleReport> {
+        let mut used = Vec::with_capacity(self.balances.len());
+        for (canister_id, before) in self.balances.iter().copied() {
+            let after = balance(self.management, canister_id).await?;
+            used.push((canister_id, before - after));
+        }
+        used.sort_by_key(|(_, used)| std::cmp::Reverse(*used));
+
+        let over: Vec<String> = used
+            .iter()
+            .filter_map(|(canister_id, used)| {
+                let limit = self
+                    .limits
+                    .iter()
+                    .find(|(id, _)| id == canister_id)
+                    .map(|(_, limit)| *limit)
+                    .or(self.default_limit)?;
+                (*used > limit).then(|| {
+                    format!(
+                        "{} used {} cycles, expected at most {}",
+                        canister_id, used, limit
+                    )
+                })
+            })
+            .collect();
+        if !over.is_empty() {
+            return Err(Error::Generic(format!(
+                "Canisters used too many cycles: {}",
+                over.join("; ")
+            )));
+        }
+        Ok(CycleReport { used })
+    }
+}
+
+/// The cycles the canisters of a [`CycleWatch`] used
+#[derive(Debug, Clone)]
+pub struct CycleReport {
+    /// The cycles each canister used, the canister using the most first
+    pub used: Vec<(Principal, i128)>,
+}
+
+impl CycleReport {
+    /// The cycles `canister_id` used, if it was watched
+    pub fn used_by(&self, canister_id: Principal) -> Option<i128> {
+        self.used
+            .iter()
+            .find(|(id, _)| *id == canister_id)
+            .map(|(_, used)| *used)
+    }
+}
+
+async fn balance(management: &ManagementCanister<'_>, canister_id: Principal) -> Result<i128> {
+    to_i128(management.canister_status(canister_id).await?.cycles)
+}
+
+fn saturate(cycles: u128) -> i128 {
+    i128::try_from(cycles).unwrap_or(i128::MAX)
+}
+
+pub(crate) fn to_i128(cycles: Nat) -> Result<i128> {
+    i128::try_from(&cycles.0)
+        .map_err(|_| Error::Generic(format!("Cycle balance out of range: {}", cycles)))
+}
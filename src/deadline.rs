@@ -0,0 +1,53 @@
+//! A deadline for a whole test, respected by all waiters and polls.
+//!
+//! Inside [`with_deadline`], [`wait_for`](crate::wait::wait_for), the waiter polling
+//! updates and [`wait::Backoff`](crate::wait::Backoff) give up once the deadline is
+//! reached, instead of each waiting for its own timeout. A [`Scenario`] with a
+//! [`deadline`](crate::scenario::Scenario::deadline) runs its steps within one, and
+//! fails with the step the deadline was exceeded at.
+//!
+//! ```
+//! # async fn run(counter: ic_test_utils::Canister<'_, ()>) {
+//! use std::time::Duration;
+//!
+//! use candid::Encode;
+//! use ic_test_utils::deadline::with_deadline;
+//!
+//! with_deadline(Duration::from_secs(60), async {
+//!     counter.call_update("inc", Encode!().unwrap()).await.unwrap();
+//! })
+//! .await;
+//! # }
+//! ```
+//!
+//! [`Scenario`]: crate::scenario::Scenario
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Run `future` with a deadline `timeout` from now. Within a deadline, the earlier
+/// of the two applies.
+/// The deadline applies to the task running `future`, not to tasks it spawns.
+pub async fn with_deadline<F: Future>(timeout: Duration, future: F) -> F::Output {
+    let deadline = Instant::now() + timeout;
+    let deadline = current().map_or(deadline, |current| current.min(deadline));
+    DEADLINE.scope(deadline, future).await
+}
+
+/// The deadline of the current task, if there is one
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// The time left until the deadline of the current task, if there is one
+pub fn remaining() -> Option<Duration> {
+    current().map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Whether the deadline of the current task has passed
+pub fn exceeded() -> bool {
+    remaining().is_some_and(|remaining| remaining.is_zero())
+}
@@ -0,0 +1,224 @@
+//! Deploy a set of canisters that need each other's principals.
+//!
+//! Each canister of a [`DeployPlan`] names the canisters its init argument needs.
+//! All canisters are created first, then installed in dependency order, with the init
+//! arguments built from the principals of the created canisters.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, ledger_wasm: Vec<u8>, backend_wasm: Vec<u8>) {
+//! use candid::Encode;
+//! use ic_test_utils::deploy::DeployPlan;
+//! use ic_test_utils::Canister;
+//!
+//! let wallet = Canister::new_wallet(agent, "alice", None).unwrap();
+//! let registry = DeployPlan::new()
+//!     .canister("ledger", ledger_wasm, &[], |_| Ok(Encode!()?))
+//!     .canister("backend", backend_wasm, &["ledger"], |ids| {
+//!         Ok(Encode!(&ids.resolve("ledger")?)?)
+//!     })
+//!     .deploy(&wallet, 1_000_000_000_000)
+//!     .await
+//!     .unwrap();
+//! let backend = registry.resolve("backend").unwrap();
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use candid::Principal;
+use futures::stream::{self, StreamExt};
+
+use crate::canister::{Canister, InstallMode, WalletCanister};
+use crate::registry::CanisterRegistry;
+use crate::{Error, Result};
+
+/// How many canisters [`install_many`] installs at the same time
+pub const MAX_CONCURRENT_INSTALLS: usize = 8;
+
+type InitArg = Box<dyn Fn(&CanisterRegistry) -> Result<Vec<u8>> + Send + Sync>;
+
+struct Planned {
+    wasm: Vec<u8>,
+    cycles: Option<u64>,
+    controllers: Option<Vec<Principal>>,
+    dependencies: Vec<String>,
+    init_arg: InitArg,
+}
+
+/// The canisters to deploy, with their dependencies.
+#[derive(Default)]
+pub struct DeployPlan {
+    canisters: BTreeMap<String, Planned>,
+}
+
+impl DeployPlan {
+    /// An empty plan
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the canister `name`, installed with `wasm` and the candid encoded argument
+    /// returned by `init_arg`. The registry passed to `init_arg` holds the principals
+    /// of all the canisters of the plan.
+    /// The canister is installed after the canisters in `dependencies`.
+    pub fn canister<F>(
+        mut self,
+        name: impl Into<String>,
+        wasm: Vec<u8>,
+        dependencies: &[&str],
+        init_arg: F,
+    ) -> Self
+    where
+        F: Fn(&CanisterRegistry) -> Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.canisters.insert(
+            name.into(),
+            Planned {
+                wasm,
+                cycles: None,
+                controllers: None,
+                dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+                init_arg: Box::new(init_arg),
+            },
+        );
+        self
+    }
+
+    /// Create the canister `name` with `cycles` instead of the cycles given to deploy
+    pub fn cycles(mut self, name: &str, cycles: u64) -> Self {
+        if let Some(planned) = self.canisters.get_mut(name) {
+            planned.cycles = Some(cycles);
+        }
+        self
+    }
+
+    /// Create the canister `name` with these controllers instead of the wallet.
+    /// The wallet has to be one of them to install the canister.
+    pub fn controllers(mut self, name: &str, controllers: Vec<Principal>) -> Self {
+        if let Some(planned) = self.canisters.get_mut(name) {
+            planned.controllers = Some(controllers);
+        }
+        self
+    }
+
+    /// The order the canisters are installed in: each canister after its dependencies.
+    pub fn install_order(&self) -> Result<Vec<&str>> {
+        for (name, planned) in &self.canisters {
+            if let Some(missing) = planned
+                .dependencies
+                .iter()
+                .find(|dep| !self.canisters.contains_key(*dep))
+            {
+                return Err(Error::Generic(format!(
+                    "Canister {} depends on unknown canister {}",
+                    name, missing
+                )));
+            }
+        }
+
+        let mut order: Vec<&str> = Vec::with_capacity(self.canisters.len());
+        while order.len() < self.canisters.len() {
+            let next = self.canisters.iter().find(|(name, planned)| {
+                !order.contains(&name.as_str())
+                    && planned
+                        .dependencies
+                        .iter()
+                        .all(|dep| order.contains(&dep.as_str()))
+            });
+            match next {
+                Some((name, _)) => order.push(name),
+                None => {
+                    let cycle: Vec<&str> = self
+                        .canisters
+                        .keys()
+                        .map(String::as_str)
+                        .filter(|name| !order.contains(name))
+                        .collect();
+                    return Err(Error::Generic(format!(
+                        "Dependency cycle between canisters {}",
+                        cycle.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Create all the canisters with `cycles` each, unless set per canister, and install them in dependency order.
+    /// Returns a registry with the principals of the deployed canisters.
+    pub async fn deploy(
+        self,
+        wallet: &WalletCanister<'_>,
+        cycles: u64,
+    ) -> Result<CanisterRegistry> {
+        let order = self.install_order()?;
+
+        let mut registry = CanisterRegistry::new();
+        for name in &order {
+            let planned = &self.canisters[*name];
+            let canister_id = wallet
+                .create_canister(
+                    planned.cycles.unwrap_or(cycles),
+                    planned.controllers.clone(),
+                )
+                .await?;
+            registry.register(*name, canister_id);
+        }
+
+        let management = Canister::new_management(wallet.backend());
+        for name in order {
+            let planned = &self.canisters[name];
+            let arg = (planned.init_arg)(&registry)?;
+            management
+                .install_raw(
+                    registry.resolve(name)?,
+                    &planned.wasm,
+                    InstallMode::Install,
+                    &arg,
+                )
+                .await?;
+        }
+        Ok(registry)
+    }
+}
+
+/// Install each `(canister, wasm, candid encoded argument)` of `installs`, up to
+/// [`MAX_CONCURRENT_INSTALLS`] at the same time, through the backend of `wallet`.
+///
+/// Returns the result of each install in the order of `installs`, so one failed install
+/// doesn't hide the others.
+/// The modules are shared, installing the same module on many canisters doesn't copy it.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, canisters: Vec<candid::Principal>, wasm: std::sync::Arc<[u8]>) {
+/// use candid::Encode;
+/// use ic_test_utils::deploy::install_many;
+/// use ic_test_utils::Canister;
+///
+/// let wallet = Canister::new_wallet(agent, "alice", None).unwrap();
+/// let installs = canisters
+///     .iter()
+///     .map(|canister| (*canister, wasm.clone(), Encode!().unwrap()))
+///     .collect();
+/// for (canister, result) in install_many(&wallet, installs).await {
+///     result.unwrap();
+/// }
+/// # }
+/// ```
+pub async fn install_many(
+    wallet: &WalletCanister<'_>,
+    installs: Vec<(Principal, Arc<[u8]>, Vec<u8>)>,
+) -> Vec<(Principal, Result<()>)> {
+    let management = Canister::new_management(wallet.backend());
+    let management = &management;
+    stream::iter(installs)
+        .map(|(canister_id, wasm, arg)| async move {
+            let result = management
+                .install_raw(canister_id, &wasm, InstallMode::Install, &arg)
+                .await;
+            (canister_id, result)
+        })
+        .buffered(MAX_CONCURRENT_INSTALLS)
+        .collect()
+        .await
+}
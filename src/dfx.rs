@@ -0,0 +1,290 @@
+//! Find the build artifacts of the canisters of a dfx project.
+//!
+//! [`DfxProject`] reads the `dfx.json` of a project and resolves the wasm module
+//! and candid file of each canister, so tests only need the canister name.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent) {
+//! use ic_test_utils::create_canister;
+//! use ic_test_utils::dfx::DfxProject;
+//!
+//! let project = DfxProject::load(".").unwrap();
+//! let wasm = project.wasm("backend").unwrap();
+//! let canister = create_canister(agent, "alice", wasm, (), 1_000_000_000_000).await.unwrap();
+//! # }
+//! ```
+//!
+//! [`CanisterIds`] reads and writes the `canister_ids.json` of a network, to use
+//! canisters deployed with dfx and record the ones created by a test.
+//!
+//! ```
+//! # fn run(created: candid::Principal) {
+//! use ic_test_utils::dfx::CanisterIds;
+//!
+//! let mut ids = CanisterIds::load(".", "ic").unwrap();
+//! let ledger = ids.get("ledger").unwrap();
+//! ids.set("backend", created);
+//! ids.save().unwrap();
+//! # }
+//! ```
+//!
+//! [`local_replica_url`] finds the port of the replica dfx started for a project,
+//! for tests that run against whatever port dfx chose.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use candid::Principal;
+use serde::Deserialize;
+
+use crate::wasm::{load_wasm, WASM_TARGET};
+use crate::{Error, Result};
+
+/// The network dfx builds for by default
+pub const LOCAL_NETWORK: &str = "local";
+
+/// A canister in `dfx.json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct DfxCanister {
+    /// `rust`, `motoko`, `custom`, `assets` etc.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// The cargo package of a rust canister
+    pub package: Option<String>,
+    /// The wasm module of a custom canister
+    pub wasm: Option<PathBuf>,
+    /// The candid file
+    pub candid: Option<PathBuf>,
+    /// The canisters this canister depends on
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DfxJson {
+    #[serde(default)]
+    canisters: BTreeMap<String, DfxCanister>,
+}
+
+/// The build artifacts of a canister
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanisterArtifacts {
+    /// The wasm module, raw or gzip compressed
+    pub wasm: PathBuf,
+    /// The candid file, if known
+    pub candid: Option<PathBuf>,
+}
+
+/// A dfx project
+#[derive(Debug, Clone)]
+pub struct DfxProject {
+    root: PathBuf,
+    canisters: BTreeMap<String, DfxCanister>,
+}
+
+impl DfxProject {
+    /// Read the `dfx.json` in `project_dir`
+    pub fn load(project_dir: impl AsRef<Path>) -> Result<Self> {
+        let root = project_dir.as_ref().to_path_buf();
+        let json = std::fs::read(root.join("dfx.json"))?;
+        let DfxJson { canisters } = serde_json::from_slice(&json)?;
+        Ok(Self { root, canisters })
+    }
+
+    /// The project directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The names of the canisters
+    pub fn canister_names(&self) -> impl Iterator<Item = &str> {
+        self.canisters.keys().map(String::as_str)
+    }
+
+    /// The canister `name` as declared in `dfx.json`
+    pub fn canister(&self, name: &str) -> Result<&DfxCanister> {
+        self.canisters
+            .get(name)
+            .ok_or_else(|| Error::UnknownCanister(name.into()))
+    }
+
+    /// Where the build artifacts of the canister `name` are.
+    ///
+    /// A rust canister is built by cargo into the `target` directory of the project,
+    /// a custom canister declares its wasm. Other canisters, like the motoko ones,
+    /// are built by dfx into `.dfx/local/canisters/<name>`.
+    pub fn artifacts(&self, name: &str) -> Result<CanisterArtifacts> {
+        let canister = self.canister(name)?;
+        let candid = canister
+            .candid
+            .as_ref()
+            .map(|candid| self.root.join(candid));
+        let dfx_dir = self
+            .root
+            .join(".dfx")
+            .join(LOCAL_NETWORK)
+            .join("canisters")
+            .join(name);
+
+        let wasm = match (canister.kind.as_deref(), &canister.wasm) {
+            (Some("rust"), _) => {
+                let package = canister.package.as_deref().unwrap_or(name);
+                self.root
+                    .join("target")
+                    .join(WASM_TARGET)
+                    .join("release")
+                    .join(format!("{}.wasm", package.replace('-', "_")))
+            }
+            (_, Some(wasm)) => self.root.join(wasm),
+            _ => dfx_dir.join(format!("{}.wasm", name)),
+        };
+        let candid = candid.or_else(|| {
+            let did = dfx_dir.join(format!("{}.did", name));
+            did.exists().then_some(did)
+        });
+
+        Ok(CanisterArtifacts { wasm, candid })
+    }
+
+    /// Read the wasm module of the canister `name`
+    pub fn wasm(&self, name: &str) -> Result<Vec<u8>> {
+        load_wasm(self.artifacts(name)?.wasm)
+    }
+
+    /// Read the candid file of the canister `name`
+    pub fn candid(&self, name: &str) -> Result<String> {
+        let path = self
+            .artifacts(name)?
+            .candid
+            .ok_or_else(|| Error::Generic(format!("No candid file for canister {}", name)))?;
+        Ok(std::fs::read_to_string(path)?)
+    }
+}
+
+/// The ids of the canisters of a project on one network, from `canister_ids.json`.
+#[derive(Debug, Clone)]
+pub struct CanisterIds {
+    path: PathBuf,
+    network: String,
+    ids: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl CanisterIds {
+    /// Read the canister ids of `network` in the project at `project_dir`.
+    ///
+    /// Like dfx, the ids of the local network are in `.dfx/local/canister_ids.json`,
+    /// and the ids of other networks in the `canister_ids.json` of the project.
+    /// A missing file is treated as empty.
+    pub fn load(project_dir: impl AsRef<Path>, network: &str) -> Result<Self> {
+        let path = if network == LOCAL_NETWORK {
+            project_dir
+                .as_ref()
+                .join(".dfx")
+                .join(LOCAL_NETWORK)
+                .join("canister_ids.json")
+        } else {
+            project_dir.as_ref().join("canister_ids.json")
+        };
+        let ids = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            network: network.into(),
+            ids,
+        })
+    }
+
+    /// The id of the canister `name`
+    pub fn get(&self, name: &str) -> Result<Principal> {
+        let id = self
+            .ids
+            .get(name)
+            .and_then(|networks| networks.get(&self.network))
+            .ok_or_else(|| Error::UnknownCanister(name.into()))?;
+        Ok(Principal::from_text(id)?)
+    }
+
+    /// The names and ids of all canisters on the network
+    pub fn all(&self) -> Result<Vec<(String, Principal)>> {
+        self.ids
+            .iter()
+            .filter_map(|(name, networks)| {
+                let id = networks.get(&self.network)?;
+                Some(Principal::from_text(id).map(|id| (name.clone(), id)))
+            })
+            .collect::<std::result::Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Record the id of the canister `name`, call [`CanisterIds::save`] to write it
+    pub fn set(&mut self, name: &str, canister_id: Principal) {
+        self.ids
+            .entry(name.into())
+            .or_default()
+            .insert(self.network.clone(), canister_id.to_text());
+    }
+
+    /// Write the ids back to `canister_ids.json`, keeping the ids of other networks
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&self.ids)?)?;
+        Ok(())
+    }
+}
+
+/// The url of the local replica dfx runs for the project at `project_dir`, see
+/// [`local_replica_port`].
+pub fn local_replica_url(project_dir: impl AsRef<Path>) -> Result<String> {
+    Ok(format!(
+        "http://127.0.0.1:{}",
+        local_replica_port(project_dir)?
+    ))
+}
+
+/// The port of the local replica dfx runs for the project at `project_dir`, read from
+/// the `webserver-port` file dfx writes when it starts.
+///
+/// The file is looked for in `.dfx/network/local` of the project, in `.dfx` for older
+/// versions of dfx, and in the shared local network of newer versions, in the dfx data
+/// directory of the user.
+pub fn local_replica_port(project_dir: impl AsRef<Path>) -> Result<u16> {
+    let dfx_dir = project_dir.as_ref().join(".dfx");
+    let mut paths = vec![dfx_dir.join("network").join(LOCAL_NETWORK), dfx_dir.clone()];
+    if let Some(data_dir) = dirs::data_local_dir() {
+        let dfx_data_dir = if cfg!(target_os = "macos") {
+            "org.dfinity.dfx"
+        } else {
+            "dfx"
+        };
+        paths.push(
+            data_dir
+                .join(dfx_data_dir)
+                .join("network")
+                .join(LOCAL_NETWORK),
+        );
+    }
+
+    for path in &paths {
+        let port = match std::fs::read_to_string(path.join("webserver-port")) {
+            Ok(port) => port,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        match port.trim().parse() {
+            Ok(0) | Err(_) => continue,
+            Ok(port) => return Ok(port),
+        }
+    }
+    Err(Error::Generic(format!(
+        "No port of a local dfx replica in {}",
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )))
+}
@@ -0,0 +1,297 @@
+//! Compare captured states of canisters, for before and after assertions.
+//!
+//! A [`State`] holds values by path, like `settings.controllers[0]`. It is captured from
+//! anything with a candid type, like a [`CanisterStatus`](crate::canister::CanisterStatus),
+//! from a candid reply, from JSON, or filled in by hand, e.g. with token balances.
+//! [`diff`] compares two states and renders what changed. Blobs captured from candid
+//! values are single hex values, so a changed `module_hash` shows up as one change.
+//!
+//! ```
+//! # async fn run(management: ic_test_utils::ManagementCanister<'_>, ledger: ic_test_utils::LedgerCanister<'_>, counter: candid::Principal, alice: ic_test_utils::canister::AccountIdentifier) {
+//! use ic_test_utils::diff::{diff, State};
+//!
+//! let capture = || async {
+//!     let status = management.canister_status(counter).await.unwrap();
+//!     let balance = ledger.account_balance(&alice).await.unwrap();
+//!     State::from_value(&status)
+//!         .unwrap()
+//!         .with("alice", balance.e8s)
+//! };
+//!
+//! let before = capture().await;
+//! // Run the operation
+//! let after = capture().await;
+//!
+//! let changes = diff(&before, &after);
+//! assert!(changes.get("alice").is_some(), "{}", changes);
+//! assert!(changes.get("module_hash").is_none(), "{}", changes);
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use candid::parser::value::{IDLField, IDLValue, VariantValue};
+use candid::{CandidType, Encode, IDLArgs, TypeEnv};
+use serde_json::Value;
+
+use crate::interface::parse_service;
+use crate::json::value_to_json;
+use crate::Result;
+
+/// Captured values by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct State {
+    values: BTreeMap<String, String>,
+}
+
+impl State {
+    /// A state without values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The state with `value` at `path`
+    pub fn with(mut self, path: impl Into<String>, value: impl Display) -> Self {
+        self.insert(path, value);
+        self
+    }
+
+    /// Set the value at `path`
+    pub fn insert(&mut self, path: impl Into<String>, value: impl Display) {
+        self.values.insert(path.into(), value.to_string());
+    }
+
+    /// The state with the values of `other`, their paths prefixed with `prefix` and a dot,
+    /// to combine the states of several canisters
+    pub fn with_state(mut self, prefix: &str, other: State) -> Self {
+        for (path, value) in other.values {
+            self.values.insert(join(prefix, &path), value);
+        }
+        self
+    }
+
+    /// The value at `path`
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.values.get(path).map(String::as_str)
+    }
+
+    /// The values of `json`, objects and arrays are flattened into the paths
+    pub fn from_json(json: &Value) -> Self {
+        let mut state = Self::new();
+        flatten(&mut state.values, String::new(), json);
+        state
+    }
+
+    /// The values of `value`, rendered like [`crate::json`] renders candid values,
+    /// except for blobs which are hex encoded
+    pub fn from_value<T: CandidType>(value: &T) -> Result<Self> {
+        let bytes = Encode!(value)?;
+        let args = IDLArgs::from_bytes_with_types(&bytes, &TypeEnv::new(), &[T::ty()])?;
+        Ok(Self::from_json(&value_to_json(&blobs_to_hex(
+            &args.args[0],
+        ))))
+    }
+
+    /// The values of `reply`, decoded with the return types of `method` in `did`, with
+    /// blobs hex encoded.
+    /// A single return value is at the top level, several are at `[0]`, `[1]` and so on.
+    pub fn from_reply(reply: &[u8], did: &str, method: &str) -> Result<Self> {
+        let (env, service) = parse_service(did)?;
+        let func = env.get_method(&service, method)?;
+        let args = IDLArgs::from_bytes_with_types(reply, &env, &func.rets)?;
+        match args.args.as_slice() {
+            [value] => Ok(Self::from_json(&value_to_json(&blobs_to_hex(value)))),
+            values => Ok(Self::from_json(&Value::Array(
+                values
+                    .iter()
+                    .map(|value| value_to_json(&blobs_to_hex(value)))
+                    .collect(),
+            ))),
+        }
+    }
+}
+
+/// `value` with the non-empty vectors of `nat8` replaced by their hex encoding
+fn blobs_to_hex(value: &IDLValue) -> IDLValue {
+    match value {
+        IDLValue::Vec(items) if !items.is_empty() => {
+            let bytes: Option<Vec<u8>> = items
+                .iter()
+                .map(|item| match item {
+                    IDLValue::Nat8(byte) => Some(*byte),
+                    _ => None,
+                })
+                .collect();
+            match bytes {
+                Some(bytes) => IDLValue::Text(hex::encode(bytes)),
+                None => IDLValue::Vec(items.iter().map(blobs_to_hex).collect()),
+            }
+        }
+        IDLValue::Opt(value) => IDLValue::Opt(Box::new(blobs_to_hex(value))),
+        IDLValue::Record(fields) => {
+            IDLValue::Record(fields.iter().map(field_blobs_to_hex).collect())
+        }
+        IDLValue::Variant(VariantValue(field, index)) => {
+            IDLValue::Variant(VariantValue(Box::new(field_blobs_to_hex(field)), *index))
+        }
+        value => value.clone(),
+    }
+}
+
+fn field_blobs_to_hex(field: &IDLField) -> IDLField {
+    IDLField {
+        id: field.id.clone(),
+        val: blobs_to_hex(&field.val),
+    }
+}
+
+fn join(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.into(),
+        (_, true) => prefix.into(),
+        _ if path.starts_with('[') => format!("{}{}", prefix, path),
+        _ => format!("{}.{}", prefix, path),
+    }
+}
+
+fn flatten(values: &mut BTreeMap<String, String>, path: String, json: &Value) {
+    match json {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                flatten(values, join(&path, key), value);
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(values, format!("{}[{}]", path, index), item);
+            }
+        }
+        Value::String(text) => {
+            values.insert(path, text.clone());
+        }
+        value => {
+            values.insert(path, value.to_string());
+        }
+    }
+}
+
+/// A changed value, see [`diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A value only the later state has
+    Added {
+        /// The path of the value
+        path: String,
+        /// The value
+        value: String,
+    },
+    /// A value only the earlier state has
+    Removed {
+        /// The path of the value
+        path: String,
+        /// The value
+        value: String,
+    },
+    /// A value both states have, but with different values
+    Changed {
+        /// The path of the value
+        path: String,
+        /// The value in the earlier state
+        before: String,
+        /// The value in the later state
+        after: String,
+    },
+}
+
+impl Change {
+    /// The path of the changed value
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Added { path, .. } | Self::Removed { path, .. } | Self::Changed { path, .. } => {
+                path
+            }
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            Self::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            Self::Changed {
+                path,
+                before,
+                after,
+            } => write!(f, "~ {}: {}, now {}", path, before, after),
+        }
+    }
+}
+
+/// The changes between two states, ordered by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The changed values
+    pub changes: Vec<Change>,
+}
+
+impl StateDiff {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The paths of the changed values
+    pub fn paths(&self) -> Vec<&str> {
+        self.changes.iter().map(Change::path).collect()
+    }
+
+    /// The change at `path`, if the value changed
+    pub fn get(&self, path: &str) -> Option<&Change> {
+        self.changes.iter().find(|change| change.path() == path)
+    }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// What changed from `before` to `after`
+pub fn diff(before: &State, after: &State) -> StateDiff {
+    let mut changes = Vec::new();
+    for (path, value) in &before.values {
+        match after.values.get(path) {
+            None => changes.push(Change::Removed {
+                path: path.clone(),
+                value: value.clone(),
+            }),
+            Some(now) if now != value => changes.push(Change::Changed {
+                path: path.clone(),
+                before: value.clone(),
+                after: now.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (path, value) in &after.values {
+        if !before.values.contains_key(path) {
+            changes.push(Change::Added {
+                path: path.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    StateDiff { changes }
+}
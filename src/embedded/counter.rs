@@ -0,0 +1,149 @@
+//! A counter keeping its value in stable memory, for install, upgrade and snapshot tests.
+use candid::{Decode, Encode, Principal};
+
+use super::encoder::{Code, Module, I32, I64};
+use crate::backend::Backend;
+use crate::{Canister, Result};
+
+/// The header of the candid reply, `(nat64)`
+const HEADER: u32 = 16;
+const HEADER_BYTES: &[u8] = b"DIDL\0\x01\x78";
+/// The value, read from the first 8 bytes of the stable memory
+const VALUE: u32 = 32;
+
+/// The wasm module of the counter canister, it is installed without an argument.
+///
+/// Its `increment` update method increments the value and replies with it, its `get`
+/// query method replies with the value, a `nat64` starting at zero. The value is kept in
+/// stable memory, so it survives upgrades and is restored with a snapshot.
+pub fn counter_wasm() -> Vec<u8> {
+    let mut module = Module::new(1);
+    let stable_size = module.import("stable64_size", &[], &[I64]);
+    let stable_grow = module.import("stable64_grow", &[I64], &[I64]);
+    let stable_read = module.import("stable64_read", &[I64, I64, I64], &[]);
+    let stable_write = module.import("stable64_write", &[I64, I64, I64], &[]);
+    let reply_append = module.import("msg_reply_data_append", &[I32, I32], &[]);
+    let reply = module.import("msg_reply", &[], &[]);
+
+    // Read the value from the stable memory, it is zero until the memory is grown
+    let load = module.function(
+        &[],
+        &[],
+        &[],
+        Code::new()
+            .call(stable_size)
+            .i64_eqz()
+            .if_()
+            .i32_const(VALUE as i32)
+            .i64_const(0)
+            .i64_store(0)
+            .else_()
+            .i64_const(VALUE as i64)
+            .i64_const(0)
+            .i64_const(8)
+            .call(stable_read)
+            .end(),
+    );
+
+    let respond = module.function(
+        &[],
+        &[],
+        &[],
+        Code::new()
+            .i32_const(HEADER as i32)
+            .i32_const(HEADER_BYTES.len() as i32)
+            .call(reply_append)
+            .i32_const(VALUE as i32)
+            .i32_const(8)
+            .call(reply_append)
+            .call(reply),
+    );
+
+    let increment = module.function(
+        &[],
+        &[],
+        &[],
+        Code::new()
+            .call(load)
+            .i32_const(VALUE as i32)
+            .i32_const(VALUE as i32)
+            .i64_load(0)
+            .i64_const(1)
+            .i64_add()
+            .i64_store(0)
+            .call(stable_size)
+            .i64_eqz()
+            .if_()
+            .i64_const(1)
+            .call(stable_grow)
+            .drop()
+            .end()
+            .i64_const(0)
+            .i64_const(VALUE as i64)
+            .i64_const(8)
+            .call(stable_write)
+            .call(respond),
+    );
+    module.export("canister_update increment", increment);
+
+    let get = module.function(&[], &[], &[], Code::new().call(load).call(respond));
+    module.export("canister_query get", get);
+
+    module.data(HEADER, HEADER_BYTES);
+    module.encode()
+}
+
+/// A canister running the [`counter_wasm`] module.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent) {
+/// use ic_test_utils::canister::CanisterBuilder;
+/// use ic_test_utils::embedded::{counter_wasm, Counter};
+/// use ic_test_utils::{Canister, ManagementCanister};
+///
+/// // Controlled by the agent, to upgrade it through the management canister
+/// let counter: Canister<'_, Counter> = CanisterBuilder::new(agent)
+///     .with_controllers(vec![agent.get_principal().unwrap()])
+///     .with_wasm_bytes(counter_wasm())
+///     .deploy()
+///     .await
+///     .unwrap();
+/// counter.increment().await.unwrap();
+///
+/// ManagementCanister::new_management(agent)
+///     .upgrade_code_no_arg(*counter.principal(), counter_wasm())
+///     .await
+///     .unwrap();
+/// assert_eq!(counter.get().await.unwrap(), 1);
+/// # }
+/// ```
+pub struct Counter;
+
+impl<'agent> Canister<'agent, Counter> {
+    /// The counter canister `canister_id`
+    pub fn new_counter(backend: &'agent dyn Backend, canister_id: Principal) -> Self {
+        Self::new(canister_id, backend)
+    }
+
+    /// Create a counter canister through the wallet of the default account and install
+    /// the module, see [`crate::canister::CanisterBuilder`]
+    #[cfg(feature = "wallet")]
+    pub async fn deploy_counter(backend: &'agent dyn Backend) -> Result<Self> {
+        crate::canister::CanisterBuilder::new(backend)
+            .with_wasm_bytes(counter_wasm())
+            .deploy()
+            .await
+    }
+
+    /// Increment the value and return it
+    pub async fn increment(&self) -> Result<u64> {
+        let reply = self.call_update("increment", Encode!()?).await?;
+        Ok(Decode!(&reply, u64)?)
+    }
+
+    /// The value
+    pub async fn get(&self) -> Result<u64> {
+        let reply = self.call_query("get", Encode!()?).await?;
+        Ok(Decode!(&reply, u64)?)
+    }
+}
@@ -0,0 +1,192 @@
+//! A canister replying with what it got: the caller, the cycles and the argument.
+use candid::{Decode, Principal};
+
+use super::encoder::{Code, Module, I32, I64};
+use crate::backend::Backend;
+use crate::{Canister, Result};
+
+/// The header of the candid reply, `(principal, nat64, blob)`
+const HEADER: u32 = 16;
+const HEADER_BYTES: &[u8] = b"DIDL\x01\x6d\x7b\x03\x68\x78\x00";
+/// The caller as a candid principal: the reference flag, the length and the bytes
+const CALLER: u32 = 64;
+/// The accepted cycles, as a 16 byte little endian number
+const CYCLES: u32 = 96;
+/// The length of the argument, as a LEB128 number
+const ARG_LEN: u32 = 128;
+/// The argument is copied from here on
+const ARG: u32 = 256;
+
+/// The wasm module of the echo canister, it is installed without an argument.
+///
+/// Its `echo` update method accepts the cycles sent with the call, up to `u64::MAX`,
+/// and replies with the caller, the accepted cycles and the raw argument.
+pub fn echo_wasm() -> Vec<u8> {
+    let mut module = Module::new(1);
+    let arg_size = module.import("msg_arg_data_size", &[], &[I32]);
+    let arg_copy = module.import("msg_arg_data_copy", &[I32, I32, I32], &[]);
+    let caller_size = module.import("msg_caller_size", &[], &[I32]);
+    let caller_copy = module.import("msg_caller_copy", &[I32, I32, I32], &[]);
+    let cycles_accept = module.import("msg_cycles_accept128", &[I64, I64, I32], &[]);
+    let reply_append = module.import("msg_reply_data_append", &[I32, I32], &[]);
+    let reply = module.import("msg_reply", &[], &[]);
+    let grow_to = module.grow_to();
+
+    let echo = module.function(
+        &[],
+        &[],
+        &[I32, I32, I32],
+        // local 0: size, 1: LEB128 output, 2: LEB128 rest
+        Code::new()
+            .i32_const(HEADER as i32)
+            .i32_const(HEADER_BYTES.len() as i32)
+            .call(reply_append)
+            // The caller
+            .call(caller_size)
+            .local_set(0)
+            .i32_const(CALLER as i32)
+            .i32_const(1)
+            .i32_store8(0)
+            .i32_const(CALLER as i32)
+            .local_get(0)
+            .i32_store8(1)
+            .i32_const(CALLER as i32 + 2)
+            .i32_const(0)
+            .local_get(0)
+            .call(caller_copy)
+            .i32_const(CALLER as i32)
+            .local_get(0)
+            .i32_const(2)
+            .i32_add()
+            .call(reply_append)
+            // The cycles
+            .i64_const(0)
+            .i64_const(-1)
+            .i32_const(CYCLES as i32)
+            .call(cycles_accept)
+            .i32_const(CYCLES as i32)
+            .i32_const(8)
+            .call(reply_append)
+            // The length of the argument
+            .call(arg_size)
+            .local_tee(0)
+            .local_set(2)
+            .i32_const(ARG_LEN as i32)
+            .local_set(1)
+            .loop_()
+            .local_get(1)
+            .local_get(2)
+            .i32_const(0x7f)
+            .i32_and()
+            .local_get(2)
+            .i32_const(7)
+            .i32_shr_u()
+            .local_tee(2)
+            .i32_const(0)
+            .i32_gt_u()
+            .i32_const(7)
+            .i32_shl()
+            .i32_or()
+            .i32_store8(0)
+            .local_get(1)
+            .i32_const(1)
+            .i32_add()
+            .local_set(1)
+            .local_get(2)
+            .br_if(0)
+            .end()
+            .i32_const(ARG_LEN as i32)
+            .local_get(1)
+            .i32_const(ARG_LEN as i32)
+            .i32_sub()
+            .call(reply_append)
+            // The argument
+            .i32_const(ARG as i32)
+            .local_get(0)
+            .i32_add()
+            .call(grow_to)
+            .i32_const(ARG as i32)
+            .i32_const(0)
+            .local_get(0)
+            .call(arg_copy)
+            .i32_const(ARG as i32)
+            .local_get(0)
+            .call(reply_append)
+            .call(reply),
+    );
+    module.export("canister_update echo", echo);
+    module.data(HEADER, HEADER_BYTES);
+    module.encode()
+}
+
+/// What the echo canister got with a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoReply {
+    /// The caller
+    pub caller: Principal,
+    /// The cycles sent with the call
+    pub cycles: u64,
+    /// The raw argument
+    pub arg: Vec<u8>,
+}
+
+impl EchoReply {
+    /// Decode the reply of the `echo` method, as a canister calling it would get it
+    pub fn decode(reply: &[u8]) -> Result<Self> {
+        let (caller, cycles, arg) = Decode!(reply, Principal, u64, serde_bytes::ByteBuf)?;
+        Ok(Self {
+            caller,
+            cycles,
+            arg: arg.into_vec(),
+        })
+    }
+}
+
+/// A canister running the [`echo_wasm`] module.
+///
+/// A test calls the canister under test, which calls the echo canister, and checks
+/// what it forwarded from the reply it got back.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, proxy: ic_test_utils::Canister<'_, ()>) {
+/// use candid::{Decode, Encode};
+/// use ic_test_utils::embedded::{Echo, EchoReply};
+/// use ic_test_utils::Canister;
+///
+/// let echo = Canister::<Echo>::deploy_echo(agent).await.unwrap();
+///
+/// // The canister under test forwards the argument and 1000 cycles to `echo`
+/// // and replies with its reply
+/// let reply = proxy
+///     .call_update("forward", Encode!(echo.principal(), &b"ping".to_vec()).unwrap())
+///     .await
+///     .unwrap();
+/// let reply = EchoReply::decode(&Decode!(&reply, Vec<u8>).unwrap()).unwrap();
+/// assert_eq!(reply.caller, *proxy.principal());
+/// assert_eq!(reply.cycles, 1000);
+/// # }
+/// ```
+pub struct Echo;
+
+impl<'agent> Canister<'agent, Echo> {
+    /// The echo canister `canister_id`
+    pub fn new_echo(backend: &'agent dyn Backend, canister_id: Principal) -> Self {
+        Self::new(canister_id, backend)
+    }
+
+    /// Create an echo canister through the wallet of the default account and install
+    /// the module, see [`crate::canister::CanisterBuilder`]
+    #[cfg(feature = "wallet")]
+    pub async fn deploy_echo(backend: &'agent dyn Backend) -> Result<Self> {
+        crate::canister::CanisterBuilder::new(backend)
+            .with_wasm_bytes(echo_wasm())
+            .deploy()
+            .await
+    }
+
+    /// Call `echo` with the raw `arg`
+    pub async fn echo(&self, arg: Vec<u8>) -> Result<EchoReply> {
+        let reply = self.call_update("echo", arg).await?;
+        EchoReply::decode(&reply)
+    }
+}
@@ -0,0 +1,362 @@
+//! Just enough of a wasm encoder for the embedded canisters.
+//!
+//! Functions are written as raw instructions, the module takes care of the sections,
+//! the index spaces and the encoding of the numbers.
+
+pub(super) const I32: u8 = 0x7f;
+pub(super) const I64: u8 = 0x7e;
+
+/// The size of a wasm page
+pub(super) const PAGE_SIZE: u32 = 65536;
+
+/// A function type, its parameter and result types
+type FuncType = (Vec<u8>, Vec<u8>);
+
+/// A wasm module with a single memory
+#[derive(Default)]
+pub(super) struct Module {
+    types: Vec<FuncType>,
+    imports: Vec<(&'static str, &'static str, u32)>,
+    functions: Vec<(u32, Vec<u8>, Vec<u8>)>,
+    exports: Vec<(String, u32)>,
+    memory_pages: u32,
+    data: Vec<(u32, Vec<u8>)>,
+}
+
+impl Module {
+    pub(super) fn new(memory_pages: u32) -> Self {
+        Self {
+            memory_pages,
+            ..Default::default()
+        }
+    }
+
+    fn type_index(&mut self, params: &[u8], results: &[u8]) -> u32 {
+        let ty = (params.to_vec(), results.to_vec());
+        match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Import the `ic0` function `name`, all imports have to come before the functions
+    pub(super) fn import(&mut self, name: &'static str, params: &[u8], results: &[u8]) -> u32 {
+        assert!(self.functions.is_empty(), "imports come before functions");
+        let ty = self.type_index(params, results);
+        self.imports.push(("ic0", name, ty));
+        self.imports.len() as u32 - 1
+    }
+
+    /// Add a function with the `locals` and `body`, without the final `end`
+    pub(super) fn function(
+        &mut self,
+        params: &[u8],
+        results: &[u8],
+        locals: &[u8],
+        body: Code,
+    ) -> u32 {
+        let ty = self.type_index(params, results);
+        self.functions.push((ty, locals.to_vec(), body.0));
+        (self.imports.len() + self.functions.len()) as u32 - 1
+    }
+
+    /// Export the function `index` as `name`
+    pub(super) fn export(&mut self, name: impl Into<String>, index: u32) {
+        self.exports.push((name.into(), index));
+    }
+
+    /// Initialize the memory at `offset` with `bytes`
+    pub(super) fn data(&mut self, offset: u32, bytes: impl Into<Vec<u8>>) {
+        self.data.push((offset, bytes.into()));
+    }
+
+    /// Add a function growing the memory to hold its argument in bytes, trapping if it can't
+    pub(super) fn grow_to(&mut self) -> u32 {
+        self.function(
+            &[I32],
+            &[],
+            &[I32],
+            Code::new()
+                .local_get(0)
+                .i32_const(PAGE_SIZE as i32 - 1)
+                .i32_add()
+                .i32_const(16)
+                .i32_shr_u()
+                .memory_size()
+                .i32_sub()
+                .local_tee(1)
+                .i32_const(0)
+                .i32_gt_s()
+                .if_()
+                .local_get(1)
+                .memory_grow()
+                .i32_const(-1)
+                .i32_eq()
+                .if_()
+                .unreachable()
+                .end()
+                .end(),
+        )
+    }
+
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+
+        section(&mut wasm, 1, &self.types, |out, (params, results)| {
+            out.push(0x60);
+            bytes(out, params);
+            bytes(out, results);
+        });
+        section(&mut wasm, 2, &self.imports, |out, (module, name, ty)| {
+            bytes(out, module.as_bytes());
+            bytes(out, name.as_bytes());
+            out.push(0x00);
+            uleb(out, *ty);
+        });
+        section(&mut wasm, 3, &self.functions, |out, (ty, _, _)| {
+            uleb(out, *ty)
+        });
+        section(&mut wasm, 5, &[self.memory_pages], |out, pages| {
+            out.push(0x00);
+            uleb(out, *pages);
+        });
+        section(&mut wasm, 7, &self.exports, |out, (name, index)| {
+            bytes(out, name.as_bytes());
+            out.push(0x00);
+            uleb(out, *index);
+        });
+        section(&mut wasm, 10, &self.functions, |out, (_, locals, body)| {
+            let mut code = Vec::new();
+            uleb(&mut code, locals.len() as u32);
+            for local in locals {
+                uleb(&mut code, 1);
+                code.push(*local);
+            }
+            code.extend_from_slice(body);
+            code.push(0x0b);
+            bytes(out, &code);
+        });
+        section(&mut wasm, 11, &self.data, |out, (offset, data)| {
+            out.push(0x00);
+            out.push(0x41);
+            sleb(out, i64::from(*offset as i32));
+            out.push(0x0b);
+            bytes(out, data);
+        });
+        wasm
+    }
+}
+
+/// The instructions of a function body
+#[derive(Default)]
+pub(super) struct Code(Vec<u8>);
+
+impl Code {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    fn op(mut self, op: u8) -> Self {
+        self.0.push(op);
+        self
+    }
+
+    fn op_index(mut self, op: u8, index: u32) -> Self {
+        self.0.push(op);
+        uleb(&mut self.0, index);
+        self
+    }
+
+    fn op_memory(mut self, op: u8, align: u32, offset: u32) -> Self {
+        self.0.push(op);
+        uleb(&mut self.0, align);
+        uleb(&mut self.0, offset);
+        self
+    }
+
+    pub(super) fn call(self, function: u32) -> Self {
+        self.op_index(0x10, function)
+    }
+
+    pub(super) fn local_get(self, local: u32) -> Self {
+        self.op_index(0x20, local)
+    }
+
+    pub(super) fn local_set(self, local: u32) -> Self {
+        self.op_index(0x21, local)
+    }
+
+    pub(super) fn local_tee(self, local: u32) -> Self {
+        self.op_index(0x22, local)
+    }
+
+    pub(super) fn i32_const(mut self, value: i32) -> Self {
+        self.0.push(0x41);
+        sleb(&mut self.0, i64::from(value));
+        self
+    }
+
+    pub(super) fn i64_const(mut self, value: i64) -> Self {
+        self.0.push(0x42);
+        sleb(&mut self.0, value);
+        self
+    }
+
+    pub(super) fn i32_load(self, offset: u32) -> Self {
+        self.op_memory(0x28, 2, offset)
+    }
+
+    pub(super) fn i64_load(self, offset: u32) -> Self {
+        self.op_memory(0x29, 3, offset)
+    }
+
+    pub(super) fn i32_store(self, offset: u32) -> Self {
+        self.op_memory(0x36, 2, offset)
+    }
+
+    pub(super) fn i64_store(self, offset: u32) -> Self {
+        self.op_memory(0x37, 3, offset)
+    }
+
+    pub(super) fn i32_store8(self, offset: u32) -> Self {
+        self.op_memory(0x3a, 0, offset)
+    }
+
+    pub(super) fn memory_size(self) -> Self {
+        self.op(0x3f).op(0x00)
+    }
+
+    pub(super) fn memory_grow(self) -> Self {
+        self.op(0x40).op(0x00)
+    }
+
+    pub(super) fn i32_add(self) -> Self {
+        self.op(0x6a)
+    }
+
+    pub(super) fn i32_sub(self) -> Self {
+        self.op(0x6b)
+    }
+
+    pub(super) fn i32_mul(self) -> Self {
+        self.op(0x6c)
+    }
+
+    pub(super) fn i32_and(self) -> Self {
+        self.op(0x71)
+    }
+
+    pub(super) fn i32_or(self) -> Self {
+        self.op(0x72)
+    }
+
+    pub(super) fn i32_shl(self) -> Self {
+        self.op(0x74)
+    }
+
+    pub(super) fn i32_shr_u(self) -> Self {
+        self.op(0x76)
+    }
+
+    pub(super) fn i32_eq(self) -> Self {
+        self.op(0x46)
+    }
+
+    pub(super) fn i32_lt_u(self) -> Self {
+        self.op(0x49)
+    }
+
+    pub(super) fn i32_gt_s(self) -> Self {
+        self.op(0x4a)
+    }
+
+    pub(super) fn i32_gt_u(self) -> Self {
+        self.op(0x4b)
+    }
+
+    pub(super) fn i64_add(self) -> Self {
+        self.op(0x7c)
+    }
+
+    pub(super) fn i64_eqz(self) -> Self {
+        self.op(0x50)
+    }
+
+    pub(super) fn drop(self) -> Self {
+        self.op(0x1a)
+    }
+
+    pub(super) fn unreachable(self) -> Self {
+        self.op(0x00)
+    }
+
+    /// `if` without a result, closed by [`Code::end`]
+    pub(super) fn if_(self) -> Self {
+        self.op(0x04).op(0x40)
+    }
+
+    /// `loop` without a result, closed by [`Code::end`]
+    pub(super) fn loop_(self) -> Self {
+        self.op(0x03).op(0x40)
+    }
+
+    /// Branch to the enclosing block `depth` if the top of the stack isn't zero
+    pub(super) fn br_if(self, depth: u32) -> Self {
+        self.op_index(0x0d, depth)
+    }
+
+    pub(super) fn else_(self) -> Self {
+        self.op(0x05)
+    }
+
+    pub(super) fn end(self) -> Self {
+        self.op(0x0b)
+    }
+}
+
+fn section<T>(wasm: &mut Vec<u8>, id: u8, items: &[T], mut encode: impl FnMut(&mut Vec<u8>, &T)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut content = Vec::new();
+    uleb(&mut content, items.len() as u32);
+    for item in items {
+        encode(&mut content, item);
+    }
+    wasm.push(id);
+    bytes(wasm, &content);
+}
+
+/// `bytes` prefixed with their length
+fn bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    uleb(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
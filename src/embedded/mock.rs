@@ -0,0 +1,423 @@
+//! A stand-in for a dependency, recording the calls it gets and replying as scripted.
+use candid::Principal;
+
+use super::encoder::{Code, Module, I32, PAGE_SIZE};
+use crate::backend::Backend;
+use crate::interface::MethodMode;
+use crate::{Canister, Error, Result};
+
+/// Where the end of the call log is stored
+const LOG_END: u32 = 0;
+/// Where the end of the scripted replies is stored
+const REPLIES_END: u32 = 4;
+/// The empty candid reply, `()`
+const EMPTY_REPLY: u32 = 16;
+const EMPTY_REPLY_BYTES: &[u8] = b"DIDL\0\0";
+/// The trap message of a bad control call
+const BAD_SCRIPT: u32 = 32;
+const BAD_SCRIPT_MESSAGE: &[u8] = b"mock: invalid or too large scripted reply";
+/// The scripted reply of each method: address, length and whether it is a reject
+const TABLE: u32 = 128;
+const TABLE_ENTRY: u32 = 12;
+/// The scripted replies are stored from here on, up to the log
+const REPLIES_START: u32 = 16 * PAGE_SIZE;
+/// The call log grows from here on
+const LOG_START: u32 = 32 * PAGE_SIZE;
+
+/// A mock canister with the methods of the dependency it stands in for.
+///
+/// Each update call is recorded with its method, caller and argument, and answered
+/// with the reply scripted for its method, the empty reply `()` until one is scripted.
+/// Query methods reply the same way, but aren't recorded, as a query can't change state.
+///
+/// ```
+/// # async fn run(wallet: ic_test_utils::WalletCanister<'_>, management: ic_test_utils::ManagementCanister<'_>, oracle: ic_test_utils::Canister<'_, ()>) {
+/// use candid::{Decode, Encode};
+/// use ic_test_utils::embedded::{Mock, MockCanister};
+/// use ic_test_utils::Canister;
+///
+/// let wasm = MockCanister::new().update("transfer").query("balance").wasm();
+/// let canister_id = wallet.create_canister(1_000_000_000_000, None).await.unwrap();
+/// management.install_code_no_arg(canister_id, &wasm).await.unwrap();
+///
+/// let ledger = Canister::new_mock(management.backend(), canister_id);
+/// ledger.mock_reply("transfer", Encode!(&7u64).unwrap()).await.unwrap();
+///
+/// // The canister under test calls the mock
+/// oracle.call_update("pay", Encode!(&canister_id).unwrap()).await.unwrap();
+///
+/// let calls = ledger.mock_calls().await.unwrap();
+/// assert_eq!(calls[0].method, "transfer");
+/// assert_eq!(calls[0].caller, *oracle.principal());
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockCanister {
+    methods: Vec<(String, MethodMode)>,
+}
+
+impl MockCanister {
+    /// A mock without methods
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the update method `name`
+    pub fn update(mut self, name: impl Into<String>) -> Self {
+        self.methods.push((name.into(), MethodMode::Update));
+        self
+    }
+
+    /// Add the query method `name`
+    pub fn query(mut self, name: impl Into<String>) -> Self {
+        self.methods.push((name.into(), MethodMode::Query));
+        self
+    }
+
+    /// The wasm module of the mock, it is installed without an argument
+    pub fn wasm(&self) -> Vec<u8> {
+        let mut module = Module::new(LOG_START / PAGE_SIZE + 1);
+        let arg_size = module.import("msg_arg_data_size", &[], &[I32]);
+        let arg_copy = module.import("msg_arg_data_copy", &[I32, I32, I32], &[]);
+        let caller_size = module.import("msg_caller_size", &[], &[I32]);
+        let caller_copy = module.import("msg_caller_copy", &[I32, I32, I32], &[]);
+        let reply_append = module.import("msg_reply_data_append", &[I32, I32], &[]);
+        let reply = module.import("msg_reply", &[], &[]);
+        let reject = module.import("msg_reject", &[I32, I32], &[]);
+        let trap = module.import("trap", &[I32, I32], &[]);
+
+        let grow_to = module.grow_to();
+
+        // Append the method index, the caller and the argument to the log
+        let record = module.function(
+            &[I32],
+            &[],
+            &[I32, I32, I32],
+            // local 1: entry, 2: caller size, 3: argument size
+            Code::new()
+                .i32_const(LOG_END as i32)
+                .i32_load(0)
+                .local_set(1)
+                .call(caller_size)
+                .local_set(2)
+                .call(arg_size)
+                .local_set(3)
+                .local_get(1)
+                .i32_const(12)
+                .i32_add()
+                .local_get(2)
+                .i32_add()
+                .local_get(3)
+                .i32_add()
+                .call(grow_to)
+                .local_get(1)
+                .local_get(0)
+                .i32_store(0)
+                .local_get(1)
+                .local_get(2)
+                .i32_store(4)
+                .local_get(1)
+                .i32_const(8)
+                .i32_add()
+                .i32_const(0)
+                .local_get(2)
+                .call(caller_copy)
+                .local_get(1)
+                .local_get(2)
+                .i32_add()
+                .local_get(3)
+                .i32_store(8)
+                .local_get(1)
+                .local_get(2)
+                .i32_add()
+                .i32_const(12)
+                .i32_add()
+                .i32_const(0)
+                .local_get(3)
+                .call(arg_copy)
+                .i32_const(LOG_END as i32)
+                .local_get(1)
+                .local_get(2)
+                .i32_add()
+                .local_get(3)
+                .i32_add()
+                .i32_const(12)
+                .i32_add()
+                .i32_store(0),
+        );
+
+        // Reply or reject as scripted for the method index
+        let respond = module.function(
+            &[I32],
+            &[],
+            &[I32],
+            Code::new()
+                .local_get(0)
+                .i32_const(TABLE_ENTRY as i32)
+                .i32_mul()
+                .i32_const(TABLE as i32)
+                .i32_add()
+                .local_tee(1)
+                .i32_load(8)
+                .if_()
+                .local_get(1)
+                .i32_load(0)
+                .local_get(1)
+                .i32_load(4)
+                .call(reject)
+                .else_()
+                .local_get(1)
+                .i32_load(0)
+                .local_get(1)
+                .i32_load(4)
+                .call(reply_append)
+                .call(reply)
+                .end(),
+        );
+
+        // Script the reply of the method index with the argument: the reject
+        // flag as a 4 byte little endian number, then the reply or reject message
+        let script = module.function(
+            &[I32],
+            &[],
+            &[I32, I32, I32],
+            // local 1: argument size, 2: stored argument, 3: table entry
+            Code::new()
+                .call(arg_size)
+                .local_tee(1)
+                .i32_const(4)
+                .i32_lt_u()
+                .i32_const(REPLIES_END as i32)
+                .i32_load(0)
+                .local_tee(2)
+                .local_get(1)
+                .i32_add()
+                .i32_const(LOG_START as i32)
+                .i32_gt_u()
+                .i32_or()
+                .if_()
+                .i32_const(BAD_SCRIPT as i32)
+                .i32_const(BAD_SCRIPT_MESSAGE.len() as i32)
+                .call(trap)
+                .end()
+                .local_get(2)
+                .i32_const(0)
+                .local_get(1)
+                .call(arg_copy)
+                .local_get(0)
+                .i32_const(TABLE_ENTRY as i32)
+                .i32_mul()
+                .i32_const(TABLE as i32)
+                .i32_add()
+                .local_tee(3)
+                .local_get(2)
+                .i32_const(4)
+                .i32_add()
+                .i32_store(0)
+                .local_get(3)
+                .local_get(1)
+                .i32_const(4)
+                .i32_sub()
+                .i32_store(4)
+                .local_get(3)
+                .local_get(2)
+                .i32_load(0)
+                .i32_store(8)
+                .i32_const(REPLIES_END as i32)
+                .local_get(2)
+                .local_get(1)
+                .i32_add()
+                .i32_store(0)
+                .i32_const(EMPTY_REPLY as i32)
+                .i32_const(EMPTY_REPLY_BYTES.len() as i32)
+                .call(reply_append)
+                .call(reply),
+        );
+
+        for (index, (name, mode)) in self.methods.iter().enumerate() {
+            let index = index as i32;
+            let handler = match mode {
+                MethodMode::Query => Code::new().i32_const(index).call(respond),
+                _ => Code::new()
+                    .i32_const(index)
+                    .call(record)
+                    .i32_const(index)
+                    .call(respond),
+            };
+            let handler = module.function(&[], &[], &[], handler);
+            let kind = match mode {
+                MethodMode::Query => "canister_query",
+                _ => "canister_update",
+            };
+            module.export(format!("{} {}", kind, name), handler);
+
+            let setter = module.function(&[], &[], &[], Code::new().i32_const(index).call(script));
+            module.export(format!("canister_update __mock_script:{}", name), setter);
+        }
+
+        let calls = module.function(
+            &[],
+            &[],
+            &[],
+            Code::new()
+                .i32_const(LOG_START as i32)
+                .i32_const(LOG_END as i32)
+                .i32_load(0)
+                .i32_const(LOG_START as i32)
+                .i32_sub()
+                .call(reply_append)
+                .call(reply),
+        );
+        module.export("canister_query __mock_calls", calls);
+
+        let clear = module.function(
+            &[],
+            &[],
+            &[],
+            Code::new()
+                .i32_const(LOG_END as i32)
+                .i32_const(LOG_START as i32)
+                .i32_store(0)
+                .i32_const(EMPTY_REPLY as i32)
+                .i32_const(EMPTY_REPLY_BYTES.len() as i32)
+                .call(reply_append)
+                .call(reply),
+        );
+        module.export("canister_update __mock_clear", clear);
+
+        let mut table = Vec::new();
+        let mut names = Vec::new();
+        for (name, _) in &self.methods {
+            table.extend_from_slice(&EMPTY_REPLY.to_le_bytes());
+            table.extend_from_slice(&(EMPTY_REPLY_BYTES.len() as u32).to_le_bytes());
+            table.extend_from_slice(&0u32.to_le_bytes());
+            names.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            names.extend_from_slice(name.as_bytes());
+        }
+        let names_start = TABLE + table.len() as u32;
+        assert!(
+            names_start as usize + names.len() <= REPLIES_START as usize,
+            "too many mocked methods"
+        );
+
+        let methods = module.function(
+            &[],
+            &[],
+            &[],
+            Code::new()
+                .i32_const(names_start as i32)
+                .i32_const(names.len() as i32)
+                .call(reply_append)
+                .call(reply),
+        );
+        module.export("canister_query __mock_methods", methods);
+        module.data(LOG_END, LOG_START.to_le_bytes());
+        module.data(REPLIES_END, REPLIES_START.to_le_bytes());
+        module.data(EMPTY_REPLY, EMPTY_REPLY_BYTES);
+        module.data(BAD_SCRIPT, BAD_SCRIPT_MESSAGE);
+        module.data(TABLE, table);
+        module.data(names_start, names);
+        module.encode()
+    }
+}
+
+/// A call recorded by a [`MockCanister`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    /// The method called
+    pub method: String,
+    /// The caller
+    pub caller: Principal,
+    /// The candid encoded argument
+    pub arg: Vec<u8>,
+}
+
+/// A canister running a [`MockCanister`] module
+pub struct Mock;
+
+impl<'agent> Canister<'agent, Mock> {
+    /// The mock canister `canister_id`
+    pub fn new_mock(backend: &'agent dyn Backend, canister_id: Principal) -> Self {
+        Self::new(canister_id, backend)
+    }
+
+    /// Reply to the calls of `method` with the candid encoded `reply` from now on
+    pub async fn mock_reply(&self, method: &str, reply: Vec<u8>) -> Result<()> {
+        let mut arg = 0u32.to_le_bytes().to_vec();
+        arg.extend(reply);
+        self.call_update(&format!("__mock_script:{}", method), arg)
+            .await?;
+        Ok(())
+    }
+
+    /// Reject the calls of `method` with `message` from now on
+    pub async fn mock_reject(&self, method: &str, message: &str) -> Result<()> {
+        let mut arg = 1u32.to_le_bytes().to_vec();
+        arg.extend_from_slice(message.as_bytes());
+        self.call_update(&format!("__mock_script:{}", method), arg)
+            .await?;
+        Ok(())
+    }
+
+    /// The update calls recorded since the install or the last [`Canister::mock_clear`],
+    /// oldest first
+    pub async fn mock_calls(&self) -> Result<Vec<RecordedCall>> {
+        let log = self.call_query("__mock_calls", Vec::new()).await?;
+        let methods = self.mock_methods().await?;
+        let invalid = || Error::Generic("Invalid mock call log".into());
+
+        let mut calls = Vec::new();
+        let mut rest = &log[..];
+        while !rest.is_empty() {
+            let (index, tail) = split_u32(rest).ok_or_else(invalid)?;
+            let (caller, tail) = split_bytes(tail).ok_or_else(invalid)?;
+            let (arg, tail) = split_bytes(tail).ok_or_else(invalid)?;
+            calls.push(RecordedCall {
+                method: methods.get(index as usize).cloned().ok_or_else(invalid)?,
+                caller: Principal::try_from_slice(caller)?,
+                arg: arg.to_vec(),
+            });
+            rest = tail;
+        }
+        Ok(calls)
+    }
+
+    /// Forget the recorded calls
+    pub async fn mock_clear(&self) -> Result<()> {
+        self.call_update("__mock_clear", Vec::new()).await?;
+        Ok(())
+    }
+
+    /// The method names by index
+    async fn mock_methods(&self) -> Result<Vec<String>> {
+        let names = self.call_query("__mock_methods", Vec::new()).await?;
+        let mut methods = Vec::new();
+        let mut rest = &names[..];
+        while !rest.is_empty() {
+            let (name, tail) = split_bytes(rest)
+                .ok_or_else(|| Error::Generic("Invalid mock method names".into()))?;
+            methods.push(String::from_utf8_lossy(name).into_owned());
+            rest = tail;
+        }
+        Ok(methods)
+    }
+}
+
+/// A little endian `u32` and the bytes after it
+fn split_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let (number, rest) = bytes.split_at(4);
+    Some((u32::from_le_bytes(number.try_into().ok()?), rest))
+}
+
+/// Bytes prefixed with their length as a little endian `u32`, and the bytes after them
+fn split_bytes(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = split_u32(bytes)?;
+    let len = len as usize;
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
@@ -0,0 +1,11 @@
+//! Canisters bundled with the crate, to deploy without building a wasm module first.
+//!
+//! The modules are generated when they are asked for, so they need no toolchain.
+mod counter;
+mod echo;
+mod encoder;
+mod mock;
+
+pub use counter::{counter_wasm, Counter};
+pub use echo::{echo_wasm, Echo, EchoReply};
+pub use mock::{Mock, MockCanister, RecordedCall};
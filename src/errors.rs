@@ -9,15 +9,15 @@ pub enum Error {
     InvalidOrMissingAccountInJson,
 
     /// A principal error
-    #[error("Principal error: {0}")]
+    #[error("Principal error")]
     Principal(#[from] ic_agent::export::PrincipalError),
 
     /// Standard IO error
-    #[error("IO error: {0}")]
+    #[error("IO error")]
     Io(#[from] std::io::Error),
 
     /// Std env var error
-    #[error("Env var error: {0}")]
+    #[error("Env var error")]
     EnvVar(#[from] std::env::VarError),
 
     /// Certificate not found error
@@ -25,15 +25,20 @@ pub enum Error {
     CertNotFound(std::path::PathBuf),
 
     /// Serde json error
-    #[error("Serde error: {0}")]
+    #[error("Serde error")]
     Json(#[from] serde_json::Error),
 
     /// Agent error
-    #[error("Agent error: {0}")]
+    #[error("Agent error")]
     Agent(#[from] ic_agent::agent::agent_error::AgentError),
 
+    /// Error of an ic-agent 0.49 agent, see [`compat`](crate::compat)
+    #[cfg(feature = "ic-agent-0.49")]
+    #[error("Agent error")]
+    Agent049(#[from] ic_agent_0_49::AgentError),
+
     /// Identity error
-    #[error("Identity error: {0}")]
+    #[error("Identity error")]
     Ident(#[from] ic_agent::identity::PemError),
 
     /// Missing configuration directory
@@ -41,7 +46,7 @@ pub enum Error {
     MissingConfig,
 
     /// Candid error
-    #[error("Candid error: {0}")]
+    #[error("Candid error")]
     Candid(#[from] candid::Error),
 
     /// Generic error as a String
@@ -52,11 +57,318 @@ pub enum Error {
     #[error("Must be a percent between 0 and 100.")]
     MustBeAPercentage(),
 
+    /// The local replica exited before it became healthy
+    #[error("Replica exited: {0}")]
+    ReplicaExited(std::process::ExitStatus),
+
+    /// The local replica did not become healthy in time
+    #[error("Replica at {0} did not become healthy in time")]
+    ReplicaNotReady(String),
+
+    /// Http error
+    #[cfg(any(
+        feature = "pocket-ic",
+        feature = "dfx-integration",
+        feature = "bitcoin"
+    ))]
+    #[error("Http error")]
+    Http(#[from] reqwest::Error),
+
+    /// Error returned by the PocketIC server
+    #[error("PocketIC error: {0}")]
+    PocketIc(String),
+
+    /// The operation needs a different backend
+    #[error("Not supported by this backend: {0}")]
+    UnsupportedByBackend(&'static str),
+
+    /// Building a wasm module failed
+    #[error("Wasm build failed: {0}")]
+    WasmBuild(String),
+
+    /// The new candid interface breaks clients of the old one
+    #[error("Incompatible candid interface: {0}")]
+    IncompatibleInterface(String),
+
+    /// No canister with this name is known
+    #[error("Unknown canister: {0}")]
+    UnknownCanister(String),
+
+    /// The call was rejected
+    #[error("{method} was rejected ({code:?}): {message}")]
+    Rejected {
+        /// The reject code
+        code: RejectCode,
+        /// The reject message
+        message: String,
+        /// The method that was called
+        method: String,
+    },
+
+    /// A call made through a [`Canister`](crate::Canister) failed
+    #[error("{method} call for canister {canister_id} with a {arg_len} byte argument failed")]
+    Call {
+        /// The canister called, or the canister a management call is for
+        canister_id: candid::Principal,
+        /// The method called
+        method: String,
+        /// The length of the candid encoded argument
+        arg_len: usize,
+        /// The error of the call
+        source: Box<Error>,
+    },
+
+    /// A step of a [`Scenario`](crate::scenario::Scenario) failed
+    #[error("Step {index} {step:?} failed after {elapsed:?}")]
+    Step {
+        /// The name of the step
+        step: String,
+        /// The number of the step, starting at 1
+        index: usize,
+        /// How long the step ran
+        elapsed: std::time::Duration,
+        /// The error of the step
+        source: Box<Error>,
+    },
+
+    /// The deadline of a [`Scenario`](crate::scenario::Scenario) passed, see
+    /// [`deadline`](crate::deadline)
+    #[error("Deadline of {timeout:?} exceeded")]
+    DeadlineExceeded {
+        /// The time the scenario had
+        timeout: std::time::Duration,
+        /// The error the step failed with after the deadline, if it didn't just hang
+        source: Option<Box<Error>>,
+    },
+
+    /// The waiter gave up before the update call completed.
+    /// The call may still execute, see [`wait_for_request`](crate::backend::wait_for_request)
+    /// to keep polling it.
+    #[error("{method} did not complete after {waited:?}, request id {}", hex::encode(request_id.as_slice()))]
+    PollTimeout {
+        /// The id of the request that is still processing
+        request_id: ic_agent::RequestId,
+        /// The effective canister id the request was sent to
+        effective_canister_id: candid::Principal,
+        /// The method that was called
+        method: String,
+        /// How long the waiter polled
+        waited: std::time::Duration,
+    },
+
+    /// An idempotent update failed until its ingress expiry passed, so it can't be
+    /// resubmitted anymore, see
+    /// [`call_update_idempotent`](crate::Canister::call_update_idempotent)
+    #[error("{method} expired after {attempts} attempts, the replica rejects it now")]
+    IngressExpired {
+        /// The method that was called
+        method: String,
+        /// The number of attempts made
+        attempts: u32,
+        /// The error of the last attempt
+        source: Box<Error>,
+    },
+
+    /// An HTTP response is not properly certified
+    #[error("Response is not certified: {0}")]
+    NotCertified(String),
+
+    /// A threshold signature doesn't verify
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    /// A bitcoind RPC call failed
+    #[cfg(feature = "bitcoin")]
+    #[error("bitcoind error {code}: {message}")]
+    Bitcoind {
+        /// The RPC error code
+        code: i64,
+        /// The RPC error message
+        message: String,
+    },
+
+    /// An operation failed and the canister was restored to the snapshot taken before it,
+    /// see [`with_rollback`](crate::snapshot::with_rollback)
+    #[error("Restored the canister to its snapshot after a failure")]
+    RolledBack(#[source] Box<Error>),
+
+    /// An operation failed and restoring the snapshot taken before it failed too
+    #[error("Restoring the canister to its snapshot failed: {}", restore.report())]
+    RollbackFailed {
+        /// The error of the operation
+        source: Box<Error>,
+        /// The error of the restore
+        restore: Box<Error>,
+    },
+
     /// Invalid memory size error
     #[error("Memory allocation must be between 0 and 2^48 (i.e 256TB), inclusively. Got {0}.")]
     InvalidMemorySize(u64),
 }
 
+impl Error {
+    /// The error of a call to `method` made through an agent,
+    /// with the reject of the replica turned into [`Error::Rejected`].
+    pub(crate) fn from_agent(err: ic_agent::AgentError, method: &str) -> Self {
+        match err {
+            ic_agent::AgentError::ReplicaError {
+                reject_code,
+                reject_message,
+            } => Self::Rejected {
+                code: RejectCode::from(reject_code),
+                message: reject_message,
+                method: method.into(),
+            },
+            err => Self::Agent(err),
+        }
+    }
+
+    /// What went wrong, for the well known failures.
+    /// This looks at the error message, which is stable across replica versions for
+    /// these errors, including the `IC0xxx` error codes of newer replicas.
+    pub fn kind(&self) -> ErrorKind {
+        let message = match self.reject() {
+            Some((_, message)) => message.to_lowercase(),
+            None => self.root().report().to_lowercase(),
+        };
+        let has = |patterns: &[&str]| patterns.iter().any(|pattern| message.contains(pattern));
+
+        // The error code of newer replicas is checked first, then the message, with
+        // traps before the size limits, as a trap message can mention a size limit
+        if has(&["ic0207", "ic0501"]) {
+            ErrorKind::OutOfCycles
+        } else if has(&["ic0508", "ic0509"]) {
+            ErrorKind::StoppedCanister
+        } else if has(&["ic0536"]) {
+            ErrorKind::MethodNotFound
+        } else if has(&["ic0301"]) {
+            ErrorKind::CanisterNotFound
+        } else if has(&["ic0502", "ic0503"]) {
+            ErrorKind::Trapped
+        } else if has(&["out of cycles", "insufficient cycles", "not enough cycles"]) {
+            ErrorKind::OutOfCycles
+        } else if has(&["is stopped", "is stopping"]) {
+            ErrorKind::StoppedCanister
+        } else if has(&[
+            "has no update method",
+            "has no query method",
+            "method not found",
+        ]) {
+            ErrorKind::MethodNotFound
+        } else if message.contains("canister") && message.contains("not found") {
+            ErrorKind::CanisterNotFound
+        } else if has(&["trapped"]) {
+            ErrorKind::Trapped
+        } else if has(&["too large", "exceeds the maximum"]) {
+            ErrorKind::WasmTooLarge
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// The message of this error followed by the messages of its sources,
+    /// like `anyhow` prints them with `{:#}`
+    pub fn report(&self) -> String {
+        let mut report = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            report.push_str(": ");
+            report.push_str(&err.to_string());
+            source = err.source();
+        }
+        report
+    }
+
+    /// The reject code, if the call was rejected
+    pub fn reject_code(&self) -> Option<RejectCode> {
+        self.reject().map(|(code, _)| code)
+    }
+
+    /// The error without the context of [`Error::Call`] and [`Error::Step`]
+    pub fn root(&self) -> &Error {
+        match self {
+            Self::Call { source, .. } | Self::Step { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The reject code and message, if the call was rejected
+    pub fn reject(&self) -> Option<(RejectCode, &str)> {
+        match self.root() {
+            Self::Rejected { code, message, .. } => Some((*code, message)),
+            Self::Agent(ic_agent::AgentError::ReplicaError {
+                reject_code,
+                reject_message,
+            }) => Some((RejectCode::from(*reject_code), reject_message)),
+            _ => None,
+        }
+    }
+}
+
+/// Well known failures, see [`Error::kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The canister doesn't have enough cycles
+    OutOfCycles,
+    /// The canister doesn't exist
+    CanisterNotFound,
+    /// The canister has no such method
+    MethodNotFound,
+    /// The wasm module or message is larger than allowed
+    WasmTooLarge,
+    /// The canister is stopped or stopping
+    StoppedCanister,
+    /// The canister trapped
+    Trapped,
+    /// Anything else
+    Other,
+}
+
+/// Why a call was rejected, see the
+/// [interface spec](https://internetcomputer.org/docs/current/references/ic-interface-spec#reject-codes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectCode {
+    /// Fatal system error, retrying is unlikely to help
+    SysFatal,
+    /// Transient system error, retrying might help
+    SysTransient,
+    /// The destination doesn't exist
+    DestinationInvalid,
+    /// The canister rejected the call explicitly
+    CanisterReject,
+    /// The canister trapped or ran out of cycles etc.
+    CanisterError,
+    /// A reject code this crate doesn't know
+    Unknown(u64),
+}
+
+impl RejectCode {
+    /// The numeric reject code
+    pub fn as_u64(&self) -> u64 {
+        match self {
+            Self::SysFatal => 1,
+            Self::SysTransient => 2,
+            Self::DestinationInvalid => 3,
+            Self::CanisterReject => 4,
+            Self::CanisterError => 5,
+            Self::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u64> for RejectCode {
+    fn from(code: u64) -> Self {
+        match code {
+            1 => Self::SysFatal,
+            2 => Self::SysTransient,
+            3 => Self::DestinationInvalid,
+            4 => Self::CanisterReject,
+            5 => Self::CanisterError,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
 impl From<String> for Error {
     fn from(s: String) -> Self {
         Self::Generic(s)
@@ -0,0 +1,97 @@
+//! Fixtures that deploy a set of canisters wired together.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, alice: candid::Principal) {
+//! use ic_test_utils::fixtures::{deploy_ledger_suite, LedgerSuiteWasms};
+//!
+//! let wasms = LedgerSuiteWasms {
+//!     ledger: std::fs::read("ledger.wasm").unwrap(),
+//!     index: std::fs::read("index.wasm").unwrap(),
+//!     cycles_minting: std::fs::read("cmc.wasm").unwrap(),
+//! };
+//! let suite = deploy_ledger_suite(agent, "alice", wasms, vec![(alice, 100_000_000)], 1_000_000_000_000)
+//!     .await
+//!     .unwrap();
+//! let ledger = suite.ledger;
+//! # }
+//! ```
+use candid::Principal;
+
+use crate::canister::{
+    AccountIdentifier, Canister, CyclesMinting, CyclesMintingInitPayload, IndexInitArgs, Ledger,
+    LedgerIndex, LedgerInitArgs, Tokens,
+};
+use crate::{Agent, Result};
+
+/// Wasm modules of the ledger suite.
+pub struct LedgerSuiteWasms {
+    /// ICP ledger wasm
+    pub ledger: Vec<u8>,
+    /// ICP index wasm
+    pub index: Vec<u8>,
+    /// Cycles minting canister wasm
+    pub cycles_minting: Vec<u8>,
+}
+
+/// Handles to a deployed ledger suite.
+pub struct LedgerSuite<'agent> {
+    /// ICP ledger
+    pub ledger: Canister<'agent, Ledger>,
+    /// ICP index, indexing the `ledger`
+    pub index: Canister<'agent, LedgerIndex>,
+    /// Cycles minting canister, owning the minting account of the `ledger`
+    pub cycles_minting: Canister<'agent, CyclesMinting>,
+}
+
+/// Deploy the ICP ledger, the index and the cycles minting canister.
+///
+/// All three canisters are created first through the wallet of `account_name`,
+/// each with `cycles`. The ledger is installed with the CMC as its minting
+/// account and `initial_balances` on the default subaccounts of the given
+/// principals. The index and the CMC are installed pointing at the ledger.
+pub async fn deploy_ledger_suite<'agent>(
+    agent: &'agent Agent,
+    account_name: impl AsRef<str>,
+    wasms: LedgerSuiteWasms,
+    initial_balances: Vec<(Principal, u64)>,
+    cycles: u64,
+) -> Result<LedgerSuite<'agent>> {
+    let wallet = Canister::new_wallet(agent, account_name, None)?;
+    let management = Canister::new_management(agent);
+
+    let ledger_id = wallet.create_canister(cycles, None).await?;
+    let index_id = wallet.create_canister(cycles, None).await?;
+    let cycles_minting_id = wallet.create_canister(cycles, None).await?;
+
+    let minting_account = AccountIdentifier::new(&cycles_minting_id, None);
+
+    let ledger_init = initial_balances.into_iter().fold(
+        LedgerInitArgs::new(&minting_account),
+        |init, (owner, e8s)| {
+            init.with_initial_balance(&AccountIdentifier::new(&owner, None), Tokens { e8s })
+        },
+    );
+    management
+        .install_code(ledger_id, wasms.ledger, (ledger_init.into_payload(),))
+        .await?;
+
+    let index_init = IndexInitArgs { ledger_id };
+    management
+        .install_code(index_id, wasms.index, (index_init,))
+        .await?;
+
+    let cycles_minting_init = CyclesMintingInitPayload::new(ledger_id, &minting_account);
+    management
+        .install_code(
+            cycles_minting_id,
+            wasms.cycles_minting,
+            (cycles_minting_init.into_payload(),),
+        )
+        .await?;
+
+    Ok(LedgerSuite {
+        ledger: Canister::new(ledger_id, agent),
+        index: Canister::new(index_id, agent),
+        cycles_minting: Canister::new(cycles_minting_id, agent),
+    })
+}
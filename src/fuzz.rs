@@ -0,0 +1,186 @@
+//! Fuzz canister endpoints with random well-typed arguments.
+//!
+//! A [`Fuzzer`] reads the candid interface of a canister, generates random arguments
+//! of the right types for the selected methods and calls them. A reject is a valid
+//! answer to a bad argument, but a trap is reported.
+//!
+//! ```
+//! # async fn run(canister: ic_test_utils::Canister<'_, ()>) {
+//! use ic_test_utils::fuzz::Fuzzer;
+//!
+//! let did = std::fs::read_to_string("counter.did").unwrap();
+//! let report = Fuzzer::new(&canister, &did)
+//!     .unwrap()
+//!     .methods(["set", "add"])
+//!     .iterations(100)
+//!     .seed(7)
+//!     .run()
+//!     .await
+//!     .unwrap();
+//! report.assert_no_traps();
+//! # }
+//! ```
+use candid::parser::configs::Configs;
+use candid::parser::types::FuncMode;
+use candid::types::{Function, Type};
+use candid::{IDLArgs, TypeEnv};
+
+use crate::chaos::next_u64;
+use crate::interface::parse_service;
+use crate::rng::TestRng;
+use crate::{Canister, Error, RejectCode, Result};
+
+/// Bytes of randomness each generated argument list is made from
+const SEED_LEN: usize = 2048;
+
+/// Calls methods of a canister with random arguments.
+pub struct Fuzzer<'a, 'agent, T> {
+    canister: &'a Canister<'agent, T>,
+    env: TypeEnv,
+    service: Vec<(String, Type)>,
+    methods: Option<Vec<String>>,
+    iterations: usize,
+    seed: u64,
+}
+
+impl<'a, 'agent, T> Fuzzer<'a, 'agent, T> {
+    /// Fuzz `canister`, with the service described by the candid file `did`.
+    /// By default every method is called 10 times.
+    pub fn new(canister: &'a Canister<'agent, T>, did: &str) -> Result<Self> {
+        let (env, service) = parse_service(did)?;
+        let service = env.as_service(&service)?.to_vec();
+        Ok(Self {
+            canister,
+            env,
+            service,
+            methods: None,
+            iterations: 10,
+            seed: 0,
+        })
+    }
+
+    /// Only call these methods
+    pub fn methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.methods = Some(methods.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// How many times each method is called
+    pub fn iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// The seed the arguments are generated from, to reproduce a run
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Take the seed the arguments are generated from from `rng`
+    pub fn rng(self, rng: &mut TestRng) -> Self {
+        self.seed(rng.next_u64())
+    }
+
+    /// Make the calls.
+    /// An error is only returned if the arguments can't be generated or the selected
+    /// methods are not in the service, failed calls are in the report.
+    pub async fn run(&self) -> Result<FuzzReport> {
+        let config = Configs::from_dhall("{=}")?;
+        let mut methods = Vec::new();
+        for (name, ty) in &self.service {
+            if self.methods.as_ref().is_none_or(|m| m.contains(name)) {
+                methods.push((name.as_str(), self.env.as_func(ty)?));
+            }
+        }
+        if let Some(selected) = &self.methods {
+            if let Some(missing) = selected
+                .iter()
+                .find(|name| !methods.iter().any(|(method, _)| method == name))
+            {
+                return Err(Error::Generic(format!(
+                    "Method {} is not in the service",
+                    missing
+                )));
+            }
+        }
+
+        let mut rng = self.seed;
+        let mut report = FuzzReport::default();
+        for _ in 0..self.iterations {
+            for (name, func) in &methods {
+                if func.modes.contains(&FuncMode::Oneway) {
+                    continue;
+                }
+                let seed: Vec<u8> = (0..SEED_LEN / 8)
+                    .flat_map(|_| next_u64(&mut rng).to_le_bytes())
+                    .collect();
+                let args = IDLArgs::any(&seed, &config, &self.env, &func.args)?;
+                let bytes = args.to_bytes_with_types(&self.env, &func.args)?;
+
+                report.calls += 1;
+                if let Err(err) = self.call(name, func, bytes).await {
+                    match err.reject() {
+                        Some((RejectCode::CanisterReject, _)) => report.rejects += 1,
+                        _ => report.failures.push(FuzzFailure {
+                            method: name.to_string(),
+                            args: args.to_string(),
+                            error: err.report(),
+                        }),
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    async fn call(&self, name: &str, func: &Function, arg: Vec<u8>) -> Result<Vec<u8>> {
+        if func.modes.contains(&FuncMode::Query) {
+            self.canister.call_query(name, arg).await
+        } else {
+            self.canister.call_update(name, arg).await
+        }
+    }
+}
+
+/// A call that trapped or failed for another reason than a reject
+#[derive(Debug, Clone)]
+pub struct FuzzFailure {
+    /// The method called
+    pub method: String,
+    /// The arguments in candid text format
+    pub args: String,
+    /// The error
+    pub error: String,
+}
+
+/// The outcome of [`Fuzzer::run`]
+#[derive(Debug, Clone, Default)]
+pub struct FuzzReport {
+    /// Number of calls made
+    pub calls: usize,
+    /// Number of calls the canister rejected
+    pub rejects: usize,
+    /// Calls that failed otherwise
+    pub failures: Vec<FuzzFailure>,
+}
+
+impl FuzzReport {
+    /// Panic if any call trapped or failed unexpectedly
+    pub fn assert_no_traps(&self) {
+        if let Some(failure) = self.failures.first() {
+            panic!(
+                "{} of {} calls failed, first: {}{} failed with {}",
+                self.failures.len(),
+                self.calls,
+                failure.method,
+                failure.args,
+                failure.error
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Compare candid replies with snapshots checked in next to the tests.
+//!
+//! The reply is decoded and rendered as candid text, which is compared with the file
+//! `tests/snapshots/<name>.snap` of the crate under test. A missing snapshot is
+//! written on the first run. Run the tests with `IC_TEST_UPDATE_SNAPSHOTS=1` to
+//! accept changed replies.
+//!
+//! ```
+//! # async fn run(ledger: ic_test_utils::LedgerCanister<'_>) {
+//! use candid::Encode;
+//! use ic_test_utils::golden::assert_reply_snapshot;
+//!
+//! let did = std::fs::read_to_string("ledger.did").unwrap();
+//! let reply = ledger.call_query("symbol", Encode!().unwrap()).await.unwrap();
+//! assert_reply_snapshot("ledger_symbol", &reply, &did, "symbol");
+//! # }
+//! ```
+use std::path::PathBuf;
+
+use candid::{CandidType, Encode, IDLArgs, TypeEnv};
+
+use crate::interface::parse_service;
+use crate::{Error, Result};
+
+/// Env var to set to overwrite snapshots that don't match
+pub const UPDATE_SNAPSHOTS_ENV_VAR: &str = "IC_TEST_UPDATE_SNAPSHOTS";
+
+/// The candid text of `reply`, decoded with the return types of `method` in `did`
+pub fn render_reply(reply: &[u8], did: &str, method: &str) -> Result<String> {
+    let (env, service) = parse_service(did)?;
+    let func = env.get_method(&service, method)?;
+    let args = IDLArgs::from_bytes_with_types(reply, &env, &func.rets)?;
+    Ok(args.to_string())
+}
+
+/// The candid text of `value`
+pub fn render_value<T: CandidType>(value: &T) -> Result<String> {
+    let bytes = Encode!(value)?;
+    let args = IDLArgs::from_bytes_with_types(&bytes, &TypeEnv::new(), &[T::ty()])?;
+    Ok(args.to_string())
+}
+
+/// Panic unless `text` matches the snapshot `name`
+pub fn assert_snapshot(name: &str, text: &str) {
+    let path = snapshot_path(name).expect("failed to find the snapshot directory");
+    let update = std::env::var(UPDATE_SNAPSHOTS_ENV_VAR).is_ok_and(|v| v != "0");
+
+    match std::fs::read_to_string(&path) {
+        Ok(expected) if expected == text => {}
+        Ok(expected) if !update => panic!(
+            "snapshot {} does not match, set {}=1 to update it\n--- expected\n{}\n--- actual\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV_VAR,
+            expected,
+            text
+        ),
+        _ => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).expect("failed to create the snapshot directory");
+            }
+            std::fs::write(&path, text).expect("failed to write the snapshot");
+        }
+    }
+}
+
+/// Panic unless the decoded `reply` of `method` matches the snapshot `name`
+pub fn assert_reply_snapshot(name: &str, reply: &[u8], did: &str, method: &str) {
+    let text = render_reply(reply, did, method).expect("failed to decode the reply");
+    assert_snapshot(name, &text)
+}
+
+/// Panic unless `value` matches the snapshot `name`
+pub fn assert_value_snapshot<T: CandidType>(name: &str, value: &T) {
+    let text = render_value(value).expect("failed to render the value");
+    assert_snapshot(name, &text)
+}
+
+fn snapshot_path(name: &str) -> Result<PathBuf> {
+    let dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| Error::Generic("CARGO_MANIFEST_DIR is not set".into()))?;
+    Ok(PathBuf::from(dir)
+        .join("tests")
+        .join("snapshots")
+        .join(format!("{}.snap", name)))
+}
@@ -0,0 +1,108 @@
+//! Runtime support for the [`ic_test`](crate::ic_test) attribute.
+//!
+//! The attribute turns an async function into a test. The agent is built for the
+//! account given by the `IC_TEST_ACCOUNT` env var (or `account = "..."`), talking
+//! to the replica at `IC_TEST_URL` (or `url = "..."`). Every canister declared in
+//! `canisters(...)` is created and installed before the test, and handed to the
+//! parameter of the same name. After the test, even if it panicked, all the created
+//! canisters are stopped and deleted.
+//!
+//! ```
+//! use ic_test_utils::{ic_test, Agent, Canister};
+//!
+//! struct Counter;
+//!
+//! #[ic_test(account = "alice", canisters(counter = "counter.wasm"))]
+//! async fn counter_starts_at_zero(agent: &Agent, counter: Canister<'_, Counter>) {
+//!     let reply = counter.call_query("get", candid::Encode!().unwrap()).await.unwrap();
+//! }
+//! ```
+#[cfg(feature = "wallet")]
+use std::path::Path;
+
+#[cfg(feature = "wallet")]
+use candid::Principal;
+
+use crate::cleanup::CanisterGuard;
+#[cfg(feature = "wallet")]
+use crate::create_canister;
+use crate::{get_agent, Agent, Result};
+
+/// Env var holding the account the test agent is built for.
+pub const ACCOUNT_ENV_VAR: &str = "IC_TEST_ACCOUNT";
+
+/// Env var holding the url of the replica.
+pub const URL_ENV_VAR: &str = "IC_TEST_URL";
+
+/// Cycles given to each canister created by the harness, unless set with `cycles = ...`.
+pub const DEFAULT_CYCLES: u64 = 1_000_000_000_000;
+
+/// The state of a single `#[ic_test]`.
+pub struct TestHarness {
+    agent: Agent,
+    account_name: String,
+    created: Vec<CanisterGuard>,
+}
+
+impl TestHarness {
+    /// Build the agent.
+    /// The env vars take precedence over the `account_name` and `url` given to the attribute.
+    /// Without a url, the replica dfx runs for the current directory is used, if there is one.
+    pub async fn setup(account_name: &str, url: Option<&str>) -> Result<Self> {
+        let account_name = std::env::var(ACCOUNT_ENV_VAR).unwrap_or_else(|_| account_name.into());
+        let url = std::env::var(URL_ENV_VAR)
+            .ok()
+            .or_else(|| url.map(Into::into));
+        #[cfg(feature = "dfx-integration")]
+        let url = url.or_else(|| crate::dfx::local_replica_url(".").ok());
+        let agent = get_agent(account_name.as_str(), url.as_deref()).await?;
+
+        Ok(Self {
+            agent,
+            account_name,
+            created: Vec::new(),
+        })
+    }
+
+    /// The agent
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// The account the agent was built for
+    pub fn account_name(&self) -> &str {
+        &self.account_name
+    }
+
+    /// Create a canister and install the wasm at `wasm_path` without init arguments.
+    /// The canister is deleted in [`TestHarness::teardown`].
+    #[cfg(feature = "wallet")]
+    pub async fn deploy(&mut self, wasm_path: impl AsRef<Path>, cycles: u64) -> Result<Principal> {
+        let bytecode = std::fs::read(wasm_path)?;
+        let guard = create_canister(&self.agent, &self.account_name, bytecode, (), cycles).await?;
+        let canister_id = guard.principal();
+        self.created.push(guard);
+        Ok(canister_id)
+    }
+
+    /// Stop and delete all the canisters created by the harness.
+    /// This is best effort, failures are ignored.
+    pub async fn teardown(self) {
+        for guard in self.created {
+            let _ = guard.cleanup().await;
+        }
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use futures::FutureExt;
+
+    /// The runtime the tests run on
+    pub fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build the test runtime")
+    }
+}
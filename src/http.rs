@@ -0,0 +1,206 @@
+//! Call the `http_request` query of a canister, the way the HTTP gateway does.
+//!
+//! [`http_get`] follows streaming callbacks, so the body of the returned response
+//! is complete, which makes asset and API canisters testable at the HTTP layer.
+//!
+//! ```
+//! # async fn run(canister: ic_test_utils::Canister<'_, ()>) {
+//! use ic_test_utils::http::http_get;
+//!
+//! let response = http_get(&canister, "/index.html", &[("accept-encoding", "gzip")])
+//!     .await
+//!     .unwrap();
+//! assert_eq!(response.status_code, 200);
+//! assert_eq!(response.header("content-encoding"), Some("gzip"));
+//! # }
+//! ```
+use candid::parser::value::{IDLField, IDLValue, VariantValue};
+use candid::{idl_hash, CandidType, Decode, Deserialize, Encode, IDLArgs, Principal};
+
+use crate::{Canister, Error, Result};
+
+/// How many chunks [`http_get`] follows at most, to stop a callback that never ends
+pub const MAX_STREAMING_CHUNKS: usize = 10_000;
+
+/// The query serving HTTP requests
+pub const HTTP_REQUEST_METHOD: &str = "http_request";
+
+/// An HTTP request, as passed to `http_request`
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct HttpRequest {
+    /// The HTTP method, like `GET`
+    pub method: String,
+    /// The path and query of the url
+    pub url: String,
+    /// The request headers
+    pub headers: Vec<(String, String)>,
+    /// The request body
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// A `GET` request for `url` without headers
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            method: "GET".into(),
+            url: url.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+/// The HTTP response returned by `http_request`
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct HttpResponse {
+    /// The HTTP status code
+    pub status_code: u16,
+    /// The response headers
+    pub headers: Vec<(String, String)>,
+    /// The response body
+    #[serde(with = "serde_bytes")]
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// The value of the header `name`, compared case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send `request` to the `http_request` query of `canister`.
+/// A streamed body is not followed, see [`http_get`].
+pub async fn http_request<T>(
+    canister: &Canister<'_, T>,
+    request: HttpRequest,
+) -> Result<HttpResponse> {
+    Ok(send(canister, request).await?.0)
+}
+
+/// `GET` the `path` of `canister` with the `headers`, and fetch the rest of the body
+/// through the streaming callback if the response is streamed.
+pub async fn http_get<T>(
+    canister: &Canister<'_, T>,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Result<HttpResponse> {
+    let mut request = HttpRequest::get(path);
+    request.headers = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let (mut response, mut next) = send(canister, request).await?;
+
+    let mut chunks = 0;
+    while let Some(Callback {
+        canister_id,
+        method,
+        token,
+    }) = next
+    {
+        chunks += 1;
+        if chunks > MAX_STREAMING_CHUNKS {
+            return Err(Error::Generic(format!(
+                "{} streamed more than {} chunks",
+                path, MAX_STREAMING_CHUNKS
+            )));
+        }
+        let arg = IDLArgs::new(&[token]).to_bytes()?;
+        let reply = Canister::<()>::new(canister_id, canister.backend())
+            .call_query(&method, arg)
+            .await?;
+        let chunk = untyped_reply(&reply)?;
+        response
+            .body
+            .extend(blob(field(&chunk, "body")).ok_or_else(|| {
+                Error::Generic(format!("Invalid streaming chunk of {}: {}", path, chunk))
+            })?);
+        next = match field(&chunk, "token") {
+            Some(IDLValue::Opt(token)) => Some(Callback {
+                canister_id,
+                method,
+                token: (**token).clone(),
+            }),
+            _ => None,
+        };
+    }
+    Ok(response)
+}
+
+/// The query to call for the next chunk of a streamed body
+struct Callback {
+    canister_id: Principal,
+    method: String,
+    token: IDLValue,
+}
+
+/// The response, and the streaming callback if the body is streamed.
+/// The type of the streaming token is up to the canister, so the callback is read
+/// from the reply decoded without types.
+async fn send<T>(
+    canister: &Canister<'_, T>,
+    request: HttpRequest,
+) -> Result<(HttpResponse, Option<Callback>)> {
+    let reply = canister
+        .call_query(HTTP_REQUEST_METHOD, Encode!(&request)?)
+        .await?;
+    let response = Decode!(&reply, HttpResponse)?;
+
+    let callback = match field(&untyped_reply(&reply)?, "streaming_strategy") {
+        Some(IDLValue::Opt(strategy)) => match &**strategy {
+            IDLValue::Variant(VariantValue(callback, _)) => match (
+                field(&callback.val, "callback"),
+                field(&callback.val, "token"),
+            ) {
+                (Some(IDLValue::Func(canister_id, method)), Some(token)) => Some(Callback {
+                    canister_id: *canister_id,
+                    method: method.clone(),
+                    token: token.clone(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok((response, callback))
+}
+
+/// The single value of a reply, decoded without types
+fn untyped_reply(reply: &[u8]) -> Result<IDLValue> {
+    IDLArgs::from_bytes(reply)?
+        .args
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Generic("Empty reply".into()))
+}
+
+/// The field `name` of a record decoded without types
+fn field<'a>(record: &'a IDLValue, name: &str) -> Option<&'a IDLValue> {
+    match record {
+        IDLValue::Record(fields) => fields
+            .iter()
+            .find(|IDLField { id, .. }| id.get_id() == idl_hash(name))
+            .map(|field| &field.val),
+        _ => None,
+    }
+}
+
+/// The bytes of a blob decoded without types
+fn blob(value: Option<&IDLValue>) -> Option<Vec<u8>> {
+    match value? {
+        IDLValue::Vec(bytes) => bytes
+            .iter()
+            .map(|byte| match byte {
+                IDLValue::Nat8(byte) => Some(*byte),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
@@ -0,0 +1,393 @@
+//! Check candid interfaces of canisters.
+//!
+//! An upgrade must not break the clients of a canister: the new service type has to be
+//! a subtype of the old one.
+//!
+//! ```
+//! use ic_test_utils::interface::assert_upgrade_compatible;
+//!
+//! let old = "service : { get : () -> (nat) query }";
+//! let new = "service : { get : () -> (nat) query; inc : () -> () }";
+//! assert_upgrade_compatible(old, new);
+//! ```
+use std::fmt;
+use std::path::Path;
+
+use candid::parser::types::FuncMode;
+use candid::types::subtype::{subtype, Gamma};
+use candid::types::Type;
+use candid::{check_prog, IDLProg, Principal, TypeEnv};
+
+use crate::{Canister, Error, ManagementCanister, Result};
+
+/// Parse a `.did` file and return its type environment and service type
+pub fn parse_service(did: &str) -> Result<(TypeEnv, Type)> {
+    let prog = did.parse::<IDLProg>()?;
+    let mut env = TypeEnv::new();
+    let service = check_prog(&mut env, &prog)?
+        .ok_or_else(|| Error::IncompatibleInterface("no service in the candid file".into()))?;
+    Ok((env, service))
+}
+
+/// The types of the init arguments of the service in `did`
+pub fn init_types(did: &str) -> Result<(TypeEnv, Vec<Type>)> {
+    let (env, service) = parse_service(did)?;
+    let args = match service {
+        Type::Class(args, _) => args,
+        _ => Vec::new(),
+    };
+    Ok((env, args))
+}
+
+/// Encode candid text like `(42 : nat, "hello")`, with the `types` if they are known.
+///
+/// Without types, numbers are encoded as `int` unless they are annotated, like `(42 : nat)`.
+///
+/// ```
+/// use candid::Decode;
+/// use ic_test_utils::interface::encode_text;
+///
+/// let arg = encode_text(r#"(record { name = "alice"; age = 42 : nat8 })"#, None).unwrap();
+/// #[derive(candid::Deserialize, candid::CandidType)]
+/// struct User {
+///     name: String,
+///     age: u8,
+/// }
+/// assert_eq!(Decode!(&arg, User).unwrap().age, 42);
+/// ```
+pub fn encode_text(text: &str, types: Option<(&TypeEnv, &[Type])>) -> Result<Vec<u8>> {
+    let args = text.parse::<candid::IDLArgs>()?;
+    let bytes = match types {
+        Some((env, types)) => {
+            let args = args.annotate_types(true, env, types)?;
+            args.to_bytes_with_types(env, types)?
+        }
+        None => args.to_bytes()?,
+    };
+    Ok(bytes)
+}
+
+/// The candid encoded `bytes`, like a reply, as candid text
+pub fn decode_text(bytes: &[u8]) -> Result<String> {
+    Ok(candid::IDLArgs::from_bytes(bytes)?.to_string())
+}
+
+/// Check that the service in `new_did` can replace the service in `old_did`
+/// without breaking existing clients.
+pub fn check_upgrade_compatible(old_did: &str, new_did: &str) -> Result<()> {
+    let (mut env, new_service) = parse_service(new_did)?;
+    let (old_env, old_service) = parse_service(old_did)?;
+    let old_service = env.merge_type(old_env, old_service);
+    subtype(&mut Gamma::new(), &env, &new_service, &old_service)
+        .map_err(|e| Error::IncompatibleInterface(e.to_string()))
+}
+
+/// Panic if the service in `new_did` is not a compatible upgrade of `old_did`.
+/// See [`check_upgrade_compatible`].
+pub fn assert_upgrade_compatible(old_did: &str, new_did: &str) {
+    if let Err(e) = check_upgrade_compatible(old_did, new_did) {
+        panic!("{}", e);
+    }
+}
+
+/// A method that differs between two interfaces, see [`diff_interfaces`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MethodDiff {
+    /// The method is only in the local interface
+    Added {
+        /// The method name
+        method: String,
+        /// Its signature
+        signature: String,
+    },
+    /// The method is only in the deployed interface
+    Removed {
+        /// The method name
+        method: String,
+        /// Its signature
+        signature: String,
+    },
+    /// The method has another signature
+    Changed {
+        /// The method name
+        method: String,
+        /// Its signature in the deployed interface
+        deployed: String,
+        /// Its signature in the local interface
+        local: String,
+    },
+}
+
+impl fmt::Display for MethodDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { method, signature } => write!(f, "+ {} : {}", method, signature),
+            Self::Removed { method, signature } => write!(f, "- {} : {}", method, signature),
+            Self::Changed {
+                method,
+                deployed,
+                local,
+            } => write!(f, "~ {} : {}, now {}", method, deployed, local),
+        }
+    }
+}
+
+/// The methods that differ between the `deployed_did` and the `local_did` services.
+/// Signatures are compared by structure, so renaming a type doesn't count as a change.
+///
+/// ```
+/// use ic_test_utils::interface::{diff_interfaces, MethodDiff};
+///
+/// let deployed = "service : { get : () -> (nat) query; reset : () -> () }";
+/// let local = "service : { get : () -> (nat) query; inc : () -> () }";
+/// let diff = diff_interfaces(deployed, local).unwrap();
+/// assert_eq!(diff.len(), 2);
+/// assert!(matches!(&diff[0], MethodDiff::Added { method, .. } if method == "inc"));
+/// ```
+pub fn diff_interfaces(deployed_did: &str, local_did: &str) -> Result<Vec<MethodDiff>> {
+    let (mut env, local) = parse_service(local_did)?;
+    let (deployed_env, deployed) = parse_service(deployed_did)?;
+    // The signatures are shown with the type names of their own interface
+    let deployed_signatures = signatures(&deployed_env, &deployed)?;
+    let local_signatures = signatures(&env, &local)?;
+
+    let deployed = env.merge_type(deployed_env, deployed);
+    let deployed_methods = env.as_service(&deployed)?;
+    let local_methods = env.as_service(&local)?;
+
+    let mut diff = Vec::new();
+    for ((method, ty), signature) in local_methods.iter().zip(&local_signatures) {
+        match deployed_methods.iter().position(|(name, _)| name == method) {
+            None => diff.push(MethodDiff::Added {
+                method: method.clone(),
+                signature: signature.clone(),
+            }),
+            Some(i) => {
+                let deployed_ty = &deployed_methods[i].1;
+                let equal = subtype(&mut Gamma::new(), &env, ty, deployed_ty).is_ok()
+                    && subtype(&mut Gamma::new(), &env, deployed_ty, ty).is_ok();
+                if !equal {
+                    diff.push(MethodDiff::Changed {
+                        method: method.clone(),
+                        deployed: deployed_signatures[i].clone(),
+                        local: signature.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for ((method, _), signature) in deployed_methods.iter().zip(&deployed_signatures) {
+        if !local_methods.iter().any(|(name, _)| name == method) {
+            diff.push(MethodDiff::Removed {
+                method: method.clone(),
+                signature: signature.clone(),
+            });
+        }
+    }
+    Ok(diff)
+}
+
+/// The signatures of the methods of `service`, in order
+fn signatures(env: &TypeEnv, service: &Type) -> Result<Vec<String>> {
+    env.as_service(service)?
+        .iter()
+        .map(|(_, ty)| Ok(env.as_func(ty)?.to_string()))
+        .collect()
+}
+
+/// Panic unless the `candid:service` metadata of the deployed `canister_id` matches
+/// the `.did` file at `did_path`, listing the methods that drifted.
+/// This reads the state tree, so it is only available on an agent backend.
+pub async fn assert_deployed_interface(
+    management: &ManagementCanister<'_>,
+    canister_id: Principal,
+    did_path: impl AsRef<Path>,
+) {
+    let did_path = did_path.as_ref();
+    let local = std::fs::read_to_string(did_path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", did_path.display(), err));
+    let deployed = management
+        .candid_interface(canister_id)
+        .await
+        .unwrap_or_else(|err| panic!("failed to read the interface: {}", err.report()))
+        .unwrap_or_else(|| panic!("canister {} has no candid:service metadata", canister_id));
+    let diff = diff_interfaces(&deployed, &local)
+        .unwrap_or_else(|err| panic!("failed to compare the interfaces: {}", err.report()));
+    assert!(
+        diff.is_empty(),
+        "the interface of canister {} drifted from {}:\n{}",
+        canister_id,
+        did_path.display(),
+        diff.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+}
+
+/// Whether a method is an update, a query or a one way call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodMode {
+    /// An update
+    Update,
+    /// A query
+    Query,
+    /// An update without a reply
+    Oneway,
+}
+
+/// A method of a service, see [`ServiceDescription`]
+#[derive(Debug, Clone)]
+pub struct MethodDescription {
+    /// The method name
+    pub name: String,
+    /// Update, query or one way
+    pub mode: MethodMode,
+    /// The argument types
+    pub args: Vec<Type>,
+    /// The return types
+    pub rets: Vec<Type>,
+}
+
+impl MethodDescription {
+    /// The signature, like `(nat) -> (text) query`
+    pub fn signature(&self) -> String {
+        let types = |types: &[Type]| {
+            types
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mode = match self.mode {
+            MethodMode::Update => "",
+            MethodMode::Query => " query",
+            MethodMode::Oneway => " oneway",
+        };
+        format!("({}) -> ({}){}", types(&self.args), types(&self.rets), mode)
+    }
+}
+
+/// The methods of a service and their types, parsed from its candid interface.
+///
+/// ```
+/// use ic_test_utils::interface::{MethodMode, ServiceDescription};
+///
+/// let did = "service : (nat) -> { get : () -> (nat) query; add : (nat) -> () }";
+/// let service = ServiceDescription::parse(did).unwrap();
+/// let add = service.method("add").unwrap();
+/// assert_eq!(add.mode, MethodMode::Update);
+/// assert_eq!(add.signature(), "(nat) -> ()");
+///
+/// // Numbers are encoded with the types of the method
+/// let arg = service.encode_arg("add", "(5)").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServiceDescription {
+    /// The named types the methods refer to
+    pub env: TypeEnv,
+    /// The types of the init arguments
+    pub init_args: Vec<Type>,
+    /// The methods, in the order of the interface
+    pub methods: Vec<MethodDescription>,
+}
+
+impl ServiceDescription {
+    /// Parse the service in the candid interface `did`
+    pub fn parse(did: &str) -> Result<Self> {
+        let (env, service) = parse_service(did)?;
+        let init_args = match &service {
+            Type::Class(args, _) => args.clone(),
+            _ => Vec::new(),
+        };
+        let methods = env
+            .as_service(&service)?
+            .iter()
+            .map(|(name, ty)| {
+                let func = env.as_func(ty)?;
+                let mode = match func.modes.first() {
+                    Some(FuncMode::Query) => MethodMode::Query,
+                    Some(FuncMode::Oneway) => MethodMode::Oneway,
+                    None => MethodMode::Update,
+                };
+                Ok(MethodDescription {
+                    name: name.clone(),
+                    mode,
+                    args: func.args.clone(),
+                    rets: func.rets.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            env,
+            init_args,
+            methods,
+        })
+    }
+
+    /// The service of the deployed `canister`, from its `candid:service` metadata,
+    /// or `None` if it has no such metadata.
+    /// This reads the state tree, so it is only available on an agent backend.
+    pub async fn of_canister<T>(canister: &Canister<'_, T>) -> Result<Option<Self>> {
+        match Canister::new_management(canister.backend())
+            .candid_interface(*canister.principal())
+            .await?
+        {
+            Some(did) => Ok(Some(Self::parse(&did)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The method `name`, if the service has it
+    pub fn method(&self, name: &str) -> Option<&MethodDescription> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// Encode the candid text `arg` for `method` with its argument types
+    pub fn encode_arg(&self, method: &str, arg: &str) -> Result<Vec<u8>> {
+        let method = self
+            .method(method)
+            .ok_or_else(|| Error::Generic(format!("The service has no method {}", method)))?;
+        encode_text(arg, Some((&self.env, &method.args)))
+    }
+}
+
+impl fmt::Display for ServiceDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "service : {{")?;
+        for method in &self.methods {
+            writeln!(f, "  {} : {};", method.name, method.signature())?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Panic unless the deployed `canister` has each of the `methods` in its `candid:service`
+/// metadata, to fail fast before a scenario instead of with a reject in the middle of it.
+/// This reads the state tree, so it is only available on an agent backend.
+///
+/// ```
+/// # async fn run(ledger: ic_test_utils::Canister<'_, ()>) {
+/// use ic_test_utils::interface::assert_methods_exist;
+///
+/// assert_methods_exist(&ledger, &["transfer", "account_balance"]).await;
+/// # }
+/// ```
+pub async fn assert_methods_exist<T>(canister: &Canister<'_, T>, methods: &[&str]) {
+    let canister_id = *canister.principal();
+    let service = ServiceDescription::of_canister(canister)
+        .await
+        .unwrap_or_else(|err| panic!("failed to read the interface: {}", err.report()))
+        .unwrap_or_else(|| panic!("canister {} has no candid:service metadata", canister_id));
+    let missing: Vec<_> = methods
+        .iter()
+        .filter(|method| service.method(method).is_none())
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "canister {} doesn't expose {:?}, its interface is:\n{}",
+        canister_id,
+        missing,
+        service
+    );
+}
@@ -0,0 +1,244 @@
+//! Convert between candid values and JSON, for data driven tests with fixture files.
+//!
+//! Candid values are rendered as JSON like this:
+//! records are objects, tuples and vectors are arrays, variants are objects with a
+//! single key, options are `null` or their value, principals are their text.
+//! Numbers that don't fit JSON numbers exactly are strings.
+//! Turning JSON into candid needs the candid types, taken from a `.did` file.
+//!
+//! ```
+//! # async fn run(ledger: ic_test_utils::LedgerCanister<'_>) {
+//! use ic_test_utils::json::{encode_json_args, reply_to_json};
+//!
+//! let did = std::fs::read_to_string("ledger.did").unwrap();
+//! let fixture: serde_json::Value =
+//!     serde_json::from_str(&std::fs::read_to_string("balance.json").unwrap()).unwrap();
+//!
+//! let arg = encode_json_args(&fixture["args"], &did, "account_balance").unwrap();
+//! let reply = ledger.call_query("account_balance", arg).await.unwrap();
+//! assert_eq!(
+//!     reply_to_json(&reply, &did, "account_balance").unwrap(),
+//!     fixture["reply"]
+//! );
+//! # }
+//! ```
+use std::str::FromStr;
+
+use candid::parser::value::{IDLField, IDLValue, VariantValue};
+use candid::types::{Field, Label, Type};
+use candid::{IDLArgs, Principal, TypeEnv};
+use serde_json::{Map, Value};
+
+use crate::interface::parse_service;
+use crate::{Error, Result};
+
+/// The largest integer a JSON number holds exactly in most JSON implementations
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// The JSON of a candid value
+pub fn value_to_json(value: &IDLValue) -> Value {
+    match value {
+        IDLValue::Bool(b) => Value::Bool(*b),
+        IDLValue::Null | IDLValue::None | IDLValue::Reserved => Value::Null,
+        IDLValue::Text(text) => Value::String(text.clone()),
+        IDLValue::Number(number) => number_to_json(number),
+        IDLValue::Float64(f) => float_to_json(*f),
+        IDLValue::Float32(f) => float_to_json(f64::from(*f)),
+        IDLValue::Opt(value) => value_to_json(value),
+        IDLValue::Vec(values) => Value::Array(values.iter().map(value_to_json).collect()),
+        IDLValue::Record(fields) if is_tuple(fields.iter().map(|field| &field.id)) => Value::Array(
+            fields
+                .iter()
+                .map(|field| value_to_json(&field.val))
+                .collect(),
+        ),
+        IDLValue::Record(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|field| (field.id.to_string(), value_to_json(&field.val)))
+                .collect(),
+        ),
+        IDLValue::Variant(VariantValue(field, _)) => {
+            let mut object = Map::new();
+            object.insert(field.id.to_string(), value_to_json(&field.val));
+            Value::Object(object)
+        }
+        IDLValue::Principal(principal) | IDLValue::Service(principal) => {
+            Value::String(principal.to_text())
+        }
+        IDLValue::Func(principal, method) => Value::Array(vec![
+            Value::String(principal.to_text()),
+            Value::String(method.clone()),
+        ]),
+        IDLValue::Int(int) => number_to_json(&int.to_string()),
+        IDLValue::Nat(nat) => number_to_json(&nat.to_string()),
+        IDLValue::Nat8(n) => Value::from(*n),
+        IDLValue::Nat16(n) => Value::from(*n),
+        IDLValue::Nat32(n) => Value::from(*n),
+        IDLValue::Nat64(n) => number_to_json(&n.to_string()),
+        IDLValue::Int8(n) => Value::from(*n),
+        IDLValue::Int16(n) => Value::from(*n),
+        IDLValue::Int32(n) => Value::from(*n),
+        IDLValue::Int64(n) => number_to_json(&n.to_string()),
+    }
+}
+
+/// The JSON array of the values in a candid encoded `reply`,
+/// decoded with the return types of `method` in `did`
+pub fn reply_to_json(reply: &[u8], did: &str, method: &str) -> Result<Value> {
+    let (env, service) = parse_service(did)?;
+    let func = env.get_method(&service, method)?;
+    let args = IDLArgs::from_bytes_with_types(reply, &env, &func.rets)?;
+    Ok(Value::Array(args.args.iter().map(value_to_json).collect()))
+}
+
+/// The candid value of type `ty` for `json`
+pub fn json_to_value(json: &Value, env: &TypeEnv, ty: &Type) -> Result<IDLValue> {
+    let mismatch = || {
+        Error::Generic(format!(
+            "JSON {} doesn't match the candid type {}",
+            json, ty
+        ))
+    };
+
+    let value = match (env.trace_type(ty)?, json) {
+        (Type::Null, Value::Null) => IDLValue::Null,
+        (Type::Reserved, _) => IDLValue::Reserved,
+        (Type::Bool, Value::Bool(b)) => IDLValue::Bool(*b),
+        (Type::Text, Value::String(text)) => IDLValue::Text(text.clone()),
+        (Type::Principal, Value::String(text)) => {
+            IDLValue::Principal(Principal::from_text(text).map_err(|_| mismatch())?)
+        }
+        (Type::Service(_), Value::String(text)) => {
+            IDLValue::Service(Principal::from_text(text).map_err(|_| mismatch())?)
+        }
+        (Type::Nat, json) => IDLValue::Nat(parse_integer(json)?),
+        (Type::Int, json) => IDLValue::Int(parse_integer(json)?),
+        (Type::Nat8, json) => IDLValue::Nat8(parse_integer(json)?),
+        (Type::Nat16, json) => IDLValue::Nat16(parse_integer(json)?),
+        (Type::Nat32, json) => IDLValue::Nat32(parse_integer(json)?),
+        (Type::Nat64, json) => IDLValue::Nat64(parse_integer(json)?),
+        (Type::Int8, json) => IDLValue::Int8(parse_integer(json)?),
+        (Type::Int16, json) => IDLValue::Int16(parse_integer(json)?),
+        (Type::Int32, json) => IDLValue::Int32(parse_integer(json)?),
+        (Type::Int64, json) => IDLValue::Int64(parse_integer(json)?),
+        (Type::Float32, Value::Number(n)) => {
+            IDLValue::Float32(n.as_f64().ok_or_else(mismatch)? as f32)
+        }
+        (Type::Float64, Value::Number(n)) => IDLValue::Float64(n.as_f64().ok_or_else(mismatch)?),
+        (Type::Opt(_), Value::Null) => IDLValue::None,
+        (Type::Opt(inner), json) => IDLValue::Opt(Box::new(json_to_value(json, env, &inner)?)),
+        (Type::Vec(inner), Value::Array(values)) => IDLValue::Vec(
+            values
+                .iter()
+                .map(|value| json_to_value(value, env, &inner))
+                .collect::<Result<_>>()?,
+        ),
+        (Type::Record(fields), Value::Array(values))
+            if is_tuple(fields.iter().map(|field| &field.id)) && values.len() == fields.len() =>
+        {
+            IDLValue::Record(
+                fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| field_value(field, value, env))
+                    .collect::<Result<_>>()?,
+            )
+        }
+        (Type::Record(fields), Value::Object(object)) => IDLValue::Record(
+            fields
+                .iter()
+                .map(|field| match object.get(&field.id.to_string()) {
+                    Some(value) => field_value(field, value, env),
+                    None => field_value(field, &Value::Null, env),
+                })
+                .collect::<Result<_>>()?,
+        ),
+        (Type::Variant(fields), json) => {
+            let (tag, value) = match json {
+                Value::String(tag) => (tag.as_str(), &Value::Null),
+                Value::Object(object) if object.len() == 1 => {
+                    let (tag, value) = object.iter().next().expect("one key");
+                    (tag.as_str(), value)
+                }
+                _ => return Err(mismatch()),
+            };
+            let (index, field) = fields
+                .iter()
+                .enumerate()
+                .find(|(_, field)| field.id.to_string() == tag)
+                .ok_or_else(mismatch)?;
+            IDLValue::Variant(VariantValue(
+                Box::new(field_value(field, value, env)?),
+                index as u64,
+            ))
+        }
+        _ => return Err(mismatch()),
+    };
+    Ok(value)
+}
+
+/// Candid encode the JSON array `json` as the arguments of `method` in `did`
+pub fn encode_json_args(json: &Value, did: &str, method: &str) -> Result<Vec<u8>> {
+    let (env, service) = parse_service(did)?;
+    let func = env.get_method(&service, method)?;
+    let values = match json {
+        Value::Array(values) if values.len() == func.args.len() => values,
+        _ => {
+            return Err(Error::Generic(format!(
+                "Expected a JSON array of the {} arguments of {}, got {}",
+                func.args.len(),
+                method,
+                json
+            )))
+        }
+    };
+    let args = values
+        .iter()
+        .zip(&func.args)
+        .map(|(value, ty)| json_to_value(value, &env, ty))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(IDLArgs::new(&args).to_bytes_with_types(&env, &func.args)?)
+}
+
+fn field_value(field: &Field, json: &Value, env: &TypeEnv) -> Result<IDLField> {
+    Ok(IDLField {
+        id: field.id.clone(),
+        val: json_to_value(json, env, &field.ty)?,
+    })
+}
+
+/// Whether the labels are those of a tuple: `0`, `1`, ...
+fn is_tuple<'a>(labels: impl Iterator<Item = &'a Label>) -> bool {
+    let mut labels = labels.peekable();
+    labels.peek().is_some()
+        && labels
+            .enumerate()
+            .all(|(i, label)| label.get_id() == i as u32)
+}
+
+/// A JSON number, or a string of the digits if the number is too large.
+/// Candid renders large numbers with `_` separators, which are dropped.
+fn number_to_json(number: &str) -> Value {
+    let digits = number.replace('_', "");
+    match digits.parse::<i64>() {
+        Ok(n) if n.unsigned_abs() <= MAX_SAFE_INTEGER => Value::from(n),
+        _ => Value::String(digits),
+    }
+}
+
+fn float_to_json(f: f64) -> Value {
+    serde_json::Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+/// An integer from a JSON number, or from a string holding a large number
+fn parse_integer<T: FromStr>(json: &Value) -> Result<T> {
+    let digits = match json {
+        Value::Number(n) if n.is_u64() || n.is_i64() => n.to_string(),
+        Value::String(digits) => digits.replace('_', ""),
+        _ => String::new(),
+    };
+    digits
+        .parse()
+        .map_err(|_| Error::Generic(format!("Expected an integer, got {}", json)))
+}
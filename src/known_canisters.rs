@@ -0,0 +1,48 @@
+//! The principals of well known mainnet canisters.
+//!
+//! The NNS canisters have the same ids on a local replica with the NNS installed,
+//! like `dfx nns install` sets it up.
+//!
+//! ```
+//! use ic_test_utils::known_canisters::{CYCLES_MINTING, ICP_LEDGER};
+//! use ic_test_utils::canister::{CYCLES_MINTING_CANISTER_ID, LEDGER_CANISTER_ID};
+//!
+//! assert_eq!(ICP_LEDGER.to_text(), LEDGER_CANISTER_ID);
+//! assert_eq!(CYCLES_MINTING.to_text(), CYCLES_MINTING_CANISTER_ID);
+//! ```
+use candid::Principal;
+
+/// The NNS registry, `rwlgt-iiaaa-aaaaa-aaaaa-cai`
+pub const REGISTRY: Principal = nns_canister(0);
+
+/// The NNS governance, `rrkah-fqaaa-aaaaa-aaaaq-cai`
+pub const GOVERNANCE: Principal = nns_canister(1);
+
+/// The ICP ledger, `ryjl3-tyaaa-aaaaa-aaaba-cai`
+pub const ICP_LEDGER: Principal = nns_canister(2);
+
+/// The NNS root, `r7inp-6aaaa-aaaaa-aaabq-cai`
+pub const NNS_ROOT: Principal = nns_canister(3);
+
+/// The cycles minting canister, `rkp4c-7iaaa-aaaaa-aaaca-cai`
+pub const CYCLES_MINTING: Principal = nns_canister(4);
+
+/// The NNS lifeline, `rno2w-sqaaa-aaaaa-aaacq-cai`
+pub const LIFELINE: Principal = nns_canister(5);
+
+/// Internet Identity, `rdmx6-jaaaa-aaaaa-aaadq-cai`
+pub const INTERNET_IDENTITY: Principal = nns_canister(7);
+
+/// The SNS-W canister, `qaa6y-5yaaa-aaaaa-aaafa-cai`
+pub const SNS_WASM: Principal = nns_canister(10);
+
+/// The ICP ledger index, `qhbym-qaaaa-aaaaa-aaafq-cai`
+pub const ICP_INDEX: Principal = nns_canister(11);
+
+/// The exchange rate canister, `uf6dk-hyaaa-aaaaq-qaaaq-cai`
+pub const EXCHANGE_RATE: Principal = Principal::from_slice(&[0, 0, 0, 0, 2, 16, 0, 1, 1, 1]);
+
+/// The id of the `index`th canister of the NNS subnet
+const fn nns_canister(index: u8) -> Principal {
+    Principal::from_slice(&[0, 0, 0, 0, 0, 0, 0, index, 1, 1])
+}
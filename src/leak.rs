@@ -0,0 +1,194 @@
+//! Catch memory leaks by repeating an operation.
+//!
+//! A [`LeakCheck`] runs an operation a number of times and reads the memory of a
+//! canister with `canister_status` after each run. It fails if the memory grows by
+//! more than a number of bytes per run, fitted as a line through the samples, so the
+//! growth of the heap in whole pages averages out.
+//!
+//! ```
+//! # async fn run(management: ic_test_utils::ManagementCanister<'_>, store: ic_test_utils::Canister<'_, ()>) {
+//! use candid::Encode;
+//! use ic_test_utils::leak::LeakCheck;
+//!
+//! let report = LeakCheck::new(&management, *store.principal())
+//!     .iterations(50)
+//!     .max_growth(512)
+//!     .run(|i| {
+//!         let store = &store;
+//!         async move {
+//!             store.call_update("put", Encode!(&i, &"value")?).await?;
+//!             store.call_update("remove", Encode!(&i)?).await?;
+//!             Ok(())
+//!         }
+//!     })
+//!     .await
+//!     .unwrap();
+//! println!("{:.0} bytes per iteration", report.total_growth());
+//! # }
+//! ```
+use std::future::Future;
+
+use candid::Principal;
+
+use crate::{Error, ManagementCanister, Result};
+
+/// Settings of a leak check
+pub struct LeakCheck<'a, 'agent> {
+    management: &'a ManagementCanister<'agent>,
+    canister_id: Principal,
+    iterations: u32,
+    warmup: u32,
+    max_growth: u64,
+}
+
+impl<'a, 'agent> LeakCheck<'a, 'agent> {
+    /// A leak check of `canister_id`, read through `management`, so the caller has to
+    /// be a controller. By default the operation runs 3 times to warm up, then 20 times
+    /// with the memory allowed to grow by 1 KiB per run.
+    pub fn new(management: &'a ManagementCanister<'agent>, canister_id: Principal) -> Self {
+        Self {
+            management,
+            canister_id,
+            iterations: 20,
+            warmup: 3,
+            max_growth: 1024,
+        }
+    }
+
+    /// The runs the memory is sampled after, at least 2, so a growth can be fitted
+    /// through the samples
+    pub fn iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations.max(2);
+        self
+    }
+
+    /// The runs before the first sample, to fill caches and lazily allocated memory
+    pub fn warmup(mut self, warmup: u32) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// The bytes the heap, the stable memory or the total memory may grow by per run
+    pub fn max_growth(mut self, bytes: u64) -> Self {
+        self.max_growth = bytes;
+        self
+    }
+
+    /// Run `operation` with the number of the run, counting the warmup, and sample the
+    /// memory after each run. Fails if the operation fails, or if a kind of memory grows
+    /// faster than allowed.
+    pub async fn run<F, Fut>(self, mut operation: F) -> Result<MemoryReport>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        for i in 0..self.warmup {
+            operation(i).await?;
+        }
+
+        let mut report = MemoryReport {
+            samples: vec![self.sample().await?],
+        };
+        for i in self.warmup..self.warmup + self.iterations {
+            operation(i).await?;
+            report.samples.push(self.sample().await?);
+        }
+
+        let (first, last) = (report.samples[0], report.samples[report.samples.len() - 1]);
+        let kinds = [
+            ("heap", report.heap_growth(), first.heap, last.heap),
+            (
+                "stable memory",
+                report.stable_growth(),
+                first.stable,
+                last.stable,
+            ),
+            (
+                "total memory",
+                Some(report.total_growth()),
+                Some(first.total),
+                Some(last.total),
+            ),
+        ];
+        for (kind, growth, first, last) in kinds {
+            if let Some(growth) = growth.filter(|growth| *growth > self.max_growth as f64) {
+                return Err(Error::Generic(format!(
+                    "The {} of canister {} grows by {:.0} bytes per iteration, more than {} \
+                     (from {} to {} bytes over {} iterations)",
+                    kind,
+                    self.canister_id,
+                    growth,
+                    self.max_growth,
+                    first.unwrap_or_default(),
+                    last.unwrap_or_default(),
+                    self.iterations,
+                )));
+            }
+        }
+        Ok(report)
+    }
+
+    async fn sample(&self) -> Result<MemorySample> {
+        let status = self.management.canister_status(self.canister_id).await?;
+        Ok(MemorySample {
+            heap: status.heap_memory_size(),
+            stable: status.stable_memory_size(),
+            total: status.total_memory_size(),
+        })
+    }
+}
+
+/// The memory of a canister after a run, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySample {
+    /// The wasm heap, if the replica reports it
+    pub heap: Option<u64>,
+    /// The stable memory, if the replica reports it
+    pub stable: Option<u64>,
+    /// All memory
+    pub total: u64,
+}
+
+/// The samples of a [`LeakCheck`]
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    /// The sample before the first run after the warmup, then one after each run
+    pub samples: Vec<MemorySample>,
+}
+
+impl MemoryReport {
+    /// The bytes the heap grew by per run, if the replica reports it
+    pub fn heap_growth(&self) -> Option<f64> {
+        let heap: Option<Vec<u64>> = self.samples.iter().map(|sample| sample.heap).collect();
+        heap.map(|heap| slope(&heap))
+    }
+
+    /// The bytes the stable memory grew by per run, if the replica reports it
+    pub fn stable_growth(&self) -> Option<f64> {
+        let stable: Option<Vec<u64>> = self.samples.iter().map(|sample| sample.stable).collect();
+        stable.map(|stable| slope(&stable))
+    }
+
+    /// The bytes all memory grew by per run
+    pub fn total_growth(&self) -> f64 {
+        let total: Vec<u64> = self.samples.iter().map(|sample| sample.total).collect();
+        slope(&total)
+    }
+}
+
+/// The slope of the least squares line through the `values` at 0, 1, 2 and so on
+fn slope(values: &[u64]) -> f64 {
+    let n = values.len() as f64;
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = values.iter().map(|v| *v as f64).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in values.iter().enumerate() {
+        let dx = x as f64 - mean_x;
+        covariance += dx * (*y as f64 - mean_y);
+        variance += dx * dx;
+    }
+    covariance / variance
+}
@@ -1,20 +1,92 @@
 #![deny(missing_docs)]
+#![allow(clippy::result_large_err)]
 #![doc = include_str!("../README.md")]
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "wallet")]
 use candid::utils::ArgumentEncoder;
-use ic_agent::ic_types::Principal;
 use ic_agent::identity::BasicIdentity;
-use ic_agent::{agent::http_transport::ReqwestHttpReplicaV2Transport, identity::PemError};
+use ic_agent::identity::PemError;
+use ic_agent::Identity;
+use ring::signature::Ed25519KeyPair;
+
+#[cfg(feature = "wallet")]
+use crate::backend::Backend;
+#[cfg(feature = "wallet")]
+use crate::cleanup::CanisterGuard;
 
 pub use ic_agent::Agent;
 
+/// The candid crate this crate is built with, to encode arguments with the same version
+pub use candid;
+/// The agent crate this crate is built with
+pub use ic_agent;
+
 mod errors;
-pub use errors::{Error, Result};
+pub use errors::{Error, ErrorKind, RejectCode, Result};
+
+pub use ic_test_utils_macros::ic_test;
 
+pub mod assert;
+pub mod backend;
+pub mod bench;
+#[cfg(feature = "bitcoin")]
+pub mod bitcoin;
 pub mod canister;
+pub mod certification;
+pub mod chaos;
+pub mod cleanup;
+#[cfg(feature = "candid-0.10")]
+pub mod compat;
+pub mod context;
+pub mod cost;
+pub mod deadline;
+#[cfg(feature = "wallet")]
+pub mod deploy;
+#[cfg(feature = "dfx-integration")]
+pub mod dfx;
+pub mod diff;
+#[cfg(feature = "embedded-canisters")]
+pub mod embedded;
+#[cfg(all(feature = "ledger", feature = "wallet"))]
+pub mod fixtures;
+pub mod fuzz;
+pub mod golden;
+pub mod harness;
+pub mod http;
+pub mod interface;
+pub mod json;
+pub mod known_canisters;
+pub mod leak;
+pub mod load;
+pub mod logs;
+#[cfg(feature = "wallet")]
+pub mod manifest;
+pub mod metrics;
+pub mod namespace;
+#[cfg(feature = "wallet")]
+pub mod pool;
+pub mod prelude;
+pub mod registry;
+#[cfg(feature = "dfx-integration")]
+pub mod replica;
+pub mod rng;
+pub mod scenario;
+pub mod snapshot;
+#[cfg(feature = "wallet")]
+pub mod threshold;
+pub mod time;
+pub mod timers;
+pub mod transport;
+pub mod upgrade;
+pub mod wait;
+pub mod wasm;
 
-pub use canister::{Canister, Management, ManagementCanister, Wallet, WalletCanister};
+pub use canister::{Canister, Management, ManagementCanister};
+#[cfg(feature = "wallet")]
+pub use canister::{CanisterBuilder, Wallet, WalletCanister};
+#[cfg(feature = "ledger")]
+pub use canister::{Ledger, LedgerCanister, SnsWasm, SnsWasmCanister};
 
 const URL: &str = "http://localhost:8000";
 
@@ -24,11 +96,7 @@ const URL: &str = "http://localhost:8000";
 /// If this is ever needed outside of `get_agent` just make this
 /// function public.
 pub fn get_identity(account_name: impl AsRef<Path>) -> Result<BasicIdentity> {
-    let mut ident_path = dirs::home_dir().ok_or(crate::Error::MissingConfig)?;
-    ident_path.push(".config");
-    ident_path.push("dfx/identity");
-    ident_path.push(account_name);
-    ident_path.push("identity.pem");
+    let ident_path = identity_path(account_name)?;
     match BasicIdentity::from_pem_file(&ident_path) {
         Ok(identity) => Ok(identity),
         Err(PemError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
@@ -38,6 +106,27 @@ pub fn get_identity(account_name: impl AsRef<Path>) -> Result<BasicIdentity> {
     }
 }
 
+/// The pem file of the dfx identity of an account
+pub(crate) fn identity_path(account_name: impl AsRef<Path>) -> Result<PathBuf> {
+    let mut ident_path = dirs::home_dir().ok_or(crate::Error::MissingConfig)?;
+    ident_path.push(".config");
+    ident_path.push("dfx/identity");
+    ident_path.push(account_name);
+    ident_path.push("identity.pem");
+    Ok(ident_path)
+}
+
+/// Generate a new random identity, for tests that need many distinct callers.
+/// See [`rng::TestRng::identity`] for identities that are the same on every run.
+pub fn generate_identity() -> Result<BasicIdentity> {
+    let rng = ring::rand::SystemRandom::new();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)
+        .map_err(|e| Error::Generic(format!("Failed to generate a key: {}", e)))?;
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|e| Error::Generic(format!("Failed to read the generated key: {}", e)))?;
+    Ok(BasicIdentity::from_key_pair(key_pair))
+}
+
 /// Get an agent by identity name.
 ///
 /// This is assuming there is an agent identity available.
@@ -50,9 +139,25 @@ pub fn get_identity(account_name: impl AsRef<Path>) -> Result<BasicIdentity> {
 /// ```
 pub async fn get_agent(name: impl Into<&str>, url: Option<&str>) -> Result<Agent> {
     let identity = get_identity(name.into())?;
+    get_agent_with_identity(identity, url).await
+}
 
-    let url = url.unwrap_or(URL);
-    let transport = ReqwestHttpReplicaV2Transport::create(url)?;
+/// Get an agent for an identity, see [`generate_identity`].
+pub async fn get_agent_with_identity(
+    identity: impl Identity + 'static,
+    url: Option<&str>,
+) -> Result<Agent> {
+    get_agent_with_transport(identity, url, transport::Transport::default()).await
+}
+
+/// Get an agent for an identity talking to the replica through `transport`,
+/// see [`transport`].
+pub async fn get_agent_with_transport(
+    identity: impl Identity + 'static,
+    url: Option<&str>,
+    transport: transport::Transport,
+) -> Result<Agent> {
+    let transport = transport.create(url.unwrap_or(URL))?;
 
     let agent = Agent::builder()
         .with_transport(transport)
@@ -64,29 +169,33 @@ pub async fn get_agent(name: impl Into<&str>, url: Option<&str>) -> Result<Agent
     Ok(agent)
 }
 
-/// Create a default `Delay` with a throttle of 500ms
-/// and a timout of five minutes.
+/// Create a default `Delay` polling after 50ms first, backing off up to 1s,
+/// with a timout of five minutes, see [`wait::Backoff`].
 pub fn get_waiter() -> garcon::Delay {
-    garcon::Delay::builder()
-        .throttle(std::time::Duration::from_millis(500))
-        .timeout(std::time::Duration::from_secs(60 * 5))
-        .build()
+    garcon::Delay::from(Box::new(wait::Backoff::new(
+        std::time::Duration::from_millis(50),
+        std::time::Duration::from_secs(1),
+        std::time::Duration::from_secs(60 * 5),
+    )))
 }
 
 /// Create a canister and install
 /// the provided byte code.
+///
+/// The canister is deleted when the returned guard is dropped,
+/// call [`CanisterGuard::keep`] to keep it.
+#[cfg(feature = "wallet")]
 pub async fn create_canister<T: ArgumentEncoder>(
-    agent: &Agent,
+    backend: &dyn Backend,
     account_name: impl AsRef<str>,
-    bytecode: Vec<u8>,
+    bytecode: impl AsRef<[u8]>,
     arg: T,
     cycles: u64,
-) -> Result<Principal> {
-    let wallet = Canister::new_wallet(agent, account_name, None)?;
-    let management = Canister::new_management(agent);
+) -> Result<CanisterGuard> {
+    let wallet = Canister::new_wallet(backend, account_name, None)?;
+    let management = Canister::new_management(backend);
     let canister_id = wallet.create_canister(cycles, None).await?;
-    management
-        .install_code(agent, canister_id, bytecode, arg)
-        .await?;
-    Ok(canister_id)
+    let guard = CanisterGuard::new(canister_id, backend);
+    management.install_code(canister_id, bytecode, arg).await?;
+    Ok(guard)
 }
@@ -0,0 +1,188 @@
+//! Put a canister under load from many callers.
+//!
+//! A [`LoadTest`] runs a scenario for a number of virtual users, each with its own
+//! identity, at a target rate for a fixed duration, and reports the latencies,
+//! errors and throughput.
+//!
+//! ```
+//! # async fn run(counter: candid::Principal) {
+//! use std::time::Duration;
+//!
+//! use candid::Encode;
+//! use ic_test_utils::load::LoadTest;
+//! use ic_test_utils::Canister;
+//!
+//! struct Counter;
+//!
+//! let report = LoadTest::new(None)
+//!     .users(20)
+//!     .rate(50.0)
+//!     .duration(Duration::from_secs(30))
+//!     .run(|agent, _user| async move {
+//!         let counter = Canister::<Counter>::new(counter, &agent);
+//!         counter.call_update("inc", Encode!()?).await?;
+//!         Ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! println!("p99 {:?}, {} errors", report.percentile(99.0), report.errors);
+//! # }
+//! ```
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+
+use crate::rng::TestRng;
+use crate::{generate_identity, get_agent_with_identity, Agent, Result};
+
+/// Settings of a load test
+pub struct LoadTest {
+    url: Option<String>,
+    users: usize,
+    rate: f64,
+    duration: Duration,
+    rng: Option<TestRng>,
+}
+
+impl LoadTest {
+    /// A load test against the replica at `url`, or the default local replica.
+    /// By default one user makes one call per second for ten seconds.
+    pub fn new(url: Option<&str>) -> Self {
+        Self {
+            url: url.map(Into::into),
+            users: 1,
+            rate: 1.0,
+            duration: Duration::from_secs(10),
+            rng: None,
+        }
+    }
+
+    /// The number of virtual users, each gets a new identity
+    pub fn users(mut self, users: usize) -> Self {
+        self.users = users.max(1);
+        self
+    }
+
+    /// The target number of scenarios per second, over all users.
+    /// Without a positive rate each user starts the next scenario as soon as the
+    /// previous one is done.
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Generate the identities of the users from `rng`, instead of new random ones,
+    /// so the users are the same when the test is reproduced
+    pub fn rng(mut self, rng: &mut TestRng) -> Self {
+        self.rng = Some(rng.fork());
+        self
+    }
+
+    /// How long to run the load for
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Run `scenario` repeatedly for each user until the duration has passed.
+    /// The scenario gets the agent of the user and the index of the user.
+    ///
+    /// A user starts the next scenario when the previous one is done, so if the
+    /// scenarios are slower than the target rate the load is lower than the rate.
+    pub async fn run<F, Fut>(mut self, scenario: F) -> Result<LoadReport>
+    where
+        F: Fn(Agent, usize) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut agents = Vec::with_capacity(self.users);
+        for _ in 0..self.users {
+            let identity = match &mut self.rng {
+                Some(rng) => rng.identity()?,
+                None => generate_identity()?,
+            };
+            agents.push(get_agent_with_identity(identity, self.url.as_deref()).await?);
+        }
+
+        // Without a rate the users run their scenarios back to back
+        let interval = if self.rate.is_finite() && self.rate > 0.0 {
+            Duration::try_from_secs_f64(self.users as f64 / self.rate).unwrap_or(Duration::MAX)
+        } else {
+            Duration::ZERO
+        };
+        let start = Instant::now();
+        let deadline = start + self.duration;
+
+        let scenario = &scenario;
+        let users = agents
+            .into_iter()
+            .enumerate()
+            .map(|(user, agent)| async move {
+                let mut latencies = Vec::new();
+                let mut errors = 0;
+                // Spread the users over the first interval
+                let mut next = start
+                    .checked_add(interval.mul_f64(user as f64 / self.users as f64))
+                    .unwrap_or(deadline);
+                while next < deadline && Instant::now() < deadline {
+                    tokio::time::sleep_until(next.into()).await;
+                    let call_start = Instant::now();
+                    match scenario(agent.clone(), user).await {
+                        Ok(()) => latencies.push(call_start.elapsed()),
+                        Err(_) => errors += 1,
+                    }
+                    next = match next.checked_add(interval) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+                (latencies, errors)
+            });
+        let results = join_all(users).await;
+
+        let mut report = LoadReport {
+            latencies: Vec::new(),
+            errors: 0,
+            elapsed: start.elapsed(),
+        };
+        for (latencies, errors) in results {
+            report.latencies.extend(latencies);
+            report.errors += errors;
+        }
+        report.latencies.sort();
+        Ok(report)
+    }
+}
+
+/// The results of a [`LoadTest`]
+#[derive(Debug, Clone)]
+pub struct LoadReport {
+    /// Latencies of the successful scenarios, sorted
+    pub latencies: Vec<Duration>,
+    /// Number of scenarios that returned an error
+    pub errors: usize,
+    /// How long the load test took
+    pub elapsed: Duration,
+}
+
+impl LoadReport {
+    /// Number of successful scenarios
+    pub fn successes(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// The latency under which `percent` of the successful scenarios finished
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (percent.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil();
+        let index = (rank as usize).clamp(1, self.latencies.len()) - 1;
+        Some(self.latencies[index])
+    }
+
+    /// Successful scenarios per second
+    pub fn throughput(&self) -> f64 {
+        self.successes() as f64 / self.elapsed.as_secs_f64()
+    }
+}
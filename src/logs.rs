@@ -0,0 +1,227 @@
+//! Assert on what a canister logs.
+//!
+//! A [`LogCapture`] remembers where the log of a canister was when it started, and
+//! collects the lines logged since then.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal) {
+//! use ic_test_utils::logs::LogCapture;
+//! use ic_test_utils::Canister;
+//!
+//! let management = Canister::new_management(agent);
+//! let capture = LogCapture::start(&management, ledger).await.unwrap();
+//! // Transfer tokens etc.
+//! let logs = capture.collect().await.unwrap();
+//! logs.assert_contains("transfer");
+//! logs.assert_not_contains("panicked");
+//! logs.assert_matches(r"fee: \d+");
+//! # }
+//! ```
+//!
+//! A [`PrintCapture`] collects the `ic_cdk::print` output of several canisters, and
+//! dumps it when the test fails, so the prints show up next to the failure:
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal, index: candid::Principal) {
+//! use ic_test_utils::logs::PrintCapture;
+//! use ic_test_utils::Canister;
+//!
+//! let management = Canister::new_management(agent);
+//! let prints = PrintCapture::start(&management, &[ledger, index]).await.unwrap();
+//! prints
+//!     .dump_on_failure(async {
+//!         // Transfer tokens etc.
+//!         Ok(())
+//!     })
+//!     .await
+//!     .unwrap();
+//! prints.collect_for(ledger).await.unwrap().assert_contains("transfer");
+//! # }
+//! ```
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+
+use candid::Principal;
+use futures::FutureExt;
+use regex::Regex;
+
+use crate::canister::{CanisterLogRecord, ManagementCanister};
+use crate::{Error, Result};
+
+/// Captures the log lines of a canister from the moment it is started.
+pub struct LogCapture<'a, 'agent> {
+    management: &'a ManagementCanister<'agent>,
+    canister_id: Principal,
+    next_idx: u64,
+}
+
+impl<'a, 'agent> LogCapture<'a, 'agent> {
+    /// Start capturing the logs of `canister_id`.
+    /// Lines logged before are ignored.
+    pub async fn start(
+        management: &'a ManagementCanister<'agent>,
+        canister_id: Principal,
+    ) -> Result<LogCapture<'a, 'agent>> {
+        let records = management.fetch_canister_logs(canister_id).await?;
+        let next_idx = records.last().map_or(0, |record| record.idx + 1);
+        Ok(Self {
+            management,
+            canister_id,
+            next_idx,
+        })
+    }
+
+    /// The lines logged since the capture started.
+    /// The canister only keeps a limited log, lines can be lost if it logs a lot.
+    pub async fn collect(&self) -> Result<CapturedLogs> {
+        let records = self
+            .management
+            .fetch_canister_logs(self.canister_id)
+            .await?
+            .into_iter()
+            .filter(|record| record.idx >= self.next_idx)
+            .collect();
+        Ok(CapturedLogs { records })
+    }
+}
+
+/// Captures the `debug_print` output of several canisters from the moment it is started.
+/// The prints are read from the canister logs, so the replica or PocketIC server has to
+/// support `fetch_canister_logs`.
+pub struct PrintCapture<'a, 'agent> {
+    captures: Vec<LogCapture<'a, 'agent>>,
+}
+
+impl<'a, 'agent> PrintCapture<'a, 'agent> {
+    /// Start capturing the prints of `canister_ids`.
+    /// Lines printed before are ignored.
+    pub async fn start(
+        management: &'a ManagementCanister<'agent>,
+        canister_ids: &[Principal],
+    ) -> Result<PrintCapture<'a, 'agent>> {
+        let mut captures = Vec::new();
+        for canister_id in canister_ids {
+            captures.push(LogCapture::start(management, *canister_id).await?);
+        }
+        Ok(Self { captures })
+    }
+
+    /// The lines each canister printed since the capture started, in the order the
+    /// canisters were given
+    pub async fn collect(&self) -> Result<Vec<(Principal, CapturedLogs)>> {
+        let mut prints = Vec::new();
+        for capture in &self.captures {
+            prints.push((capture.canister_id, capture.collect().await?));
+        }
+        Ok(prints)
+    }
+
+    /// The lines `canister_id` printed since the capture started.
+    /// Fails if the canister is not captured.
+    pub async fn collect_for(&self, canister_id: Principal) -> Result<CapturedLogs> {
+        match self
+            .captures
+            .iter()
+            .find(|capture| capture.canister_id == canister_id)
+        {
+            Some(capture) => capture.collect().await,
+            None => Err(Error::Generic(format!(
+                "The prints of canister {} are not captured",
+                canister_id
+            ))),
+        }
+    }
+
+    /// Print the lines of each canister to stderr
+    pub async fn dump(&self) {
+        match self.collect().await {
+            Ok(prints) => {
+                for (canister_id, logs) in prints {
+                    eprintln!("prints of canister {}:", canister_id);
+                    for line in logs.lines() {
+                        eprintln!("  {}", line);
+                    }
+                }
+            }
+            Err(err) => eprintln!("failed to collect the canister prints: {}", err.report()),
+        }
+    }
+
+    /// Run `test`, and [`dump`](Self::dump) the prints if it fails or panics.
+    /// A panic is resumed after the dump.
+    pub async fn dump_on_failure<T, Fut>(&self, test: Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        match AssertUnwindSafe(test).catch_unwind().await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => {
+                self.dump().await;
+                Err(err)
+            }
+            Err(panic) => {
+                self.dump().await;
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+}
+
+/// Log lines collected by a [`LogCapture`]
+#[derive(Debug, Clone)]
+pub struct CapturedLogs {
+    /// The log records, oldest first
+    pub records: Vec<CanisterLogRecord>,
+}
+
+impl CapturedLogs {
+    /// The log lines as text
+    pub fn lines(&self) -> Vec<String> {
+        self.records
+            .iter()
+            .map(|record| String::from_utf8_lossy(&record.content).into_owned())
+            .collect()
+    }
+
+    /// Whether a line contains `needle`
+    pub fn contains(&self, needle: &str) -> bool {
+        self.lines().iter().any(|line| line.contains(needle))
+    }
+
+    /// Whether a line matches the regex `pattern`.
+    /// Panics if the pattern is not a valid regex.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let regex = Regex::new(pattern).expect("invalid regex");
+        self.lines().iter().any(|line| regex.is_match(line))
+    }
+
+    /// Panic unless a line contains `needle`
+    pub fn assert_contains(&self, needle: &str) {
+        assert!(
+            self.contains(needle),
+            "no log line contains {:?}, the logs are:\n{}",
+            needle,
+            self.lines().join("\n")
+        );
+    }
+
+    /// Panic if a line contains `needle`
+    pub fn assert_not_contains(&self, needle: &str) {
+        assert!(
+            !self.contains(needle),
+            "a log line contains {:?}, the logs are:\n{}",
+            needle,
+            self.lines().join("\n")
+        );
+    }
+
+    /// Panic unless a line matches the regex `pattern`
+    pub fn assert_matches(&self, pattern: &str) {
+        assert!(
+            self.matches(pattern),
+            "no log line matches {:?}, the logs are:\n{}",
+            pattern,
+            self.lines().join("\n")
+        );
+    }
+}
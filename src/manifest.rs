@@ -0,0 +1,175 @@
+//! Describe a whole test environment in a manifest file.
+//!
+//! ```toml
+//! account = "alice"
+//!
+//! [canisters.ledger]
+//! wasm = "wasm/ledger.wasm.gz"
+//! candid = "wasm/ledger.did"
+//! init_arg = '(variant { Init = record { minting_account = "{{env.MINTER}}" } })'
+//!
+//! [canisters.backend]
+//! wasm = "target/wasm32-unknown-unknown/release/backend.wasm"
+//! cycles = 5_000_000_000_000
+//! controllers = ["2vxsx-fae"]
+//! init_arg = '(principal "{{ledger}}")'
+//! ```
+//!
+//! Init arguments are candid text. `{{name}}` is replaced by the principal of the
+//! canister `name` of the manifest, which is then installed first, and `{{env.VAR}}`
+//! by the env var `VAR`. Paths are relative to the manifest.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent) {
+//! use ic_test_utils::manifest::Environment;
+//!
+//! let registry = Environment::from_manifest("env.toml")
+//!     .unwrap()
+//!     .deploy(agent)
+//!     .await
+//!     .unwrap();
+//! let backend = registry.resolve("backend").unwrap();
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use candid::Principal;
+use serde::Deserialize;
+
+use crate::backend::Backend;
+use crate::deploy::DeployPlan;
+use crate::harness::DEFAULT_CYCLES;
+use crate::interface::{encode_text, init_types};
+use crate::registry::CanisterRegistry;
+use crate::wasm::load_wasm;
+use crate::{Canister, Error, Result};
+
+/// The content of a manifest file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    /// The account whose wallet creates the canisters
+    pub account: String,
+    /// Cycles of each canister, unless set per canister
+    pub cycles: Option<u64>,
+    /// The canisters by name
+    pub canisters: BTreeMap<String, ManifestCanister>,
+}
+
+/// A canister in a [`Manifest`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestCanister {
+    /// The wasm module, raw or gzip compressed
+    pub wasm: PathBuf,
+    /// The candid file, used to encode the init argument with the right types
+    pub candid: Option<PathBuf>,
+    /// Cycles of the canister
+    pub cycles: Option<u64>,
+    /// Extra controllers, the wallet is always a controller
+    #[serde(default)]
+    pub controllers: Vec<String>,
+    /// The init argument as candid text, `()` if not set
+    pub init_arg: Option<String>,
+}
+
+/// A test environment read from a manifest.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    root: PathBuf,
+    manifest: Manifest,
+}
+
+impl Environment {
+    /// Read a TOML manifest
+    pub fn from_manifest(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| Error::Generic(format!("Invalid manifest {}: {}", path.display(), e)))?;
+        let root = path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf();
+        Ok(Self { root, manifest })
+    }
+
+    /// The manifest
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Create and install all the canisters of the manifest.
+    /// Returns a registry with the principals of the canisters.
+    pub async fn deploy(&self, backend: &dyn Backend) -> Result<CanisterRegistry> {
+        let wallet = Canister::new_wallet(backend, &self.manifest.account, None)?;
+        let plan = self.plan(*wallet.principal())?;
+        plan.deploy(&wallet, self.manifest.cycles.unwrap_or(DEFAULT_CYCLES))
+            .await
+    }
+
+    fn plan(&self, wallet: Principal) -> Result<DeployPlan> {
+        let mut plan = DeployPlan::new();
+        for (name, canister) in &self.manifest.canisters {
+            let wasm = load_wasm(self.root.join(&canister.wasm))?;
+            let types = match &canister.candid {
+                Some(candid) => Some(init_types(&std::fs::read_to_string(
+                    self.root.join(candid),
+                )?)?),
+                None => None,
+            };
+            let template = canister.init_arg.clone().unwrap_or_else(|| "()".into());
+            let dependencies = placeholders(&template)
+                .into_iter()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.starts_with("env."))
+                .collect::<Vec<_>>();
+            let dependencies = dependencies.iter().map(String::as_str).collect::<Vec<_>>();
+
+            plan = plan.canister(name, wasm, &dependencies, move |registry| {
+                let text = render(&template, registry)?;
+                encode_text(&text, types.as_ref().map(|(env, ty)| (env, &ty[..])))
+            });
+            if let Some(cycles) = canister.cycles {
+                plan = plan.cycles(name, cycles);
+            }
+            if !canister.controllers.is_empty() {
+                let mut controllers = vec![wallet];
+                for controller in &canister.controllers {
+                    controllers.push(Principal::from_text(controller)?);
+                }
+                plan = plan.controllers(name, controllers);
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// The placeholders between `{{` and `}}` in `template`, untrimmed
+fn placeholders(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                names.push(after[..end].to_string());
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Replace the placeholders in `template` by principals and env vars
+fn render(template: &str, registry: &CanisterRegistry) -> Result<String> {
+    let mut text = template.to_string();
+    for raw in placeholders(template) {
+        let name = raw.trim();
+        let value = match name.strip_prefix("env.") {
+            Some(var) => std::env::var(var)?,
+            None => registry.resolve(name)?.to_text(),
+        };
+        text = text.replace(&format!("{{{{{}}}}}", raw), &value);
+    }
+    Ok(text)
+}
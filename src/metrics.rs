@@ -0,0 +1,407 @@
+//! Read the metrics a canister exports in the Prometheus text format.
+//!
+//! ```
+//! # async fn run(canister: ic_test_utils::Canister<'_, ()>) {
+//! use ic_test_utils::metrics::scrape_metrics;
+//!
+//! let metrics = scrape_metrics(&canister).await.unwrap();
+//! assert_eq!(metrics.get("transfers_total"), Some(3.0));
+//! assert_eq!(
+//!     metrics.get_with_labels("errors_total", &[("kind", "insufficient_funds")]),
+//!     Some(1.0)
+//! );
+//! # }
+//! ```
+//!
+//! The other way round, a [`CallMetrics`] backend counts the calls a test run makes,
+//! the failures by reject code and the latency per canister and method, and exports
+//! them in the Prometheus text format, e.g. for the textfile collector of a node
+//! exporter next to a nightly soak test:
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal) {
+//! use candid::Encode;
+//! use ic_test_utils::metrics::CallMetrics;
+//! use ic_test_utils::Canister;
+//!
+//! let metrics = CallMetrics::new(agent);
+//! let ledger = Canister::<()>::new(ledger, &metrics);
+//! for _ in 0..100 {
+//!     let _ = ledger.call_update("transfer", Encode!(&100u64).unwrap()).await;
+//! }
+//! metrics.save("/var/lib/node_exporter/soak.prom").unwrap();
+//! # }
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use ic_agent::ic_types::Principal;
+use ic_agent::Agent;
+
+use crate::backend::{Backend, BackendFuture};
+use crate::http::{http_request, HttpRequest};
+use crate::{Canister, Error, Result};
+
+/// The path the metrics are served at
+pub const METRICS_PATH: &str = "/metrics";
+
+/// A sample of a metric
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// The metric name
+    pub name: String,
+    /// The labels of the sample
+    pub labels: BTreeMap<String, String>,
+    /// The value
+    pub value: f64,
+}
+
+/// The samples of a metrics page
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    samples: Vec<Sample>,
+}
+
+impl Metrics {
+    /// Parse a page in the Prometheus text format.
+    /// Comments, like `# HELP` and `# TYPE`, and timestamps are ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let samples = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(parse_sample)
+            .collect::<Result<_>>()?;
+        Ok(Self { samples })
+    }
+
+    /// All the samples
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// The value of the metric `name`, the first sample if it has several
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.samples
+            .iter()
+            .find(|sample| sample.name == name)
+            .map(|sample| sample.value)
+    }
+
+    /// The value of the sample of `name` that has all the `labels`
+    pub fn get_with_labels(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        self.samples
+            .iter()
+            .find(|sample| {
+                sample.name == name
+                    && labels.iter().all(|(key, value)| {
+                        sample.labels.get(*key).map(String::as_str) == Some(*value)
+                    })
+            })
+            .map(|sample| sample.value)
+    }
+}
+
+/// Query the [`METRICS_PATH`] of the canister through its `http_request` query
+pub async fn scrape_metrics<T>(canister: &Canister<'_, T>) -> Result<Metrics> {
+    let response = http_request(canister, HttpRequest::get(METRICS_PATH)).await?;
+    if response.status_code != 200 {
+        return Err(Error::Generic(format!(
+            "{} returned status {}",
+            METRICS_PATH, response.status_code
+        )));
+    }
+    let text = String::from_utf8(response.body)
+        .map_err(|e| Error::Generic(format!("Metrics are not utf-8: {}", e)))?;
+    Metrics::parse(&text)
+}
+
+/// Parse a line like `name{label="value"} 1.5 1700000000000`
+fn parse_sample(line: &str) -> Result<Sample> {
+    let invalid = || Error::Generic(format!("Invalid metrics line: {}", line));
+
+    let (name, labels, rest) = match line.find('{') {
+        Some(open) => {
+            let close = line.rfind('}').ok_or_else(invalid)?;
+            let labels = parse_labels(&line[open + 1..close]).ok_or_else(invalid)?;
+            (&line[..open], labels, &line[close + 1..])
+        }
+        None => {
+            let end = line.find(char::is_whitespace).ok_or_else(invalid)?;
+            (&line[..end], BTreeMap::new(), &line[end..])
+        }
+    };
+    let value = rest.split_whitespace().next().ok_or_else(invalid)?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        value => value.parse().map_err(|_| invalid())?,
+    };
+
+    Ok(Sample {
+        name: name.trim().into(),
+        labels,
+        value,
+    })
+}
+
+/// Parse `a="1",b="x\"y"`
+fn parse_labels(text: &str) -> Option<BTreeMap<String, String>> {
+    let mut labels = BTreeMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim().to_string();
+        let quoted = rest[eq + 1..].trim_start().strip_prefix('"')?;
+        let mut value = String::new();
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in quoted.char_indices() {
+            match (escaped, c) {
+                (false, '\\') => escaped = true,
+                (false, '"') => {
+                    end = Some(i);
+                    break;
+                }
+                (true, 'n') => {
+                    value.push('\n');
+                    escaped = false;
+                }
+                (_, c) => {
+                    value.push(c);
+                    escaped = false;
+                }
+            }
+        }
+        labels.insert(key, value);
+        rest = quoted[end? + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Some(labels)
+}
+
+/// The upper bounds of the latency buckets of [`CallMetrics`], in seconds
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// The canister, method and kind of calls
+type MethodKey = (String, String, &'static str);
+
+#[derive(Default)]
+struct MethodMetrics {
+    calls: u64,
+    failures: BTreeMap<String, u64>,
+    buckets: Vec<u64>,
+    latency_sum: f64,
+}
+
+/// A backend counting the calls made through it, see the [module docs](self).
+///
+/// It exports `ic_test_calls_total`, `ic_test_call_failures_total` by `reject_code`,
+/// which is `error` for failures other than rejects, and the histogram
+/// `ic_test_call_duration_seconds`, all labeled with `canister`, `method` and `kind`.
+pub struct CallMetrics<'a> {
+    inner: &'a dyn Backend,
+    methods: Mutex<BTreeMap<MethodKey, MethodMetrics>>,
+}
+
+impl<'a> CallMetrics<'a> {
+    /// Count the calls passed on to `inner`
+    pub fn new(inner: &'a dyn Backend) -> Self {
+        Self {
+            inner,
+            methods: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn export(&self) -> String {
+        let methods = self.lock();
+        let mut text = String::new();
+        let labels = |(canister, method, kind): &MethodKey| {
+            format!(
+                "canister=\"{}\",method=\"{}\",kind=\"{}\"",
+                escape(canister),
+                escape(method),
+                kind
+            )
+        };
+
+        text.push_str("# HELP ic_test_calls_total Calls made by the test run\n");
+        text.push_str("# TYPE ic_test_calls_total counter\n");
+        for (key, metrics) in methods.iter() {
+            let _ = writeln!(
+                text,
+                "ic_test_calls_total{{{}}} {}",
+                labels(key),
+                metrics.calls
+            );
+        }
+
+        text.push_str("# HELP ic_test_call_failures_total Failed calls by reject code\n");
+        text.push_str("# TYPE ic_test_call_failures_total counter\n");
+        for (key, metrics) in methods.iter() {
+            for (code, failures) in &metrics.failures {
+                let _ = writeln!(
+                    text,
+                    "ic_test_call_failures_total{{{},reject_code=\"{}\"}} {}",
+                    labels(key),
+                    code,
+                    failures
+                );
+            }
+        }
+
+        text.push_str("# HELP ic_test_call_duration_seconds Latency of the calls\n");
+        text.push_str("# TYPE ic_test_call_duration_seconds histogram\n");
+        for (key, metrics) in methods.iter() {
+            let labels = labels(key);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&metrics.buckets) {
+                let _ = writeln!(
+                    text,
+                    "ic_test_call_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                text,
+                "ic_test_call_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, metrics.calls
+            );
+            let _ = writeln!(
+                text,
+                "ic_test_call_duration_seconds_sum{{{}}} {}",
+                labels, metrics.latency_sum
+            );
+            let _ = writeln!(
+                text,
+                "ic_test_call_duration_seconds_count{{{}}} {}",
+                labels, metrics.calls
+            );
+        }
+        text
+    }
+
+    /// The metrics parsed back, for assertions
+    pub fn metrics(&self) -> Result<Metrics> {
+        Metrics::parse(&self.export())
+    }
+
+    /// Write the metrics in the Prometheus text format to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.export())?;
+        Ok(())
+    }
+
+    async fn call(
+        &self,
+        kind: &'static str,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &str,
+        arg: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let result = if kind == "update" {
+            self.inner
+                .update_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        } else {
+            self.inner
+                .query_call(canister_id, effective_canister_id, method_name, arg)
+                .await
+        };
+        let latency = started.elapsed().as_secs_f64();
+
+        let mut methods = self.lock();
+        let metrics = methods
+            .entry((canister_id.to_text(), method_name.into(), kind))
+            .or_default();
+        metrics.calls += 1;
+        if metrics.buckets.is_empty() {
+            metrics.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(metrics.buckets.iter_mut()) {
+            if latency <= *bound {
+                *count += 1;
+            }
+        }
+        metrics.latency_sum += latency;
+        if let Err(ref err) = result {
+            let code = err
+                .reject_code()
+                .map_or_else(|| "error".into(), |code| format!("{:?}", code));
+            *metrics.failures.entry(code).or_default() += 1;
+        }
+        result
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<MethodKey, MethodMetrics>> {
+        self.methods.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Backend for CallMetrics<'_> {
+    fn update_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(
+            "update",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn query_call<'a>(
+        &'a self,
+        canister_id: Principal,
+        effective_canister_id: Option<Principal>,
+        method_name: &'a str,
+        arg: Vec<u8>,
+    ) -> BackendFuture<'a, Vec<u8>> {
+        Box::pin(self.call(
+            "query",
+            canister_id,
+            effective_canister_id,
+            method_name,
+            arg,
+        ))
+    }
+
+    fn agent(&self) -> Option<&Agent> {
+        self.inner.agent()
+    }
+
+    fn get_time(&self) -> BackendFuture<'_, SystemTime> {
+        self.inner.get_time()
+    }
+
+    fn set_time(&self, time: SystemTime) -> BackendFuture<'_, ()> {
+        self.inner.set_time(time)
+    }
+
+    fn advance_time(&self, duration: Duration) -> BackendFuture<'_, ()> {
+        self.inner.advance_time(duration)
+    }
+
+    fn tick(&self) -> BackendFuture<'_, ()> {
+        self.inner.tick()
+    }
+}
@@ -0,0 +1,226 @@
+//! Tag the canisters of a test, to share a testnet between test runs.
+//!
+//! A [`Namespace`] is named after the test and the run, like `transfer-ci-1234`. Every
+//! canister created through it gets a controller derived from that tag, and its id is
+//! recorded in a file of the tag. [`Namespace::sweep`] deletes all recorded canisters
+//! still carrying the tag controller, so a run cleans up after tests that crashed
+//! without touching the canisters of runs going on at the same time.
+//!
+//! ```
+//! # async fn run(wallet: ic_test_utils::WalletCanister<'_>, registry: &mut ic_test_utils::registry::CanisterRegistry) {
+//! use ic_test_utils::namespace::Namespace;
+//! use ic_test_utils::Canister;
+//!
+//! let namespace = Namespace::new("transfer");
+//! let ledger = namespace.create_canister(&wallet, 1_000_000_000_000).await.unwrap();
+//! registry.register(namespace.label("ledger"), ledger);
+//!
+//! // Once the test is done, or in a later run with the same `IC_TEST_RUN_ID`
+//! let management = Canister::new_management(wallet.backend());
+//! let deleted = namespace.sweep(&management).await.unwrap();
+//! # }
+//! ```
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use candid::Principal;
+use sha2::{Digest, Sha224};
+
+use crate::canister::ManagementCanister;
+#[cfg(feature = "wallet")]
+use crate::canister::WalletCanister;
+use crate::{Error, ErrorKind, Result};
+
+/// Env var with the id of the run, shared by all the tests of a CI job
+pub const RUN_ID_ENV_VAR: &str = "IC_TEST_RUN_ID";
+
+/// The canisters of a test run
+#[derive(Debug, Clone)]
+pub struct Namespace {
+    tag: String,
+    record_dir: PathBuf,
+}
+
+impl Namespace {
+    /// The namespace of `test_name` in this run. The run id is taken from
+    /// `IC_TEST_RUN_ID`, or made up from the process id and the time.
+    pub fn new(test_name: &str) -> Self {
+        let run_id = std::env::var(RUN_ID_ENV_VAR).unwrap_or_else(|_| {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            format!("{}-{}", std::process::id(), secs)
+        });
+        Self::with_run_id(test_name, &run_id)
+    }
+
+    /// The namespace of `test_name` in the run `run_id`
+    pub fn with_run_id(test_name: &str, run_id: &str) -> Self {
+        Self::from_tag(format!("{}-{}", test_name, run_id))
+    }
+
+    /// The namespace with the tag `tag`, like one printed by a previous run.
+    /// Characters other than letters, digits, `-` and `_` are replaced with `-`.
+    pub fn from_tag(tag: impl AsRef<str>) -> Self {
+        let tag = tag
+            .as_ref()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+                _ => '-',
+            })
+            .collect();
+        Self {
+            tag,
+            record_dir: std::env::temp_dir().join("ic-test-utils-namespaces"),
+        }
+    }
+
+    /// Record the canisters in `dir` instead of the temp dir, e.g. a cache of the CI
+    /// job, so a later job can sweep them
+    pub fn with_record_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.record_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// The tag
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The controller added to the canisters of the namespace.
+    /// It is a self-authenticating id nobody has the key of, derived from the tag.
+    pub fn controller(&self) -> Principal {
+        let mut bytes = Sha224::digest(self.tag.as_bytes()).to_vec();
+        bytes.push(0x02);
+        Principal::from_slice(&bytes)
+    }
+
+    /// `name` in the namespace, to register a canister as in a
+    /// [`CanisterRegistry`](crate::registry::CanisterRegistry) shared by several tests
+    pub fn label(&self, name: &str) -> String {
+        format!("{}/{}", self.tag, name)
+    }
+
+    /// Create an empty canister with `cycles` through `wallet`, controlled by the
+    /// wallet, the sender and the namespace, and record it.
+    #[cfg(feature = "wallet")]
+    pub async fn create_canister(
+        &self,
+        wallet: &WalletCanister<'_>,
+        cycles: u64,
+    ) -> Result<Principal> {
+        let mut controllers = vec![*wallet.principal(), self.controller()];
+        if let Some(agent) = wallet.backend().agent() {
+            controllers.push(agent.get_principal().map_err(Error::Generic)?);
+        }
+        let canister_id = wallet.create_canister(cycles, controllers).await?;
+        self.record(canister_id)?;
+        Ok(canister_id)
+    }
+
+    /// Add the namespace controller to a canister created some other way, and record it.
+    /// The caller has to be a controller of the canister.
+    pub async fn tag_canister(
+        &self,
+        management: &ManagementCanister<'_>,
+        canister_id: Principal,
+    ) -> Result<()> {
+        management
+            .add_controller(canister_id, self.controller())
+            .await?;
+        self.record(canister_id)
+    }
+
+    /// The canisters recorded for the namespace, on this machine or in the record dir
+    pub fn recorded(&self) -> Result<Vec<Principal>> {
+        let text = match fs::read_to_string(self.record_file()) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut recorded = Vec::new();
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            let canister_id = Principal::from_text(line)?;
+            if !recorded.contains(&canister_id) {
+                recorded.push(canister_id);
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Stop and delete the recorded canisters that still carry the namespace controller,
+    /// and return them. Canisters that are gone, or that the caller doesn't control
+    /// anymore, are skipped and forgotten. The caller has to be a controller of the
+    /// canisters.
+    ///
+    /// If a canister can't be checked or deleted, like when the replica is down, it is
+    /// kept in the record for the next sweep, and the first such error is returned once
+    /// the other canisters are swept.
+    pub async fn sweep(&self, management: &ManagementCanister<'_>) -> Result<Vec<Principal>> {
+        let controller = self.controller();
+        let mut deleted = Vec::new();
+        let mut kept = Vec::new();
+        let mut first_error = None;
+        for canister_id in self.recorded()? {
+            let result = match management.canister_status(canister_id).await {
+                Ok(status) if status.settings.controllers.contains(&controller) => {
+                    match management.stop_canister(canister_id).await {
+                        Ok(()) => management.delete_canister(canister_id).await,
+                        Err(err) => Err(err),
+                    }
+                    .map(|()| deleted.push(canister_id))
+                }
+                Ok(_) => Ok(()),
+                Err(err) if is_gone_or_not_controlled(&err) => Ok(()),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                kept.push(canister_id);
+                first_error.get_or_insert(err);
+            }
+        }
+
+        if kept.is_empty() {
+            match fs::remove_file(self.record_file()) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        } else {
+            let text: String = kept.iter().map(|id| format!("{}\n", id)).collect();
+            fs::write(self.record_file(), text)?;
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(deleted),
+        }
+    }
+
+    fn record(&self, canister_id: Principal) -> Result<()> {
+        fs::create_dir_all(&self.record_dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.record_file())?;
+        writeln!(file, "{}", canister_id)?;
+        Ok(())
+    }
+
+    fn record_file(&self) -> PathBuf {
+        self.record_dir.join(format!("{}.txt", self.tag))
+    }
+}
+
+/// Whether `err` rejects a call because the canister doesn't exist, or because the
+/// caller isn't one of its controllers
+fn is_gone_or_not_controlled(err: &Error) -> bool {
+    if err.kind() == ErrorKind::CanisterNotFound {
+        return true;
+    }
+    err.reject().is_some_and(|(_, message)| {
+        let message = message.to_lowercase();
+        message.contains("only the controllers") || message.contains("not a controller")
+    })
+}
@@ -0,0 +1,106 @@
+//! A pool of canisters shared by tests running in parallel.
+//!
+//! Creating a canister is slow, so the pool creates a number of empty canisters
+//! up front. A test acquires one with the wasm it needs, and the canister goes back
+//! to the pool when the [`PooledCanister`] is dropped.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, wasm: Vec<u8>) {
+//! use ic_test_utils::pool::CanisterPool;
+//! use ic_test_utils::Canister;
+//!
+//! let wallet = Canister::new_wallet(agent, "alice", None).unwrap();
+//! let pool = CanisterPool::new(wallet, 8, 1_000_000_000_000).await.unwrap();
+//!
+//! let canister = pool.acquire(wasm, ()).await.unwrap();
+//! let principal = canister.principal();
+//! # }
+//! ```
+use std::sync::Mutex;
+
+use candid::utils::ArgumentEncoder;
+use candid::Principal;
+use futures::future::try_join_all;
+
+use crate::canister::{Canister, ManagementCanister, WalletCanister};
+use crate::Result;
+
+/// A pool of canisters created by a wallet.
+pub struct CanisterPool<'agent> {
+    wallet: WalletCanister<'agent>,
+    management: ManagementCanister<'agent>,
+    cycles: u64,
+    available: Mutex<Vec<Principal>>,
+}
+
+impl<'agent> CanisterPool<'agent> {
+    /// Create a pool of `size` empty canisters, each with `cycles`.
+    pub async fn new(wallet: WalletCanister<'agent>, size: usize, cycles: u64) -> Result<Self> {
+        let created = try_join_all((0..size).map(|_| wallet.create_canister(cycles, None))).await?;
+        let management = Canister::new_management(wallet.backend());
+
+        Ok(Self {
+            wallet,
+            management,
+            cycles,
+            available: Mutex::new(created),
+        })
+    }
+
+    /// The number of canisters currently in the pool
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Take a canister out of the pool and install `wasm` on it, replacing any
+    /// code and state left by a previous test.
+    /// If the pool is empty a new canister is created.
+    pub async fn acquire<T: ArgumentEncoder>(
+        &self,
+        wasm: impl AsRef<[u8]>,
+        arg: T,
+    ) -> Result<PooledCanister<'_, 'agent>> {
+        let next = self.lock().pop();
+        let canister_id = match next {
+            Some(canister_id) => canister_id,
+            None => self.wallet.create_canister(self.cycles, None).await?,
+        };
+        // Return the canister to the pool even if the install fails
+        let pooled = PooledCanister {
+            pool: self,
+            canister_id,
+        };
+        self.management
+            .reinstall_code(canister_id, wasm, arg)
+            .await?;
+        Ok(pooled)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Principal>> {
+        self.available.lock().expect("canister pool lock poisoned")
+    }
+}
+
+/// A canister taken from a [`CanisterPool`], returned to it on drop.
+pub struct PooledCanister<'pool, 'agent> {
+    pool: &'pool CanisterPool<'agent>,
+    canister_id: Principal,
+}
+
+impl<'agent> PooledCanister<'_, 'agent> {
+    /// The id of the canister
+    pub fn principal(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// A handle to call the canister
+    pub fn canister<T>(&self) -> Canister<'agent, T> {
+        Canister::new(self.canister_id, self.pool.wallet.backend())
+    }
+}
+
+impl Drop for PooledCanister<'_, '_> {
+    fn drop(&mut self) {
+        self.pool.lock().push(self.canister_id);
+    }
+}
@@ -0,0 +1,26 @@
+//! The types and functions most tests use, in one import.
+//!
+//! ```
+//! use ic_test_utils::prelude::*;
+//!
+//! # async fn run(counter: Principal) -> Result<()> {
+//! let agent = get_agent("alice", None).await?;
+//! let counter = Canister::<()>::new(counter, &agent);
+//! let reply = counter.call_query("get", Encode!()?).await?;
+//! let count = Decode!(&reply, u64)?;
+//! # Ok(())
+//! # }
+//! ```
+pub use candid::{CandidType, Decode, Deserialize, Encode, Nat, Principal};
+
+pub use crate::backend::Backend;
+pub use crate::canister::{Canister, Management, ManagementCanister};
+#[cfg(feature = "wallet")]
+pub use crate::canister::{CanisterBuilder, Wallet, WalletCanister};
+pub use crate::context::TestContext;
+#[cfg(feature = "wallet")]
+pub use crate::create_canister;
+pub use crate::{
+    generate_identity, get_agent, get_agent_with_identity, get_identity, ic_test, Agent, Error,
+    ErrorKind, RejectCode, Result,
+};
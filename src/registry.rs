@@ -0,0 +1,101 @@
+//! Refer to canisters by name instead of passing principals around.
+//!
+//! A [`CanisterRegistry`] resolves a name like `"ledger"` to a principal, from
+//! canisters registered by the test, then the `CANISTER_ID_<NAME>` env vars dfx
+//! sets, then the `canister_ids.json` of a dfx project.
+//!
+//! ```
+//! # fn run(agent: &ic_agent::Agent, backend: candid::Principal) {
+//! use ic_test_utils::registry::CanisterRegistry;
+//! use ic_test_utils::Ledger;
+//!
+//! let mut registry = CanisterRegistry::new().with_dfx_project(".", "local").unwrap();
+//! registry.register("backend", backend);
+//! let ledger = registry.canister::<Ledger>(agent, "ledger").unwrap();
+//! # }
+//! ```
+use std::collections::BTreeMap;
+#[cfg(feature = "dfx-integration")]
+use std::path::Path;
+
+use candid::Principal;
+
+use crate::backend::Backend;
+#[cfg(feature = "dfx-integration")]
+use crate::dfx::CanisterIds;
+use crate::{Canister, Error, Result};
+
+/// Prefix of the env vars holding canister ids, as set by dfx
+pub const CANISTER_ID_ENV_PREFIX: &str = "CANISTER_ID_";
+
+/// Canister ids by name, for the current environment.
+#[derive(Debug, Clone, Default)]
+pub struct CanisterRegistry {
+    registered: BTreeMap<String, Principal>,
+    #[cfg(feature = "dfx-integration")]
+    files: Vec<CanisterIds>,
+}
+
+impl CanisterRegistry {
+    /// An empty registry, only resolving names from env vars
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also resolve names from the `canister_ids.json` of `network` in the dfx
+    /// project at `project_dir`
+    #[cfg(feature = "dfx-integration")]
+    pub fn with_dfx_project(
+        mut self,
+        project_dir: impl AsRef<Path>,
+        network: &str,
+    ) -> Result<Self> {
+        self.files.push(CanisterIds::load(project_dir, network)?);
+        Ok(self)
+    }
+
+    /// Register the canister `name`, this overrides env vars and files
+    pub fn register(&mut self, name: impl Into<String>, canister_id: Principal) {
+        self.registered.insert(name.into(), canister_id);
+    }
+
+    /// The canisters registered by the test, without those of env vars and files
+    pub fn registered(&self) -> impl Iterator<Item = (&str, Principal)> + '_ {
+        self.registered
+            .iter()
+            .map(|(name, canister_id)| (name.as_str(), *canister_id))
+    }
+
+    /// The id of the canister `name`
+    pub fn resolve(&self, name: &str) -> Result<Principal> {
+        if let Some(canister_id) = self.registered.get(name) {
+            return Ok(*canister_id);
+        }
+        if let Ok(id) = std::env::var(env_var(name)) {
+            return Ok(Principal::from_text(id)?);
+        }
+        #[cfg(feature = "dfx-integration")]
+        if let Some(canister_id) = self.files.iter().find_map(|ids| ids.get(name).ok()) {
+            return Ok(canister_id);
+        }
+        Err(Error::UnknownCanister(name.into()))
+    }
+
+    /// A handle to the canister `name`
+    pub fn canister<'agent, T>(
+        &self,
+        backend: &'agent dyn Backend,
+        name: &str,
+    ) -> Result<Canister<'agent, T>> {
+        Ok(Canister::new(self.resolve(name)?, backend))
+    }
+}
+
+/// The env var dfx uses for the id of the canister `name`: `CANISTER_ID_<NAME>`
+fn env_var(name: &str) -> String {
+    format!(
+        "{}{}",
+        CANISTER_ID_ENV_PREFIX,
+        name.to_uppercase().replace('-', "_")
+    )
+}
@@ -0,0 +1,299 @@
+//! Start and stop a local replica from a test.
+//!
+//! The replica is started in the background and killed when the
+//! [`LocalReplica`] is dropped.
+//!
+//! ```
+//! # async fn run() {
+//! use ic_test_utils::{get_agent, replica::LocalReplica};
+//!
+//! let replica = LocalReplica::start_dfx(".", None).await.unwrap();
+//! let agent = get_agent("bob", Some(&replica.url())).await.unwrap();
+//! # }
+//! ```
+//!
+//! [`wait_for_replica`] waits for a replica started elsewhere, like by CI, before the
+//! first call.
+//!
+//! For the same replica version on every machine, run a pinned image in docker:
+//!
+//! ```
+//! # async fn run() {
+//! use ic_test_utils::replica::{DockerConfig, LocalReplica};
+//!
+//! let config = DockerConfig::new("my-registry/ic-replica", "0.11.1");
+//! let replica = LocalReplica::start_docker(config).await.unwrap();
+//! # }
+//! ```
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use garcon::Waiter;
+use ic_agent::agent::status::Status;
+use ic_agent::Agent;
+
+use crate::transport::Transport;
+use crate::wait::wait_for;
+use crate::{Error, Result};
+
+/// How long to wait for a replica to become healthy.
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The kind of local replica.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReplicaKind {
+    /// A replica started with `dfx start`
+    Dfx,
+    /// A PocketIC server
+    PocketIc,
+    /// A replica running in a docker container
+    Docker,
+}
+
+/// Configuration of a replica running in docker.
+#[derive(Debug, Clone)]
+pub struct DockerConfig {
+    /// The replica image
+    pub image: String,
+    /// The tag of the image, pin this for reproducible replica versions
+    pub tag: String,
+    /// The port the replica listens on inside the container
+    pub container_port: u16,
+    /// The port on the host to map the `container_port` to.
+    /// If this is `None` a free port is picked.
+    pub host_port: Option<u16>,
+    /// A host directory mounted into the container, and where to mount it,
+    /// to keep the replica state
+    pub volume: Option<(PathBuf, String)>,
+}
+
+impl DockerConfig {
+    /// Configuration for `image:tag` with the replica listening on port 8000
+    /// and no volume.
+    pub fn new(image: impl Into<String>, tag: impl Into<String>) -> Self {
+        Self {
+            image: image.into(),
+            tag: tag.into(),
+            container_port: 8000,
+            host_port: None,
+            volume: None,
+        }
+    }
+}
+
+/// How to shut a replica down, besides killing the process.
+enum Shutdown {
+    Kill,
+    DfxStop(PathBuf),
+    DockerStop(String),
+}
+
+/// A local replica running in the background.
+pub struct LocalReplica {
+    child: Child,
+    kind: ReplicaKind,
+    port: u16,
+    shutdown: Shutdown,
+}
+
+impl LocalReplica {
+    /// Run `dfx start --clean` in the `project_dir` and wait until the replica
+    /// is healthy. If the `port` is `None` a free port is picked.
+    pub async fn start_dfx(project_dir: impl AsRef<Path>, port: Option<u16>) -> Result<Self> {
+        let port = port.map_or_else(free_port, Ok)?;
+        let child = Command::new("dfx")
+            .args(["start", "--clean", "--host", &format!("127.0.0.1:{}", port)])
+            .current_dir(project_dir.as_ref())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let mut replica = Self {
+            child,
+            kind: ReplicaKind::Dfx,
+            port,
+            shutdown: Shutdown::DfxStop(project_dir.as_ref().to_path_buf()),
+        };
+        replica.wait_until_healthy().await?;
+        Ok(replica)
+    }
+
+    /// Start the PocketIC server `binary` and wait until it is ready.
+    /// If the `port` is `None` a free port is picked.
+    pub async fn start_pocket_ic(binary: impl AsRef<Path>, port: Option<u16>) -> Result<Self> {
+        let port = port.map_or_else(free_port, Ok)?;
+        let child = Command::new(binary.as_ref())
+            .args(["--port", &port.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let mut replica = Self {
+            child,
+            kind: ReplicaKind::PocketIc,
+            port,
+            shutdown: Shutdown::Kill,
+        };
+        replica.wait_until_healthy().await?;
+        Ok(replica)
+    }
+
+    /// Run the replica image from `config` in docker and wait until the replica is healthy.
+    /// The container is stopped and removed on drop.
+    pub async fn start_docker(config: DockerConfig) -> Result<Self> {
+        let port = config.host_port.map_or_else(free_port, Ok)?;
+        let name = format!("ic-test-utils-{}-{}", std::process::id(), port);
+
+        let mut command = Command::new("docker");
+        command.args([
+            "run",
+            "--rm",
+            "--name",
+            &name,
+            "-p",
+            &format!("{}:{}", port, config.container_port),
+        ]);
+        if let Some((ref host_dir, ref container_dir)) = config.volume {
+            command.args(["-v", &format!("{}:{}", host_dir.display(), container_dir)]);
+        }
+        let child = command
+            .arg(format!("{}:{}", config.image, config.tag))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let mut replica = Self {
+            child,
+            kind: ReplicaKind::Docker,
+            port,
+            shutdown: Shutdown::DockerStop(name),
+        };
+        replica.wait_until_healthy().await?;
+        Ok(replica)
+    }
+
+    /// The kind of replica
+    pub fn kind(&self) -> ReplicaKind {
+        self.kind
+    }
+
+    /// The port the replica listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The url of the replica, to be passed to [`crate::get_agent`]
+    pub fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    async fn wait_until_healthy(&mut self) -> Result<()> {
+        let mut waiter = garcon::Delay::builder()
+            .throttle(Duration::from_millis(500))
+            .timeout(START_TIMEOUT)
+            .build();
+        waiter.start();
+
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Err(Error::ReplicaExited(status));
+            }
+            if self.is_healthy().await {
+                return Ok(());
+            }
+            waiter
+                .async_wait()
+                .await
+                .map_err(|_| Error::ReplicaNotReady(self.url()))?;
+        }
+    }
+
+    async fn is_healthy(&self) -> bool {
+        match self.kind {
+            ReplicaKind::Dfx | ReplicaKind::Docker => {
+                let agent = match Transport::default()
+                    .create(&self.url())
+                    .and_then(|transport| Ok(Agent::builder().with_transport(transport).build()?))
+                {
+                    Ok(agent) => agent,
+                    Err(_) => return false,
+                };
+                agent.status().await.is_ok_and(|status| is_healthy(&status))
+            }
+            ReplicaKind::PocketIc => reqwest::get(format!("{}/status", self.url()))
+                .await
+                .is_ok_and(|response| response.status().is_success()),
+        }
+    }
+}
+
+impl Drop for LocalReplica {
+    fn drop(&mut self) {
+        let stop = match self.shutdown {
+            Shutdown::Kill => None,
+            Shutdown::DfxStop(ref project_dir) => {
+                let mut command = Command::new("dfx");
+                command.arg("stop").current_dir(project_dir);
+                Some(command)
+            }
+            Shutdown::DockerStop(ref name) => {
+                let mut command = Command::new("docker");
+                command.args(["stop", name]);
+                Some(command)
+            }
+        };
+        if let Some(mut stop) = stop {
+            let _ = stop.stdout(Stdio::null()).stderr(Stdio::null()).status();
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Poll `/api/v2/status` of the replica at `url` until it reports healthy, and return
+/// the status, with the replica version in `impl_version` and the root key.
+///
+/// Fails with [`Error::ReplicaNotReady`] if the replica isn't healthy within `timeout`.
+///
+/// ```
+/// # async fn run() {
+/// use std::time::Duration;
+///
+/// use ic_test_utils::replica::wait_for_replica;
+///
+/// let status = wait_for_replica("http://localhost:8000", Duration::from_secs(30))
+///     .await
+///     .unwrap();
+/// println!("replica {:?}", status.impl_version);
+/// # }
+/// ```
+pub async fn wait_for_replica(url: &str, timeout: Duration) -> Result<Status> {
+    let agent = Agent::builder()
+        .with_transport(Transport::default().create(url)?)
+        .build()?;
+    let check = || async {
+        match agent.status().await {
+            Ok(status) if is_healthy(&status) => Ok(status),
+            Ok(status) => Err(format!("{:?}", status.replica_health_status)),
+            Err(err) => Err(err.to_string()),
+        }
+    };
+    wait_for(check, timeout, Duration::from_millis(100))
+        .await
+        .map_err(|_| Error::ReplicaNotReady(url.into()))
+}
+
+/// Whether the status reports a healthy replica, older replicas don't report it
+fn is_healthy(status: &Status) -> bool {
+    status
+        .replica_health_status
+        .as_deref()
+        .is_none_or(|health| health == "healthy")
+}
+
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
@@ -0,0 +1,124 @@
+//! Reproducible randomness for test data.
+//!
+//! A [`TestRng`] generates identities, subaccounts, amounts and payloads from a seed.
+//! [`TestRng::from_env`] takes the seed from `IC_TEST_SEED`, or picks one and prints it,
+//! so a failing randomized test is reproduced exactly by running it again with the seed.
+//!
+//! ```
+//! # async fn run() {
+//! use ic_test_utils::canister::AccountIdentifier;
+//! use ic_test_utils::rng::TestRng;
+//! use ic_test_utils::get_agent_with_identity;
+//!
+//! let mut rng = TestRng::from_env().unwrap();
+//! let alice = get_agent_with_identity(rng.identity().unwrap(), None).await.unwrap();
+//! let account = AccountIdentifier::new(&alice.get_principal().unwrap(), Some(rng.subaccount()));
+//! let amount = rng.amount(1..=1_000_000);
+//! let memo = rng.bytes(32);
+//! # }
+//! ```
+use std::ops::RangeInclusive;
+
+use ic_agent::identity::BasicIdentity;
+use ring::rand::SecureRandom;
+use ring::signature::Ed25519KeyPair;
+
+use crate::chaos::next_u64;
+use crate::{Error, Result};
+
+/// Env var with the seed of [`TestRng::from_env`]
+pub const SEED_ENV_VAR: &str = "IC_TEST_SEED";
+
+/// Random test data from a seed
+#[derive(Debug, Clone)]
+pub struct TestRng {
+    seed: u64,
+    state: u64,
+}
+
+impl TestRng {
+    /// Generate from `seed`
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// Generate from the seed in `IC_TEST_SEED`, or from a new random seed.
+    /// The seed is printed, so it shows up with the output of a failing test.
+    pub fn from_env() -> Result<Self> {
+        let seed = match std::env::var(SEED_ENV_VAR) {
+            Ok(seed) => seed
+                .parse()
+                .map_err(|e| Error::Generic(format!("Invalid {}: {}", SEED_ENV_VAR, e)))?,
+            Err(_) => {
+                let mut bytes = [0; 8];
+                ring::rand::SystemRandom::new()
+                    .fill(&mut bytes)
+                    .map_err(|_| Error::Generic("Failed to pick a seed".into()))?;
+                u64::from_le_bytes(bytes)
+            }
+        };
+        eprintln!(
+            "test data seed {}, rerun with {}={} to reproduce",
+            seed, SEED_ENV_VAR, seed
+        );
+        Ok(Self::new(seed))
+    }
+
+    /// The seed
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A random number
+    pub fn next_u64(&mut self) -> u64 {
+        next_u64(&mut self.state)
+    }
+
+    /// Another generator, seeded from this one, e.g. for each user of a test
+    pub fn fork(&mut self) -> TestRng {
+        TestRng::new(self.next_u64())
+    }
+
+    /// A random number in `range`, like a token amount
+    pub fn amount(&mut self, range: RangeInclusive<u64>) -> u64 {
+        let (start, end) = range.into_inner();
+        if start >= end {
+            return start;
+        }
+        match (end - start).checked_add(1) {
+            Some(span) => start + self.next_u64() % span,
+            None => self.next_u64(),
+        }
+    }
+
+    /// Fill `bytes` with random bytes
+    pub fn fill(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&random[..chunk.len()]);
+        }
+    }
+
+    /// `len` random bytes, like a payload
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.fill(&mut bytes);
+        bytes
+    }
+
+    /// A random subaccount
+    pub fn subaccount(&mut self) -> [u8; 32] {
+        let mut subaccount = [0; 32];
+        self.fill(&mut subaccount);
+        subaccount
+    }
+
+    /// A random Ed25519 identity, like [`crate::generate_identity`] but reproducible
+    pub fn identity(&mut self) -> Result<BasicIdentity> {
+        let mut seed = [0; 32];
+        self.fill(&mut seed);
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|e| Error::Generic(format!("Failed to derive a key: {}", e)))?;
+        Ok(BasicIdentity::from_key_pair(key_pair))
+    }
+}
@@ -0,0 +1,163 @@
+//! Named steps of a long end to end flow.
+//!
+//! A [`Scenario`] runs its steps in order with a shared context, prints each step with
+//! how long it took, and stops at the first failing step with an [`Error::Step`]
+//! naming it.
+//!
+//! With a [`deadline`](Scenario::deadline), all waiters and polls of the steps give up
+//! at the deadline at the latest, and the step it is exceeded at fails with an
+//! [`Error::DeadlineExceeded`], instead of each waiter timing out on its own.
+//!
+//! ```
+//! # async fn run(ledger: ic_test_utils::Canister<'_, ()>, alice: candid::Principal, bob: candid::Principal) {
+//! use std::time::Duration;
+//!
+//! use candid::Encode;
+//! use ic_test_utils::scenario::Scenario;
+//! use ic_test_utils::Canister;
+//!
+//! struct Flow<'a> {
+//!     ledger: Canister<'a, ()>,
+//! }
+//!
+//! let flow = Flow { ledger };
+//! Scenario::<Flow>::new()
+//!     .deadline(Duration::from_secs(120))
+//!     .step("alice approves", |flow| async move {
+//!         flow.ledger.call_update("approve", Encode!(&bob, &100u64)?).await?;
+//!         Ok(())
+//!     })
+//!     .step("bob transfers from alice", |flow| async move {
+//!         flow.ledger
+//!             .call_update("transfer_from", Encode!(&alice, &bob, &100u64)?)
+//!             .await?;
+//!         Ok(())
+//!     })
+//!     .run(&flow)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use crate::deadline::{self, with_deadline};
+use crate::{Error, Result};
+
+type StepFn<'c, C> = Box<dyn FnOnce(&'c C) -> Pin<Box<dyn Future<Output = Result<()>> + 'c>> + 'c>;
+
+/// Steps run in order with a context of type `C`
+pub struct Scenario<'c, C> {
+    steps: Vec<(String, StepFn<'c, C>)>,
+    deadline: Option<Duration>,
+}
+
+impl<'c, C> Default for Scenario<'c, C> {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            deadline: None,
+        }
+    }
+}
+
+impl<'c, C> Scenario<'c, C> {
+    /// A scenario without steps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the step `name`, running `step` with the context
+    pub fn step<F, Fut>(mut self, name: impl Into<String>, step: F) -> Self
+    where
+        F: FnOnce(&'c C) -> Fut + 'c,
+        Fut: Future<Output = Result<()>> + 'c,
+    {
+        self.steps.push((
+            name.into(),
+            Box::new(move |context| Box::pin(step(context))),
+        ));
+        self
+    }
+
+    /// Give all steps together `timeout` to run, from the start of [`Scenario::run`]
+    pub fn deadline(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(timeout);
+        self
+    }
+
+    /// Run the steps in order with `context`, printing each step with how long it took.
+    /// Stops at the first step failing, with an [`Error::Step`] naming it.
+    /// A step running past the deadline fails with an [`Error::DeadlineExceeded`].
+    pub async fn run(self, context: &'c C) -> Result<ScenarioReport> {
+        let count = self.steps.len();
+        let deadline = self
+            .deadline
+            .map(|timeout| (timeout, Instant::now() + timeout));
+        let mut report = ScenarioReport { steps: Vec::new() };
+        for (i, (name, step)) in self.steps.into_iter().enumerate() {
+            let started = Instant::now();
+            let result = match deadline {
+                Some((timeout, at)) => {
+                    let remaining = at.saturating_duration_since(started);
+                    let step = with_deadline(remaining, async {
+                        let result = step(context).await;
+                        (result, deadline::exceeded())
+                    });
+                    match tokio::time::timeout_at(at.into(), step).await {
+                        Ok((Ok(()), _)) => Ok(()),
+                        Ok((Err(err), true)) => Err(Error::DeadlineExceeded {
+                            timeout,
+                            source: Some(Box::new(err)),
+                        }),
+                        Ok((Err(err), false)) => Err(err),
+                        Err(_) => Err(Error::DeadlineExceeded {
+                            timeout,
+                            source: None,
+                        }),
+                    }
+                }
+                None => step(context).await,
+            };
+            let elapsed = started.elapsed();
+            match result {
+                Ok(()) => {
+                    eprintln!("step {}/{} {:?} ok in {:?}", i + 1, count, name, elapsed);
+                    report.steps.push((name, elapsed));
+                }
+                Err(err) => {
+                    eprintln!(
+                        "step {}/{} {:?} failed in {:?}: {}",
+                        i + 1,
+                        count,
+                        name,
+                        elapsed,
+                        err.report()
+                    );
+                    return Err(Error::Step {
+                        step: name,
+                        index: i + 1,
+                        elapsed,
+                        source: Box::new(err),
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The steps of a [`Scenario`] that ran
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    /// The name of each step, with how long it took
+    pub steps: Vec<(String, Duration)>,
+}
+
+impl ScenarioReport {
+    /// How long all steps took
+    pub fn elapsed(&self) -> Duration {
+        self.steps.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+}
@@ -0,0 +1,188 @@
+//! Share expensive fixtures between tests by restoring snapshots.
+//!
+//! A [`StateGuard`] snapshots a set of canisters, and restores them to that state
+//! when the test is done, so the next test starts from the same fixture.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, ledger: candid::Principal) {
+//! use ic_test_utils::snapshot::StateGuard;
+//! use ic_test_utils::Canister;
+//!
+//! let management = Canister::new_management(agent);
+//! StateGuard::scope(&management, &[ledger], async {
+//!     // Transfer tokens etc.
+//! })
+//! .await
+//! .unwrap();
+//! # }
+//! ```
+//!
+//! [`with_rollback`] snapshots a canister before a destructive operation, like an
+//! upgrade, and restores it if the operation or a health check afterwards fails.
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+
+use candid::Principal;
+use futures::FutureExt;
+
+use crate::canister::ManagementCanister;
+use crate::{Error, Result};
+
+/// Snapshots of a set of canisters to restore later.
+pub struct StateGuard<'a, 'agent> {
+    management: &'a ManagementCanister<'agent>,
+    snapshots: Vec<(Principal, Vec<u8>)>,
+}
+
+impl<'a, 'agent> StateGuard<'a, 'agent> {
+    /// Take a snapshot of each of the `canisters`.
+    /// The canisters are stopped while the snapshot is taken.
+    /// If this fails for a canister, the snapshots taken so far are deleted again.
+    pub async fn take(
+        management: &'a ManagementCanister<'agent>,
+        canisters: &[Principal],
+    ) -> Result<StateGuard<'a, 'agent>> {
+        let mut guard = Self {
+            management,
+            snapshots: Vec::with_capacity(canisters.len()),
+        };
+        for canister_id in canisters.iter().copied() {
+            match guard.snapshot(canister_id).await {
+                Ok(snapshot_id) => guard.snapshots.push((canister_id, snapshot_id)),
+                Err(err) => {
+                    let _ = guard.discard().await;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(guard)
+    }
+
+    async fn snapshot(&self, canister_id: Principal) -> Result<Vec<u8>> {
+        self.management.stop_canister(canister_id).await?;
+        let snapshot = self
+            .management
+            .take_canister_snapshot(canister_id, None)
+            .await;
+        match (snapshot, self.management.start_canister(canister_id).await) {
+            (Ok(snapshot), Err(err)) => {
+                let _ = self
+                    .management
+                    .delete_canister_snapshot(canister_id, snapshot.id)
+                    .await;
+                Err(err)
+            }
+            (snapshot, started) => {
+                started?;
+                Ok(snapshot?.id)
+            }
+        }
+    }
+
+    /// Restore each canister to its snapshot and delete the snapshots.
+    pub async fn restore(self) -> Result<()> {
+        for (canister_id, snapshot_id) in self.snapshots {
+            self.management.stop_canister(canister_id).await?;
+            let loaded = self
+                .management
+                .load_canister_snapshot(canister_id, snapshot_id.clone())
+                .await;
+            self.management.start_canister(canister_id).await?;
+            loaded?;
+            self.management
+                .delete_canister_snapshot(canister_id, snapshot_id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the snapshots without restoring the canisters.
+    pub async fn discard(self) -> Result<()> {
+        for (canister_id, snapshot_id) in self.snapshots {
+            self.management
+                .delete_canister_snapshot(canister_id, snapshot_id)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot the `canisters`, run `test` and restore the canisters afterwards,
+    /// also when `test` panics.
+    /// If `test` panics the panic is raised again, after printing the error of the
+    /// restore if it failed too.
+    pub async fn scope<F: Future>(
+        management: &'a ManagementCanister<'agent>,
+        canisters: &[Principal],
+        test: F,
+    ) -> Result<F::Output> {
+        let guard = Self::take(management, canisters).await?;
+        let output = AssertUnwindSafe(test).catch_unwind().await;
+        match output {
+            Ok(output) => {
+                guard.restore().await?;
+                Ok(output)
+            }
+            Err(panic) => {
+                if let Err(err) = guard.restore().await {
+                    eprintln!("Restoring the snapshots failed: {}", err.report());
+                }
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+}
+
+/// Snapshot `canister_id`, then run `operation` and `health_check`.
+/// If either fails the canister is restored to the snapshot, and the error is returned
+/// as [`Error::RolledBack`], or as [`Error::RollbackFailed`] with both errors if the
+/// restore failed too. The snapshot is deleted afterwards.
+///
+/// ```
+/// # async fn run(agent: &ic_agent::Agent, counter: candid::Principal, wasm: Vec<u8>) {
+/// use candid::Encode;
+/// use ic_test_utils::snapshot::with_rollback;
+/// use ic_test_utils::Canister;
+///
+/// let management = Canister::new_management(agent);
+/// let canister = Canister::<()>::new(counter, agent);
+/// with_rollback(
+///     &management,
+///     counter,
+///     || management.upgrade_code(counter, wasm, ()),
+///     || async {
+///         canister.call_query("get", Encode!()?).await?;
+///         Ok(())
+///     },
+/// )
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+pub async fn with_rollback<F, Fut, H, HFut>(
+    management: &ManagementCanister<'_>,
+    canister_id: Principal,
+    operation: F,
+    health_check: H,
+) -> Result<()>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<()>>,
+    H: FnOnce() -> HFut,
+    HFut: Future<Output = Result<()>>,
+{
+    let guard = StateGuard::take(management, &[canister_id]).await?;
+    let result = match operation().await {
+        Ok(()) => health_check().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => guard.discard().await,
+        Err(e) => match guard.restore().await {
+            Ok(()) => Err(Error::RolledBack(Box::new(e))),
+            Err(restore) => Err(Error::RollbackFailed {
+                source: Box::new(e),
+                restore: Box::new(restore),
+            }),
+        },
+    }
+}
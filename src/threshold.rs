@@ -0,0 +1,158 @@
+//! Verify threshold signatures made with `sign_with_ecdsa` and `sign_with_schnorr`.
+//!
+//! The public keys are derived by the management canister for a canister and a
+//! derivation path, and read through a wallet, as only canisters can ask for them.
+//! Verifying a signature against them shows the canister signed what it was meant to,
+//! with the key it was meant to.
+//! Schnorr signatures can be verified for ed25519 keys, not for BIP-340 keys yet.
+//!
+//! ```
+//! # async fn run(wallet: ic_test_utils::WalletCanister<'_>, signer: candid::Principal, message_hash: [u8; 32], signature: Vec<u8>) {
+//! use ic_test_utils::threshold::{ecdsa_public_key, verify_ecdsa, TEST_KEY_NAME};
+//!
+//! let derivation_path = vec![b"user".to_vec()];
+//! let key = ecdsa_public_key(&wallet, signer, derivation_path, TEST_KEY_NAME)
+//!     .await
+//!     .unwrap();
+//! verify_ecdsa(&key.public_key, &message_hash, &signature).unwrap();
+//! # }
+//! ```
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ecdsa::hazmat::VerifyPrimitive;
+use k256::ecdsa::Signature;
+use k256::elliptic_curve::bigint::U256;
+use k256::elliptic_curve::ops::Reduce;
+use k256::{FieldBytes, PublicKey, Scalar};
+
+use crate::canister::WalletCanister;
+use crate::{Error, Result};
+
+/// The name of the test key of a local replica
+pub const TEST_KEY_NAME: &str = "dfx_test_key";
+
+/// The cycles attached to the public key calls
+pub const PUBLIC_KEY_CYCLES: u64 = 10_000_000_000;
+
+/// A public key derived by the management canister
+#[derive(Debug, Clone, CandidType, Deserialize)]
+pub struct DerivedPublicKey {
+    /// The key, a compressed SEC1 point for secp256k1, or 32 bytes for ed25519
+    #[serde(with = "serde_bytes")]
+    pub public_key: Vec<u8>,
+    /// The chain code, to derive further keys
+    #[serde(with = "serde_bytes")]
+    pub chain_code: Vec<u8>,
+}
+
+/// The algorithm of a Schnorr key
+#[derive(Debug, Clone, Copy, CandidType, Deserialize, PartialEq, Eq)]
+pub enum SchnorrAlgorithm {
+    /// BIP-340 Schnorr signatures over secp256k1
+    #[serde(rename = "bip340secp256k1")]
+    Bip340Secp256k1,
+    /// Ed25519 signatures
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+#[derive(CandidType, Deserialize)]
+enum EcdsaCurve {
+    #[serde(rename = "secp256k1")]
+    Secp256k1,
+}
+
+#[derive(CandidType)]
+struct EcdsaKeyId<'a> {
+    curve: EcdsaCurve,
+    name: &'a str,
+}
+
+#[derive(CandidType)]
+struct SchnorrKeyId<'a> {
+    algorithm: SchnorrAlgorithm,
+    name: &'a str,
+}
+
+#[derive(CandidType)]
+struct PublicKeyArgs<K> {
+    canister_id: Option<Principal>,
+    derivation_path: Vec<serde_bytes::ByteBuf>,
+    key_id: K,
+}
+
+/// The secp256k1 key of `canister_id` for `derivation_path`, as used by `sign_with_ecdsa`
+pub async fn ecdsa_public_key(
+    wallet: &WalletCanister<'_>,
+    canister_id: Principal,
+    derivation_path: Vec<Vec<u8>>,
+    key_name: &str,
+) -> Result<DerivedPublicKey> {
+    let args = PublicKeyArgs {
+        canister_id: Some(canister_id),
+        derivation_path: derivation_path
+            .into_iter()
+            .map(serde_bytes::ByteBuf::from)
+            .collect(),
+        key_id: EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: key_name,
+        },
+    };
+    let data = wallet
+        .call_management("ecdsa_public_key", Encode!(&args)?, PUBLIC_KEY_CYCLES)
+        .await?;
+    Ok(Decode!(&data, DerivedPublicKey)?)
+}
+
+/// The `algorithm` key of `canister_id` for `derivation_path`, as used by `sign_with_schnorr`
+pub async fn schnorr_public_key(
+    wallet: &WalletCanister<'_>,
+    canister_id: Principal,
+    derivation_path: Vec<Vec<u8>>,
+    algorithm: SchnorrAlgorithm,
+    key_name: &str,
+) -> Result<DerivedPublicKey> {
+    let args = PublicKeyArgs {
+        canister_id: Some(canister_id),
+        derivation_path: derivation_path
+            .into_iter()
+            .map(serde_bytes::ByteBuf::from)
+            .collect(),
+        key_id: SchnorrKeyId {
+            algorithm,
+            name: key_name,
+        },
+    };
+    let data = wallet
+        .call_management("schnorr_public_key", Encode!(&args)?, PUBLIC_KEY_CYCLES)
+        .await?;
+    Ok(Decode!(&data, DerivedPublicKey)?)
+}
+
+/// Verify the 64 byte `r || s` `signature` returned by `sign_with_ecdsa` for the
+/// 32 byte `message_hash`, with the SEC1 encoded secp256k1 `public_key`
+pub fn verify_ecdsa(public_key: &[u8], message_hash: &[u8], signature: &[u8]) -> Result<()> {
+    if message_hash.len() != 32 {
+        return Err(invalid("message hash", "expected 32 bytes"));
+    }
+    let key = PublicKey::from_sec1_bytes(public_key).map_err(|e| invalid("public key", e))?;
+    let signature = Signature::try_from(signature).map_err(|e| invalid("signature", e))?;
+    // Both s and -s are valid, the verification only accepts the low one
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let z = <Scalar as Reduce<U256>>::from_be_bytes_reduced(*FieldBytes::from_slice(message_hash));
+    key.as_affine()
+        .verify_prehashed(z, &signature)
+        .map_err(|_| Error::InvalidSignature("the signature doesn't match".into()))
+}
+
+/// Verify the 64 byte `signature` returned by `sign_with_schnorr` with an ed25519 key
+/// for `message`, with the 32 byte `public_key`
+pub fn verify_ed25519(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+        .verify(message, signature)
+        .map_err(|_| Error::InvalidSignature("the signature doesn't match".into()))
+}
+
+fn invalid(what: &str, err: impl std::fmt::Display) -> Error {
+    Error::InvalidSignature(format!("invalid {}: {}", what, err))
+}
@@ -0,0 +1,62 @@
+//! Compare the times a canister records with the certified time of the replica.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, canister: candid::Principal, recorded_nanos: u64) {
+//! use std::time::{Duration, UNIX_EPOCH};
+//!
+//! use ic_test_utils::time::{assert_time_within, certified_time};
+//!
+//! let now = certified_time(agent, canister).await.unwrap();
+//! let recorded = UNIX_EPOCH + Duration::from_nanos(recorded_nanos);
+//! assert_time_within(recorded, now, Duration::from_secs(5));
+//! # }
+//! ```
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use candid::Principal;
+use ic_agent::hash_tree::{Label, LookupResult};
+
+use crate::backend::Backend;
+use crate::wasm::read_leb128;
+use crate::{Error, Result};
+
+/// The time in the certified state tree of the subnet of `effective_canister_id`.
+///
+/// On a backend without an agent, like PocketIC, this is the time of the backend.
+pub async fn certified_time(
+    backend: &dyn Backend,
+    effective_canister_id: Principal,
+) -> Result<SystemTime> {
+    let agent = match backend.agent() {
+        Some(agent) => agent,
+        None => return backend.get_time().await,
+    };
+
+    let path: Vec<Label> = vec!["time".into()];
+    let cert = agent
+        .read_state_raw(vec![path.clone()], effective_canister_id, false)
+        .await?;
+    let mut encoded = match cert.tree.lookup_path(&path) {
+        LookupResult::Found(value) => value,
+        _ => return Err(Error::Generic("No time in the certificate".into())),
+    };
+    let nanos = read_leb128(&mut encoded)
+        .ok_or_else(|| Error::Generic("Invalid time in the certificate".into()))?;
+    Ok(UNIX_EPOCH + Duration::from_nanos(nanos))
+}
+
+/// Panic unless `time` is within `skew` of `reference`
+pub fn assert_time_within(time: SystemTime, reference: SystemTime, skew: Duration) {
+    let difference = match time.duration_since(reference) {
+        Ok(after) => after,
+        Err(before) => before.duration(),
+    };
+    assert!(
+        difference <= skew,
+        "{:?} is {:?} away from {:?}, more than {:?}",
+        time,
+        difference,
+        reference,
+        skew
+    );
+}
@@ -0,0 +1,123 @@
+//! Wait for the timers and heartbeats of a canister to run.
+//!
+//! On PocketIC time only moves when told to, so the time is advanced and rounds are
+//! executed until the timers ran. On a replica the state is polled until they ran.
+//! Heartbeats run once per round, see [`after_heartbeats`].
+//!
+//! ```
+//! # async fn run(counter: ic_test_utils::Canister<'_, ()>) {
+//! use std::time::Duration;
+//!
+//! use candid::{Decode, Encode};
+//! use ic_test_utils::timers::await_timer_execution;
+//!
+//! // The counter increments itself every minute
+//! await_timer_execution(
+//!     &counter,
+//!     || async {
+//!         let reply = counter.call_query("get", Encode!()?).await?;
+//!         Ok(Decode!(&reply, u64)? >= 3)
+//!     },
+//!     Duration::from_secs(5 * 60),
+//! )
+//! .await
+//! .unwrap();
+//! # }
+//! ```
+use std::future::Future;
+use std::time::Duration;
+
+use crate::backend::{await_rounds, ROUND_DURATION};
+use crate::wait::wait_for;
+use crate::{Canister, Error, Result};
+
+/// The steps the time is advanced in on PocketIC, until the timeout is reached
+pub const TIMER_STEPS: u32 = 100;
+
+/// The interval `predicate` is polled at on a replica
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Wait until `predicate` holds, as the timers of `canister` ran.
+///
+/// On PocketIC the time of the instance is advanced by up to `timeout`, in
+/// [`TIMER_STEPS`] steps of at least a round with a tick after each. On a replica
+/// `predicate` is polled for up to `timeout`.
+pub async fn await_timer_execution<T, F, Fut>(
+    canister: &Canister<'_, T>,
+    mut predicate: F,
+    timeout: Duration,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let backend = canister.backend();
+    if backend.agent().is_some() {
+        return wait_for(
+            || {
+                let check = predicate();
+                async move {
+                    match check.await {
+                        Ok(true) => Ok(Ok(())),
+                        Ok(false) => Err(()),
+                        Err(e) => Ok(Err(e)),
+                    }
+                }
+            },
+            timeout,
+            POLL_INTERVAL,
+        )
+        .await
+        .map_err(|_| timed_out(timeout))?;
+    }
+
+    let step = (timeout / TIMER_STEPS).max(ROUND_DURATION);
+    let mut advanced = Duration::ZERO;
+    loop {
+        if predicate().await? {
+            return Ok(());
+        }
+        if advanced >= timeout {
+            return Err(timed_out(timeout));
+        }
+        backend.advance_time(step).await?;
+        backend.tick().await?;
+        advanced += step;
+    }
+}
+
+/// Let `count` heartbeats of `canister` run, then run `assertions` and return what they return.
+///
+/// A heartbeat runs every round, so `count` rounds are ticked on PocketIC and
+/// waited for on a replica, where at least `count` heartbeats ran afterwards.
+///
+/// ```
+/// # async fn run(ticker: ic_test_utils::Canister<'_, ()>) {
+/// use candid::{Decode, Encode};
+/// use ic_test_utils::timers::after_heartbeats;
+///
+/// let beats = after_heartbeats(&ticker, 5, || async {
+///     let reply = ticker.call_query("beats", Encode!().unwrap()).await.unwrap();
+///     Decode!(&reply, u64).unwrap()
+/// })
+/// .await
+/// .unwrap();
+/// assert!(beats >= 5);
+/// # }
+/// ```
+pub async fn after_heartbeats<T, F, Fut, R>(
+    canister: &Canister<'_, T>,
+    count: u32,
+    assertions: F,
+) -> Result<R>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = R>,
+{
+    await_rounds(canister.backend(), count).await?;
+    Ok(assertions().await)
+}
+
+fn timed_out(timeout: Duration) -> Error {
+    Error::Generic(format!("The timers did not run within {:?}", timeout))
+}
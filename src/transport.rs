@@ -0,0 +1,233 @@
+//! The HTTP client agents talk to the replica with.
+//!
+//! The `reqwest-transport` feature, on by default, uses the reqwest transport of
+//! ic-agent. The `hyper-transport` feature adds a transport on a plain hyper client
+//! with rustls, for workspaces where the reqwest of ic-agent conflicts with another
+//! one. [`Transport::default`] picks the one set in `IC_TEST_TRANSPORT`, or the
+//! reqwest one if it is enabled.
+//!
+//! The transports don't keep connections open between requests. A pooled connection
+//! is served by a task of the runtime that opened it, so an agent shared by the
+//! `#[tokio::test]`s of a process, each with its own runtime, would fail once the
+//! runtime of the first test is gone, and block when used from another thread while
+//! that runtime is blocked, like in the cleanup of a panicking test.
+//!
+//! ```
+//! # async fn run() {
+//! use ic_test_utils::transport::Transport;
+//! use ic_test_utils::{generate_identity, get_agent_with_transport};
+//!
+//! let identity = generate_identity().unwrap();
+//! let agent = get_agent_with_transport(identity, None, Transport::Hyper)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::str::FromStr;
+
+use ic_agent::agent::ReplicaV2Transport;
+
+use crate::{Error, Result};
+
+/// Env var naming the transport of [`Transport::default`], `reqwest` or `hyper`.
+pub const TRANSPORT_ENV_VAR: &str = "IC_TEST_TRANSPORT";
+
+/// The HTTP client of an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// The reqwest transport of ic-agent, with the `reqwest-transport` feature
+    Reqwest,
+    /// A hyper client with rustls, with the `hyper-transport` feature
+    Hyper,
+}
+
+impl Default for Transport {
+    /// The transport named in `IC_TEST_TRANSPORT`, or the reqwest one if it is
+    /// enabled, or the hyper one
+    fn default() -> Self {
+        match std::env::var(TRANSPORT_ENV_VAR).map(|name| name.parse()) {
+            Ok(Ok(transport)) => transport,
+            _ if cfg!(feature = "reqwest-transport") => Self::Reqwest,
+            _ => Self::Hyper,
+        }
+    }
+}
+
+impl FromStr for Transport {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "reqwest" => Ok(Self::Reqwest),
+            "hyper" => Ok(Self::Hyper),
+            _ => Err(Error::Generic(format!(
+                "Unknown transport {}, expected reqwest or hyper",
+                name
+            ))),
+        }
+    }
+}
+
+impl Transport {
+    /// A transport to the replica at `url`.
+    /// Fails if the feature of the transport is not enabled.
+    #[cfg_attr(
+        not(any(feature = "reqwest-transport", feature = "hyper-transport")),
+        allow(unused_variables)
+    )]
+    pub fn create(self, url: &str) -> Result<Box<dyn ReplicaV2Transport>> {
+        match self {
+            #[cfg(feature = "reqwest-transport")]
+            Self::Reqwest => {
+                use ic_agent::agent::http_transport::{reqwest, ReqwestHttpReplicaV2Transport};
+                use ic_agent::AgentError;
+                let client = reqwest::Client::builder()
+                    .use_rustls_tls()
+                    .pool_max_idle_per_host(0)
+                    .build()
+                    .map_err(|e| AgentError::TransportError(Box::new(e)))?;
+                Ok(Box::new(ReqwestHttpReplicaV2Transport::create_with_client(
+                    url, client,
+                )?))
+            }
+            #[cfg(feature = "hyper-transport")]
+            Self::Hyper => Ok(Box::new(hyper_transport::HyperTransport::create(url)?)),
+            #[allow(unreachable_patterns)]
+            transport => Err(Error::Generic(format!(
+                "The {} transport needs the {}-transport feature",
+                transport.name(),
+                transport.name()
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Reqwest => "reqwest",
+            Self::Hyper => "hyper",
+        }
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+mod hyper_transport {
+    use std::future::Future;
+    use std::pin::Pin;
+
+    use hyper::client::HttpConnector;
+    use hyper::{header, Body, Client, Method, Request};
+    use hyper_rustls::HttpsConnector;
+    use ic_agent::agent::agent_error::HttpErrorPayload;
+    use ic_agent::agent::ReplicaV2Transport;
+    use ic_agent::ic_types::Principal;
+    use ic_agent::{AgentError, RequestId};
+
+    type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AgentError>> + Send + 'a>>;
+
+    /// A transport on a hyper client, making the same requests as the reqwest one
+    pub struct HyperTransport {
+        url: String,
+        client: Client<HttpsConnector<HttpConnector>>,
+    }
+
+    impl HyperTransport {
+        pub fn create(url: &str) -> Result<Self, AgentError> {
+            let base = format!("{}/api/v2/", url.trim_end_matches('/'));
+            if base.parse::<hyper::Uri>().is_err() {
+                return Err(AgentError::InvalidReplicaUrl(url.into()));
+            }
+            let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http()
+                .enable_http1()
+                .enable_http2()
+                .build();
+            Ok(Self {
+                url: base,
+                client: Client::builder().pool_max_idle_per_host(0).build(connector),
+            })
+        }
+
+        async fn execute(
+            &self,
+            method: Method,
+            endpoint: &str,
+            body: Option<Vec<u8>>,
+        ) -> Result<Vec<u8>, AgentError> {
+            let request = Request::builder()
+                .method(method)
+                .uri(format!("{}{}", self.url, endpoint))
+                .header(header::CONTENT_TYPE, "application/cbor")
+                .body(body.map_or_else(Body::empty, Body::from))
+                .map_err(|e| AgentError::TransportError(Box::new(e)))?;
+            let response = self
+                .client
+                .request(request)
+                .await
+                .map_err(|e| AgentError::TransportError(Box::new(e)))?;
+
+            let status = response.status();
+            let content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(Into::into);
+            let body = hyper::body::to_bytes(response.into_body())
+                .await
+                .map_err(|e| AgentError::TransportError(Box::new(e)))?
+                .to_vec();
+
+            if status.is_client_error() || status.is_server_error() {
+                Err(AgentError::HttpError(HttpErrorPayload {
+                    status: status.into(),
+                    content_type,
+                    content: body,
+                }))
+            } else {
+                Ok(body)
+            }
+        }
+    }
+
+    impl ReplicaV2Transport for HyperTransport {
+        fn call<'a>(
+            &'a self,
+            effective_canister_id: Principal,
+            envelope: Vec<u8>,
+            _request_id: RequestId,
+        ) -> TransportFuture<'a, ()> {
+            Box::pin(async move {
+                let endpoint = format!("canister/{}/call", effective_canister_id);
+                self.execute(Method::POST, &endpoint, Some(envelope))
+                    .await?;
+                Ok(())
+            })
+        }
+
+        fn read_state<'a>(
+            &'a self,
+            effective_canister_id: Principal,
+            envelope: Vec<u8>,
+        ) -> TransportFuture<'a, Vec<u8>> {
+            Box::pin(async move {
+                let endpoint = format!("canister/{}/read_state", effective_canister_id);
+                self.execute(Method::POST, &endpoint, Some(envelope)).await
+            })
+        }
+
+        fn query<'a>(
+            &'a self,
+            effective_canister_id: Principal,
+            envelope: Vec<u8>,
+        ) -> TransportFuture<'a, Vec<u8>> {
+            Box::pin(async move {
+                let endpoint = format!("canister/{}/query", effective_canister_id);
+                self.execute(Method::POST, &endpoint, Some(envelope)).await
+            })
+        }
+
+        fn status<'a>(&'a self) -> TransportFuture<'a, Vec<u8>> {
+            Box::pin(self.execute(Method::GET, "status", None))
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Check that the state of a canister survives an upgrade.
+//!
+//! [`test_upgrade`] installs the first version of a canister, populates its state,
+//! upgrades it to the second version and checks the state again.
+//! [`test_rollback`] does the reverse, to make sure an emergency rollback to the
+//! previous version is safe.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent, counter: candid::Principal, v1: Vec<u8>, v2: Vec<u8>) {
+//! use candid::{Decode, Encode};
+//! use ic_test_utils::upgrade::test_upgrade;
+//! use ic_test_utils::Canister;
+//!
+//! struct Counter;
+//!
+//! let canister = Canister::<Counter>::new(counter, agent);
+//! test_upgrade(
+//!     &canister,
+//!     &v1,
+//!     &v2,
+//!     |counter| async move {
+//!         counter.call_update("inc", Encode!().unwrap()).await.unwrap();
+//!     },
+//!     |counter| async move {
+//!         let reply = counter.call_query("get", Encode!().unwrap()).await.unwrap();
+//!         assert_eq!(Decode!(&reply, u64).unwrap(), 1);
+//!     },
+//! )
+//! .await
+//! .unwrap();
+//! # }
+//! ```
+use std::future::Future;
+
+use crate::{Canister, Result};
+
+/// Install `wasm_v1` on the canister, replacing its code and state, and run `populate`.
+/// Then run `check`, upgrade the canister to `wasm_v2` and run `check` again.
+///
+/// Both versions are installed without arguments.
+pub async fn test_upgrade<'agent, T, P, PF, C, CF>(
+    canister: &Canister<'agent, T>,
+    wasm_v1: impl AsRef<[u8]>,
+    wasm_v2: impl AsRef<[u8]>,
+    populate: P,
+    check: C,
+) -> Result<()>
+where
+    P: FnOnce(Canister<'agent, T>) -> PF,
+    PF: Future<Output = ()>,
+    C: Fn(Canister<'agent, T>) -> CF,
+    CF: Future<Output = ()>,
+{
+    let management = Canister::new_management(canister.backend());
+    let canister_id = *canister.principal();
+
+    management.reinstall_code(canister_id, wasm_v1, ()).await?;
+    populate(handle(canister)).await;
+    check(handle(canister)).await;
+
+    management.upgrade_code(canister_id, wasm_v2, ()).await?;
+    check(handle(canister)).await;
+    Ok(())
+}
+
+/// Install `wasm_old` on the canister, replacing its code and state, upgrade it to
+/// `wasm_new` and run `populate`. Then run `check`, downgrade the canister back to
+/// `wasm_old` and run `check` again.
+///
+/// The old version has to decode the state written by the new version,
+/// so `check` should only test what both versions know about.
+/// Both versions are installed without arguments.
+pub async fn test_rollback<'agent, T, P, PF, C, CF>(
+    canister: &Canister<'agent, T>,
+    wasm_old: impl AsRef<[u8]>,
+    wasm_new: impl AsRef<[u8]>,
+    populate: P,
+    check: C,
+) -> Result<()>
+where
+    P: FnOnce(Canister<'agent, T>) -> PF,
+    PF: Future<Output = ()>,
+    C: Fn(Canister<'agent, T>) -> CF,
+    CF: Future<Output = ()>,
+{
+    let management = Canister::new_management(canister.backend());
+    let canister_id = *canister.principal();
+
+    management
+        .reinstall_code(canister_id, wasm_old.as_ref(), ())
+        .await?;
+    management.upgrade_code(canister_id, wasm_new, ()).await?;
+    populate(handle(canister)).await;
+    check(handle(canister)).await;
+
+    management
+        .upgrade_code(canister_id, wasm_old.as_ref(), ())
+        .await?;
+    check(handle(canister)).await;
+    Ok(())
+}
+
+/// A new handle to the same canister, to pass to the closures
+fn handle<'agent, T>(canister: &Canister<'agent, T>) -> Canister<'agent, T> {
+    Canister::new(*canister.principal(), canister.backend())
+}
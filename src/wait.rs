@@ -0,0 +1,161 @@
+//! Poll until a canister reaches some state.
+//!
+//! ```
+//! # async fn run(counter: ic_test_utils::Canister<'_, ()>) {
+//! use std::time::Duration;
+//!
+//! use candid::{Decode, Encode};
+//! use ic_test_utils::wait::wait_for;
+//!
+//! let count = wait_for(
+//!     || async {
+//!         let reply = counter.call_query("get", Encode!().unwrap()).await.unwrap();
+//!         let count = Decode!(&reply, u64).unwrap();
+//!         // Done once the count reached 10, otherwise report what was seen
+//!         if count >= 10 { Ok(count) } else { Err(count) }
+//!     },
+//!     Duration::from_secs(30),
+//!     Duration::from_millis(100),
+//! )
+//! .await
+//! .unwrap();
+//! # }
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use garcon::{Waiter, WaiterError};
+
+use crate::deadline;
+
+/// The interval between checks grows up to this many times the initial interval
+pub const MAX_BACKOFF: u32 = 8;
+
+/// [`wait_for`] gave up
+#[derive(Debug, Clone)]
+pub struct WaitTimeout<V> {
+    /// How long it waited
+    pub waited: Duration,
+    /// The value seen by the last check
+    pub last: V,
+}
+
+impl<V: fmt::Debug> fmt::Display for WaitTimeout<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Timed out after {:?}, last observed: {:?}",
+            self.waited, self.last
+        )
+    }
+}
+
+impl<V: fmt::Debug> std::error::Error for WaitTimeout<V> {}
+
+/// Run `check` until it returns `Ok`, or give up after `timeout`.
+///
+/// `check` returns `Err` with what it observed while the state is not reached yet.
+/// Within a [`deadline`](crate::deadline) it checks a last time at the deadline and gives up.
+/// The first retry waits `interval`, and every retry waits twice as long as the
+/// previous one, up to [`MAX_BACKOFF`] times `interval`.
+pub async fn wait_for<F, Fut, T, V>(
+    mut check: F,
+    timeout: Duration,
+    interval: Duration,
+) -> Result<T, WaitTimeout<V>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, V>>,
+{
+    let start = Instant::now();
+    let mut delay = interval;
+    loop {
+        let last = match check().await {
+            Ok(value) => return Ok(value),
+            Err(last) => last,
+        };
+        let waited = start.elapsed();
+        if waited + delay > timeout {
+            return Err(WaitTimeout { waited, last });
+        }
+        match deadline::remaining() {
+            Some(remaining) if remaining.is_zero() => return Err(WaitTimeout { waited, last }),
+            Some(remaining) => tokio::time::sleep(delay.min(remaining)).await,
+            None => tokio::time::sleep(delay).await,
+        }
+        delay = (delay * 2).min(interval * MAX_BACKOFF);
+    }
+}
+
+/// A waiter for the agent that polls quickly at first and backs off, so an update
+/// on a fast local replica returns as soon as it's done instead of after a fixed
+/// throttle. Each wait is twice as long as the previous one, up to `max_interval`.
+/// The agent stops polling as soon as the request status is terminal.
+///
+/// Unlike the garcon waiters, the async wait doesn't block or spawn a thread.
+/// Within a [`deadline`](crate::deadline) it gives up at the deadline at the latest.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max_interval: Duration,
+    timeout: Duration,
+    started: Option<Instant>,
+    next: Duration,
+}
+
+impl Backoff {
+    /// Wait `initial` first, up to `max_interval` between polls, and give up after `timeout`
+    pub fn new(initial: Duration, max_interval: Duration, timeout: Duration) -> Self {
+        Self {
+            initial,
+            max_interval,
+            timeout,
+            started: None,
+            next: initial,
+        }
+    }
+
+    fn next_delay(&mut self) -> Result<Duration, WaiterError> {
+        let started = self.started.ok_or(WaiterError::NotStarted)?;
+        if started.elapsed() + self.next > self.timeout {
+            return Err(WaiterError::Timeout);
+        }
+        let delay = match deadline::remaining() {
+            Some(remaining) if remaining.is_zero() => return Err(WaiterError::Timeout),
+            Some(remaining) => self.next.min(remaining),
+            None => self.next,
+        };
+        self.next = (self.next * 2).min(self.max_interval);
+        Ok(delay)
+    }
+}
+
+impl Waiter for Backoff {
+    fn restart(&mut self) -> Result<(), WaiterError> {
+        if self.started.is_none() {
+            return Err(WaiterError::NotStarted);
+        }
+        self.start();
+        Ok(())
+    }
+
+    fn start(&mut self) {
+        self.started = Some(Instant::now());
+        self.next = self.initial;
+    }
+
+    fn wait(&mut self) -> Result<(), WaiterError> {
+        std::thread::sleep(self.next_delay()?);
+        Ok(())
+    }
+
+    fn async_wait(&mut self) -> Pin<Box<dyn Future<Output = Result<(), WaiterError>> + Send>> {
+        let delay = self.next_delay();
+        Box::pin(async move {
+            tokio::time::sleep(delay?).await;
+            Ok(())
+        })
+    }
+}
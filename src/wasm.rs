@@ -0,0 +1,418 @@
+//! Get the wasm modules to install.
+//!
+//! ```
+//! # async fn run(agent: &ic_agent::Agent) {
+//! use ic_test_utils::{create_canister, wasm::build_wasm};
+//!
+//! let wasm = build_wasm("my_canister").unwrap();
+//! let canister = create_canister(agent, "alice", wasm, (), 1_000_000_000_000).await.unwrap();
+//! # }
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// The target canisters are built for.
+pub const WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// Wasm modules built by this process, by package name.
+fn built() -> &'static Mutex<HashMap<String, Vec<u8>>> {
+    static BUILT: OnceLock<Mutex<HashMap<String, Vec<u8>>>> = OnceLock::new();
+    BUILT.get_or_init(Default::default)
+}
+
+/// Build the workspace member `package` for [`WASM_TARGET`] in release mode
+/// and return the wasm module.
+///
+/// The package is only built once per process, later calls return the same module.
+pub fn build_wasm(package: &str) -> Result<Vec<u8>> {
+    let mut built = built().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(wasm) = built.get(package) {
+        return Ok(wasm.clone());
+    }
+
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let output = Command::new(&cargo)
+        .args(["build", "--target", WASM_TARGET, "--release", "-p", package])
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::WasmBuild(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    let mut path = target_dir(&cargo)?;
+    path.push(WASM_TARGET);
+    path.push("release");
+    path.push(format!("{}.wasm", package.replace('-', "_")));
+    let wasm = std::fs::read(&path)?;
+
+    built.insert(package.to_string(), wasm.clone());
+    Ok(wasm)
+}
+
+/// The target directory of the current workspace
+fn target_dir(cargo: &str) -> Result<PathBuf> {
+    let output = Command::new(cargo)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::WasmBuild(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    let metadata = serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .ok_or_else(|| Error::WasmBuild("Missing target directory in cargo metadata".into()))?;
+    Ok(PathBuf::from(target_dir))
+}
+
+/// The first bytes of a gzip compressed module
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Raw modules larger than this are compressed before they're installed,
+/// to stay under the ingress message limit of 2MiB.
+pub const MAX_RAW_WASM_SIZE: usize = 2 * 1024 * 1024 - 64 * 1024;
+
+/// Whether `wasm` is gzip compressed
+pub fn is_gzip(wasm: &[u8]) -> bool {
+    wasm.starts_with(&GZIP_MAGIC)
+}
+
+/// Gzip compress a raw module. Already compressed modules are returned as is.
+pub fn gzip(wasm: Vec<u8>) -> Result<Vec<u8>> {
+    if is_gzip(&wasm) {
+        return Ok(wasm);
+    }
+    compress(&wasm)
+}
+
+fn compress(wasm: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(wasm)?;
+    Ok(encoder.finish()?)
+}
+
+/// Decompress a gzip compressed module. Raw modules are returned as is.
+pub fn gunzip(wasm: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_gzip(&wasm) {
+        return Ok(wasm);
+    }
+    let mut raw = Vec::new();
+    GzDecoder::new(wasm.as_slice()).read_to_end(&mut raw)?;
+    Ok(raw)
+}
+
+/// Read a `.wasm` or `.wasm.gz` module from disk.
+/// The module is installed as it is read, the replica accepts both.
+pub fn load_wasm(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    Ok(std::fs::read(path)?)
+}
+
+/// The module as it's sent to the replica:
+/// raw modules over [`MAX_RAW_WASM_SIZE`] are compressed, other modules are borrowed as is.
+pub(crate) fn prepare_upload(wasm: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if wasm.len() > MAX_RAW_WASM_SIZE && !is_gzip(wasm) {
+        Ok(Cow::Owned(compress(wasm)?))
+    } else {
+        Ok(Cow::Borrowed(wasm))
+    }
+}
+
+/// Env var holding the path to the `ic-wasm` binary, `ic-wasm` on the path is used otherwise.
+pub const IC_WASM_ENV_VAR: &str = "IC_WASM";
+
+/// Who can read a metadata section of a canister
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataVisibility {
+    /// Anyone
+    Public,
+    /// Only the controllers
+    Private,
+}
+
+/// Post processing of a built module with `ic-wasm`, the way a release pipeline
+/// would before the module is shipped.
+///
+/// ```
+/// # fn run() {
+/// use ic_test_utils::wasm::{build_wasm, PostProcess};
+///
+/// let wasm = build_wasm("my_canister").unwrap();
+/// let wasm = PostProcess::new()
+///     .shrink()
+///     .candid_service(std::fs::read_to_string("my_canister.did").unwrap())
+///     .run(wasm)
+///     .unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PostProcess {
+    shrink: bool,
+    metadata: Vec<(String, MetadataVisibility, String)>,
+}
+
+impl PostProcess {
+    /// No post processing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove unused functions and debug info
+    pub fn shrink(mut self) -> Self {
+        self.shrink = true;
+        self
+    }
+
+    /// Add a `icp:<visibility> <name>` custom section
+    pub fn metadata(
+        mut self,
+        name: impl Into<String>,
+        visibility: MetadataVisibility,
+        content: impl Into<String>,
+    ) -> Self {
+        self.metadata
+            .push((name.into(), visibility, content.into()));
+        self
+    }
+
+    /// Add the public `candid:service` metadata
+    pub fn candid_service(self, did: impl Into<String>) -> Self {
+        self.metadata("candid:service", MetadataVisibility::Public, did)
+    }
+
+    /// Run `ic-wasm` on the module.
+    /// Compressed modules are decompressed first, and the result is a raw module.
+    pub fn run(&self, wasm: Vec<u8>) -> Result<Vec<u8>> {
+        let mut wasm = gunzip(wasm)?;
+        if self.shrink {
+            wasm = ic_wasm(&wasm, &["shrink"])?;
+        }
+        for (name, visibility, content) in &self.metadata {
+            let content_file = TempFile::new("did")?;
+            std::fs::write(&content_file.0, content)?;
+            let visibility = match visibility {
+                MetadataVisibility::Public => "public",
+                MetadataVisibility::Private => "private",
+            };
+            let content_path = content_file.0.to_string_lossy();
+            wasm = ic_wasm(
+                &wasm,
+                &["metadata", name, "-f", &content_path, "-v", visibility],
+            )?;
+        }
+        Ok(wasm)
+    }
+}
+
+/// Run `ic-wasm <input> -o <output> <args>` and return the output module
+fn ic_wasm(wasm: &[u8], args: &[&str]) -> Result<Vec<u8>> {
+    let input = TempFile::new("wasm")?;
+    let output = TempFile::new("wasm")?;
+    std::fs::write(&input.0, wasm)?;
+
+    let ic_wasm = std::env::var(IC_WASM_ENV_VAR).unwrap_or_else(|_| "ic-wasm".into());
+    let result = Command::new(ic_wasm)
+        .arg(&input.0)
+        .arg("-o")
+        .arg(&output.0)
+        .args(args)
+        .output()?;
+    if !result.status.success() {
+        return Err(Error::WasmBuild(
+            String::from_utf8_lossy(&result.stderr).into_owned(),
+        ));
+    }
+    Ok(std::fs::read(&output.0)?)
+}
+
+/// A file in the temp dir, removed on drop
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(extension: &str) -> Result<Self> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = std::env::temp_dir().join("ic-test-utils-wasm");
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self(dir.join(format!(
+            "tmp-{}-{}.{}",
+            std::process::id(),
+            n,
+            extension
+        ))))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The content of the custom section `name` of a raw or compressed module.
+/// Canister metadata is stored in sections named `icp:public <name>` or `icp:private <name>`.
+pub fn custom_section(wasm: &[u8], name: &str) -> Result<Option<Vec<u8>>> {
+    let raw;
+    let mut wasm = if is_gzip(wasm) {
+        raw = gunzip(wasm.to_vec())?;
+        &raw[..]
+    } else {
+        wasm
+    };
+    if !wasm.starts_with(b"\0asm") || wasm.len() < 8 {
+        return Err(Error::WasmBuild("Not a wasm module".into()));
+    }
+    wasm = &wasm[8..];
+
+    while !wasm.is_empty() {
+        let id = wasm[0];
+        wasm = &wasm[1..];
+        let size = leb128(&mut wasm)? as usize;
+        if size > wasm.len() {
+            return Err(Error::WasmBuild("Truncated wasm section".into()));
+        }
+        let (mut section, rest) = wasm.split_at(size);
+        wasm = rest;
+        if id != 0 {
+            continue;
+        }
+        let name_len = leb128(&mut section)? as usize;
+        if name_len > section.len() {
+            return Err(Error::WasmBuild("Truncated wasm section name".into()));
+        }
+        if &section[..name_len] == name.as_bytes() {
+            return Ok(Some(section[name_len..].to_vec()));
+        }
+    }
+    Ok(None)
+}
+
+/// The candid interface embedded in the `candid:service` metadata of a module
+pub fn embedded_candid(wasm: &[u8]) -> Result<Option<String>> {
+    for name in ["icp:public candid:service", "icp:private candid:service"] {
+        if let Some(did) = custom_section(wasm, name)? {
+            return String::from_utf8(did)
+                .map(Some)
+                .map_err(|e| Error::WasmBuild(e.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+fn leb128(bytes: &mut &[u8]) -> Result<u64> {
+    read_leb128(bytes).ok_or_else(|| Error::WasmBuild("Invalid LEB128 integer".into()))
+}
+
+/// Read an unsigned LEB128 integer from the start of `bytes`
+pub(crate) fn read_leb128(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// The SHA-256 of `wasm` as the replica reports it once the module is installed by this
+/// crate, which hashes the uploaded module, so compressed if it is large and raw.
+///
+/// ```
+/// use ic_test_utils::wasm::wasm_hash;
+///
+/// let hash = wasm_hash(b"\0asm\x01\0\0\0").unwrap();
+/// assert_eq!(hash.len(), 32);
+/// ```
+pub fn wasm_hash(wasm: &[u8]) -> Result<[u8; 32]> {
+    Ok(sha256(&prepare_upload(wasm)?))
+}
+
+/// The SHA-256 of a wasm module, as reported by the replica.
+pub(crate) fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// A cache of wasm modules by their content hash, in memory and on disk.
+///
+/// The on disk cache survives the process, so modules can be looked up by
+/// hash across test runs. The modules are shared, so installing a cached module
+/// on many canisters doesn't copy it, see [`install_many`](crate::deploy::install_many).
+///
+/// ```
+/// use ic_test_utils::wasm::WasmCache;
+///
+/// let cache = WasmCache::new(None).unwrap();
+/// let hash = cache.insert(vec![0, 97, 115, 109]).unwrap();
+/// assert!(cache.get(&hash).unwrap().is_some());
+/// ```
+pub struct WasmCache {
+    dir: PathBuf,
+    modules: Mutex<HashMap<[u8; 32], Arc<[u8]>>>,
+}
+
+impl WasmCache {
+    /// Create a cache storing the modules in `dir`.
+    /// If the `dir` is `None` a directory in the temp dir is used.
+    pub fn new(dir: Option<PathBuf>) -> Result<Self> {
+        let dir = dir.unwrap_or_else(|| std::env::temp_dir().join("ic-test-utils-wasm"));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            modules: Default::default(),
+        })
+    }
+
+    /// Add a module to the cache and return its hash
+    pub fn insert(&self, wasm: Vec<u8>) -> Result<[u8; 32]> {
+        let hash = sha256(&wasm);
+        let path = self.path(&hash);
+        if !path.exists() {
+            std::fs::write(path, &wasm)?;
+        }
+        self.lock().insert(hash, wasm.into());
+        Ok(hash)
+    }
+
+    /// Get a module by its hash, from memory or else from disk
+    pub fn get(&self, hash: &[u8; 32]) -> Result<Option<Arc<[u8]>>> {
+        if let Some(wasm) = self.lock().get(hash) {
+            return Ok(Some(wasm.clone()));
+        }
+
+        let wasm = match std::fs::read(self.path(hash)) {
+            Ok(wasm) => wasm,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // Don't trust a file that was changed on disk
+        if sha256(&wasm) != *hash {
+            return Ok(None);
+        }
+        let wasm: Arc<[u8]> = wasm.into();
+        self.lock().insert(*hash, wasm.clone());
+        Ok(Some(wasm))
+    }
+
+    fn path(&self, hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(format!("{}.wasm", hex::encode(hash)))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<[u8; 32], Arc<[u8]>>> {
+        self.modules.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}